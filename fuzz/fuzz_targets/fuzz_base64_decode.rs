@@ -0,0 +1,16 @@
+//! Feeds arbitrary bytes to the base64 decoder, checking that it never panics
+//! and that whatever it accepts survives another encode and decode.
+//!
+//! Run with `cargo fuzz run fuzz_base64_decode` from the repository root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustc_serialize::base64::{FromBase64, ToBase64, STANDARD};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(decoded) = data.from_base64() {
+        let encoded = decoded.to_base64(STANDARD);
+        assert_eq!(encoded.from_base64().ok(), Some(decoded));
+    }
+});
@@ -0,0 +1,24 @@
+//! Feeds arbitrary bytes to the JSON parser, checking that it never panics
+//! and that whatever it accepts parses back to the same value once encoded,
+//! and encodes to the same text again.
+//!
+//! Run with `cargo fuzz run fuzz_json_parse` from the repository root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustc_serialize::json::Json;
+
+fuzz_target!(|data: &[u8]| {
+    let json = match Json::from_reader(&mut &data[..]) {
+        Ok(json) => json,
+        Err(_) => return,
+    };
+
+    let encoded = json.to_string();
+    let reparsed = Json::from_str(&encoded).expect("encoded JSON failed to parse");
+    assert_eq!(json, reparsed, "{} came back different", encoded);
+    // `Json`'s equality lets some differences through, such as a number
+    // changing representation, which the re-encoded text won't.
+    assert_eq!(encoded, reparsed.to_string(), "{} encoded differently", encoded);
+});
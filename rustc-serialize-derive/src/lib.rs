@@ -0,0 +1,972 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Custom derive for the `Encodable` and `Decodable` traits of
+//! `rustc-serialize`.
+//!
+//! The generated code follows the `derive(RustcEncodable, RustcDecodable)`
+//! mode which used to be built into the compiler, so switching over doesn't
+//! change how a type is encoded:
+//!
+//! * structs go through `emit_struct`, with the fields of tuple structs named
+//!   `_field0`, `_field1`, and so on,
+//! * enums go through `emit_enum`, the fields of struct variants being
+//!   encoded through `emit_enum_struct_variant_field`, which encoders
+//!   usually treat as positional arguments like those of tuple variants,
+//! * every type parameter of the type gets an `Encodable` (respectively
+//!   `Decodable`) bound.
+//!
+//! # Usage
+//!
+//! ```toml
+//! [dependencies]
+//! rustc-serialize = "0.4"
+//! rustc-serialize-derive = "0.1"
+//! ```
+//!
+//! ```rust,ignore
+//! extern crate rustc_serialize;
+//! #[macro_use] extern crate rustc_serialize_derive;
+//!
+//! #[derive(RustcEncodable, RustcDecodable)]
+//! struct Point {
+//!     x: i32,
+//!     y: i32,
+//! }
+//! ```
+//!
+//! # Attributes
+//!
+//! The name a field or variant is encoded under can be changed with
+//! `#[serialize(rename = "...")]`, and a naming convention can be applied to
+//! all fields of a struct (or all variants of an enum) with
+//! `#[serialize(rename_all = "...")]` on the type. The supported conventions
+//! are `lowercase`, `UPPERCASE`, `camelCase`, `PascalCase`, `snake_case`,
+//! `SCREAMING_SNAKE_CASE`, `kebab-case` and `SCREAMING-KEBAB-CASE`. Decoding
+//! only accepts the new names.
+//!
+//! ```rust,ignore
+//! #[derive(RustcEncodable, RustcDecodable)]
+//! #[serialize(rename_all = "camelCase")]
+//! struct Account {
+//!     user_id: u32,                  // "userId"
+//!     #[serialize(rename = "e-mail")]
+//!     email_address: Option<String>, // "e-mail"
+//! }
+//! ```
+//!
+//! A field marked `#[serialize(skip)]` is never encoded, and decodes to its
+//! `Default` value. With `#[serialize(skip_serializing_if = "path")]`, the
+//! field is left out whenever the function at `path`, taking a reference to
+//! the field's value, returns `true`. Such a field is still decoded as usual
+//! when it is present.
+//!
+//! ```rust,ignore
+//! #[derive(RustcEncodable, RustcDecodable)]
+//! struct Entry {
+//!     name: String,
+//!     #[serialize(skip_serializing_if = "Vec::is_empty")]
+//!     tags: Vec<String>,
+//!     #[serialize(skip)]
+//!     cached_len: usize,
+//! }
+//! ```
+//!
+//! A field marked `#[serialize(default)]` decodes to its `Default` value when
+//! it is missing or `null`, rather than failing. `#[serialize(default =
+//! "path")]` calls the function at `path`, taking no arguments, for the value
+//! instead, and also gives the value of a skipped field.
+//!
+//! The fields of a struct field marked `#[serialize(flatten)]` are encoded as
+//! if they were fields of the enclosing struct, through
+//! `Encoder::emit_struct_flattened`. When decoding, flattened fields take
+//! their keys first, in order, and the struct's own fields get what is left.
+//! The JSON decoder reports a key wanted by more than one of them as a
+//! `ConflictingFieldError`.
+//!
+//! ```rust,ignore
+//! #[derive(RustcEncodable, RustcDecodable)]
+//! struct Page {
+//!     title: String,
+//!     #[serialize(flatten)]
+//!     meta: Meta, // its fields sit next to "title"
+//! }
+//! ```
+//!
+//! A struct with a single field marked `#[serialize(transparent)]` is encoded
+//! as that field alone, rather than as a struct holding it, and decodes from
+//! it the same way. This suits newtypes:
+//!
+//! ```rust,ignore
+//! #[derive(RustcEncodable, RustcDecodable)]
+//! #[serialize(transparent)]
+//! struct Meters(f64); // 3.0 rather than {"_field0":3.0}
+//! ```
+//!
+//! Enums go through `emit_enum` unless they are given one of the following
+//! representations, shown for `Shape::Circle { r: 1 }` encoded to JSON:
+//!
+//! * `#[serialize(tag = "type")]`: a struct holding the variant's name in
+//!   `type` next to its fields, `{"type":"Circle","r":1}`. Tuple variants
+//!   must have exactly one field, holding a struct.
+//! * `#[serialize(tag = "t", content = "c")]`: a struct holding the variant's
+//!   name in `t` and its content in `c`, `{"t":"Circle","c":{"r":1}}`.
+//! * `#[serialize(untagged)]`: the content alone, `{"r":1}`. Decoding goes
+//!   through `Decoder::read_alternatives` and picks the first variant the
+//!   value decodes as, failing with the error of the last variant.
+//!
+//! The content of a unit variant is `null`, that of a tuple variant with a
+//! single field is the field's value, and other variants are encoded as
+//! tuples or structs.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as Tokens};
+use quote::quote;
+use syn::ext::IdentExt;
+use syn::{Attribute, Data, DeriveInput, ExprPath, Fields, Generics, Ident, Index,
+          Lit, LitStr, Meta, NestedMeta, Variant};
+
+/// Derives `rustc_serialize::Encodable`.
+#[proc_macro_derive(RustcEncodable, attributes(serialize))]
+pub fn derive_encodable(input: TokenStream) -> TokenStream {
+    expand(input, encodable)
+}
+
+/// Derives `rustc_serialize::Decodable`.
+#[proc_macro_derive(RustcDecodable, attributes(serialize))]
+pub fn derive_decodable(input: TokenStream) -> TokenStream {
+    expand(input, decodable)
+}
+
+fn expand(input: TokenStream,
+          f: fn(&DeriveInput) -> syn::Result<Tokens>) -> TokenStream {
+    let input = match syn::parse::<DeriveInput>(input) {
+        Ok(input) => input,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    match f(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn encodable(input: &DeriveInput) -> syn::Result<Tokens> {
+    let name = &input.ident;
+    let name_str = name.unraw().to_string();
+    let mut counted = false;
+    let transparent = container_options(input)?.transparent;
+    let body = match input.data {
+        Data::Struct(ref data) if transparent => {
+            let member = match data.fields.iter().next().unwrap().ident {
+                Some(ref ident) => quote!(#ident),
+                None => {
+                    let index = Index::from(0);
+                    quote!(#index)
+                }
+            };
+            quote!(::rustc_serialize::Encodable::encode(&self.#member, __s))
+        }
+        Data::Struct(ref data) => {
+            let options = field_options(input, &data.fields)?;
+            let fields = data.fields.iter().zip(options).enumerate()
+                                    .filter(|&(_, (_, ref options))| !options.skip)
+                                    .collect::<Vec<_>>();
+            let len = fields.len();
+            // with fields left out depending on their value, the index of a
+            // field is only known once the ones before it have been seen
+            counted = fields.iter().any(|&(_, (_, ref o))| o.skip_serializing_if.is_some());
+            let fields = fields.iter().enumerate().map(|(idx, &(i, (field, ref options)))| {
+                let member = match field.ident {
+                    Some(ref ident) => quote!(#ident),
+                    None => {
+                        let index = Index::from(i);
+                        quote!(#index)
+                    }
+                };
+                let key = &options.key;
+                let index = if counted { quote!(__idx) } else { quote!(#idx) };
+                let emit = if options.flatten {
+                    quote! {
+                        __s.emit_struct_flattened(#index, |__s| {
+                            ::rustc_serialize::Encodable::encode(&self.#member, __s)
+                        })?;
+                    }
+                } else {
+                    quote! {
+                        __s.emit_struct_field(#key, #index, |__s| {
+                            ::rustc_serialize::Encodable::encode(&self.#member, __s)
+                        })?;
+                    }
+                };
+                match options.skip_serializing_if {
+                    Some(ref predicate) => quote! {
+                        if !#predicate(&self.#member) {
+                            #emit
+                            __idx += 1;
+                        }
+                    },
+                    None if counted => quote! {
+                        #emit
+                        __idx += 1;
+                    },
+                    None => emit,
+                }
+            });
+            let init = if counted { quote!(let mut __idx = 0;) } else { quote!() };
+            quote! {
+                __s.emit_struct(#name_str, #len, |__s| {
+                    #init
+                    #(#fields)*
+                    Ok(())
+                })
+            }
+        }
+        Data::Enum(ref data) => {
+            let names = variant_names(input)?;
+            let tagging = container_options(input)?.tagging;
+            let variants = data.variants.iter().zip(names);
+            let arms = variants.enumerate().map(|(idx, (variant, variant_str))| {
+                let (pattern, bindings) = variant_pattern(name, variant);
+                let len = variant.fields.len();
+                let body = match tagging {
+                    Tagging::External => match variant.fields {
+                        Fields::Named(_) => {
+                            let names = variant_field_names(variant);
+                            let indices = 0..len;
+                            quote! {
+                                __s.emit_enum_struct_variant(#variant_str, #idx, #len, |__s| {
+                                    #(
+                                        __s.emit_enum_struct_variant_field(#names, #indices,
+                                                                           |__s| {
+                                            ::rustc_serialize::Encodable::encode(#bindings, __s)
+                                        })?;
+                                    )*
+                                    Ok(())
+                                })
+                            }
+                        }
+                        _ => {
+                            let indices = 0..len;
+                            quote! {
+                                __s.emit_enum_variant(#variant_str, #idx, #len, |__s| {
+                                    #(
+                                        __s.emit_enum_variant_arg(#indices, |__s| {
+                                            ::rustc_serialize::Encodable::encode(#bindings, __s)
+                                        })?;
+                                    )*
+                                    Ok(())
+                                })
+                            }
+                        }
+                    },
+                    Tagging::Internal(ref tag) => {
+                        let tag = quote! {
+                            __s.emit_struct_field(#tag, 0, |__s| __s.emit_str(#variant_str))?;
+                        };
+                        let (len, fields) = match variant.fields {
+                            Fields::Named(_) => {
+                                (len + 1, encode_variant_fields(variant, &bindings, 1))
+                            }
+                            Fields::Unnamed(_) if len == 1 => {
+                                (2, quote! {
+                                    __s.emit_struct_flattened(1, |__s| {
+                                        ::rustc_serialize::Encodable::encode(__self_0, __s)
+                                    })?;
+                                })
+                            }
+                            Fields::Unnamed(_) => {
+                                return Err(syn::Error::new_spanned(variant,
+                                    "internally tagged enums can only have tuple variants \
+                                     with a single field"));
+                            }
+                            Fields::Unit => (1, quote!()),
+                        };
+                        quote! {
+                            __s.emit_struct(#name_str, #len, |__s| {
+                                #tag
+                                #fields
+                                Ok(())
+                            })
+                        }
+                    }
+                    Tagging::Adjacent(ref tag, ref content) => {
+                        let tag = quote! {
+                            __s.emit_struct_field(#tag, 0, |__s| __s.emit_str(#variant_str))?;
+                        };
+                        match variant.fields {
+                            Fields::Unit => quote! {
+                                __s.emit_struct(#name_str, 1, |__s| {
+                                    #tag
+                                    Ok(())
+                                })
+                            },
+                            _ => {
+                                let value = encode_untagged(variant, &variant_str, &bindings);
+                                quote! {
+                                    __s.emit_struct(#name_str, 2, |__s| {
+                                        #tag
+                                        __s.emit_struct_field(#content, 1, |__s| #value)?;
+                                        Ok(())
+                                    })
+                                }
+                            }
+                        }
+                    }
+                    Tagging::Untagged => encode_untagged(variant, &variant_str, &bindings),
+                };
+                Ok(quote!(#pattern => #body,))
+            }).collect::<syn::Result<Vec<_>>>()?;
+            match tagging {
+                Tagging::External => quote! {
+                    __s.emit_enum(#name_str, |__s| match *self {
+                        #(#arms)*
+                    })
+                },
+                _ => quote! {
+                    match *self {
+                        #(#arms)*
+                    }
+                },
+            }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(input,
+                                               "unions can't derive `RustcEncodable`"));
+        }
+    };
+
+    let generics = with_bound(&input.generics, quote!(::rustc_serialize::Encodable));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let allow = if counted { quote!(#[allow(unused_assignments)]) } else { quote!() };
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics ::rustc_serialize::Encodable for #name #ty_generics #where_clause {
+            #allow
+            fn encode<__S: ::rustc_serialize::Encoder>(&self, __s: &mut __S)
+                -> ::std::result::Result<(), __S::Error>
+            {
+                #body
+            }
+        }
+    })
+}
+
+fn decodable(input: &DeriveInput) -> syn::Result<Tokens> {
+    let name = &input.ident;
+    let name_str = name.unraw().to_string();
+    let transparent = container_options(input)?.transparent;
+    let body = match input.data {
+        Data::Struct(ref data) if transparent => {
+            let value = vec![quote!(::rustc_serialize::Decodable::decode(__d)?)];
+            let value = construct(quote!(#name), &data.fields, value);
+            quote!(Ok(#value))
+        }
+        Data::Struct(ref data) => {
+            let options = field_options(input, &data.fields)?;
+            let len = options.iter().filter(|o| !o.skip).count();
+            // flattened fields take their keys out of the object before the
+            // struct's own fields, so they are read ahead into locals
+            let mut flattened = Vec::new();
+            let mut idx = 0usize;
+            let values = data.fields.iter().zip(options).enumerate().map(|(n, (field, options))| {
+                if options.skip {
+                    return match options.default {
+                        Some(ref default) => quote!(#default()),
+                        None => quote!(::std::default::Default::default()),
+                    };
+                }
+                let key = &options.key;
+                idx += 1;
+                let i = idx - 1;
+                if options.flatten {
+                    let local = Ident::new(&format!("__field{}", n), Span::call_site());
+                    flattened.push(quote! {
+                        let #local = __d.read_struct_flattened(#i,
+                                                               ::rustc_serialize::Decodable::decode)?;
+                    });
+                    return quote!(#local);
+                }
+                match options.default {
+                    // decoders give `None` for missing fields
+                    Some(ref default) => {
+                        let ty = &field.ty;
+                        quote!({
+                            let __value: ::std::option::Option<#ty> =
+                                __d.read_struct_field(#key, #i,
+                                                      ::rustc_serialize::Decodable::decode)?;
+                            __value.unwrap_or_else(#default)
+                        })
+                    }
+                    None => quote! {
+                        __d.read_struct_field(#key, #i, ::rustc_serialize::Decodable::decode)?
+                    },
+                }
+            }).collect();
+            let value = construct(quote!(#name), &data.fields, values);
+            quote! {
+                __d.read_struct(#name_str, #len, |__d| {
+                    #(#flattened)*
+                    Ok(#value)
+                })
+            }
+        }
+        Data::Enum(ref data) => {
+            let names = variant_names(input)?;
+            match container_options(input)?.tagging {
+                Tagging::External => {
+                    let arms = data.variants.iter().enumerate().map(|(idx, variant)| {
+                        let ident = &variant.ident;
+                        let values = match variant.fields {
+                            Fields::Named(_) => {
+                                variant_field_names(variant).iter().enumerate().map(|(i, name)| {
+                                    quote! {
+                                        __d.read_enum_struct_variant_field(#name, #i,
+                                            ::rustc_serialize::Decodable::decode)?
+                                    }
+                                }).collect()
+                            }
+                            _ => (0..variant.fields.len()).map(|i| {
+                                quote! {
+                                    __d.read_enum_variant_arg(#i,
+                                        ::rustc_serialize::Decodable::decode)?
+                                }
+                            }).collect(),
+                        };
+                        let value = construct(quote!(#name::#ident), &variant.fields, values);
+                        quote!(#idx => Ok(#value),)
+                    });
+                    quote! {
+                        __d.read_enum(#name_str, |__d| {
+                            __d.read_enum_variant(&[#(#names),*], |__d, __idx| match __idx {
+                                #(#arms)*
+                                _ => unreachable!(),
+                            })
+                        })
+                    }
+                }
+                Tagging::Internal(ref tag) => {
+                    let len = 1 + data.variants.iter().map(|v| v.fields.len()).max().unwrap_or(0);
+                    let arms = data.variants.iter().zip(&names).map(|(variant, variant_str)| {
+                        let ident = &variant.ident;
+                        let values = match variant.fields {
+                            Fields::Unnamed(_) => vec![quote! {
+                                __d.read_struct_flattened(1, ::rustc_serialize::Decodable::decode)?
+                            }],
+                            _ => decode_variant_fields(variant, 1),
+                        };
+                        let value = construct(quote!(#name::#ident), &variant.fields, values);
+                        quote!(#variant_str => Ok(#value),)
+                    });
+                    decode_tagged(&name_str, len, tag, arms)
+                }
+                Tagging::Adjacent(ref tag, ref content) => {
+                    let arms = data.variants.iter().zip(&names).map(|(variant, variant_str)| {
+                        let ident = &variant.ident;
+                        match variant.fields {
+                            Fields::Unit => quote!(#variant_str => Ok(#name::#ident),),
+                            _ => {
+                                let value = decode_untagged(name, variant, variant_str);
+                                quote! {
+                                    #variant_str => __d.read_struct_field(#content, 1, |__d| #value),
+                                }
+                            }
+                        }
+                    });
+                    decode_tagged(&name_str, 2, tag, arms)
+                }
+                Tagging::Untagged => {
+                    let len = data.variants.len();
+                    let arms = data.variants.iter().zip(&names).enumerate()
+                        .map(|(i, (variant, variant_str))| {
+                            let value = decode_untagged(name, variant, variant_str);
+                            quote!(#i => #value,)
+                        });
+                    quote! {
+                        __d.read_alternatives(#len, |__d, __i| match __i {
+                            #(#arms)*
+                            _ => unreachable!(),
+                        })
+                    }
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(input,
+                                               "unions can't derive `RustcDecodable`"));
+        }
+    };
+
+    let generics = with_bound(&input.generics, quote!(::rustc_serialize::Decodable));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics ::rustc_serialize::Decodable for #name #ty_generics #where_clause {
+            fn decode<__D: ::rustc_serialize::Decoder>(__d: &mut __D)
+                -> ::std::result::Result<Self, __D::Error>
+            {
+                #body
+            }
+        }
+    })
+}
+
+/// How a field of a struct is encoded, as given by its attributes.
+struct FieldOptions {
+    /// The key the field is encoded under: the field's name, or
+    /// `_field<index>` for the fields of tuple structs, unless renamed.
+    key: String,
+    /// Whether the field is left out of the encoding altogether, and
+    /// defaulted when decoding.
+    skip: bool,
+    /// A function deciding whether to leave out the field given its value.
+    skip_serializing_if: Option<ExprPath>,
+    /// The function giving the value of the field when it is missing.
+    default: Option<ExprPath>,
+    /// Whether the fields of the field's value are inlined into the struct.
+    flatten: bool,
+}
+
+fn field_options(input: &DeriveInput, fields: &Fields) -> syn::Result<Vec<FieldOptions>> {
+    let rule = container_options(input)?.rule;
+    fields.iter().enumerate().map(|(i, field)| {
+        let mut options = FieldOptions {
+            key: match field.ident {
+                Some(ref ident) => rule.apply(&ident.unraw().to_string()),
+                None => format!("_field{}", i),
+            },
+            skip: false,
+            skip_serializing_if: None,
+            default: None,
+            flatten: false,
+        };
+        let mut renamed = false;
+        for (key, value) in serialize_options(&field.attrs)? {
+            if key == "rename" {
+                options.key = string_value(&key, value)?.value();
+                renamed = true;
+            } else if key == "skip" {
+                flag(&key, value)?;
+                options.skip = true;
+            } else if key == "skip_serializing_if" {
+                options.skip_serializing_if = Some(string_value(&key, value)?.parse()?);
+            } else if key == "flatten" {
+                flag(&key, value)?;
+                options.flatten = true;
+            } else if key == "default" {
+                options.default = Some(match value {
+                    Some(value) => value.parse()?,
+                    None => syn::parse_quote!(::std::default::Default::default),
+                });
+            } else {
+                return Err(syn::Error::new_spanned(key, "unknown serialize option"));
+            }
+        }
+        if options.flatten && (renamed || options.default.is_some()) {
+            return Err(syn::Error::new_spanned(field,
+                "a flattened field has no key of its own, so can't be renamed or defaulted"));
+        }
+        Ok(options)
+    }).collect()
+}
+
+/// The names the variants of an enum are encoded under.
+fn variant_names(input: &DeriveInput) -> syn::Result<Vec<String>> {
+    let rule = container_options(input)?.rule;
+    let data = match input.data {
+        Data::Enum(ref data) => data,
+        _ => unreachable!(),
+    };
+    data.variants.iter().map(|variant| {
+        // the fields of a variant are encoded by position, so there is no
+        // name to change
+        for field in variant.fields.iter() {
+            if let Some(attr) = field.attrs.iter().find(|a| a.path.is_ident("serialize")) {
+                return Err(syn::Error::new_spanned(attr,
+                    "fields of enum variants are encoded by position and can't be renamed"));
+            }
+        }
+        Ok(match rename(&variant.attrs)? {
+            Some(name) => name,
+            None => rule.apply(&variant.ident.unraw().to_string()),
+        })
+    }).collect()
+}
+
+/// Builds the expression constructing `path` out of `fields`, given the
+/// expressions reading the value of each field.
+fn construct(path: Tokens, fields: &Fields, values: Vec<Tokens>) -> Tokens {
+    match *fields {
+        Fields::Named(ref fields) => {
+            let idents = fields.named.iter().map(|f| &f.ident);
+            quote!(#path { #(#idents: #values),* })
+        }
+        Fields::Unnamed(_) => quote!(#path(#(#values),*)),
+        Fields::Unit => path,
+    }
+}
+
+/// Builds the pattern matching `variant` of the enum `name`, binding its
+/// fields by reference to `__self_0`, `__self_1`, and so on.
+fn variant_pattern(name: &Ident, variant: &Variant) -> (Tokens, Vec<Ident>) {
+    let ident = &variant.ident;
+    let bindings = (0..variant.fields.len()).map(|i| {
+        Ident::new(&format!("__self_{}", i), Span::call_site())
+    }).collect::<Vec<_>>();
+    let pattern = match variant.fields {
+        Fields::Named(ref fields) => {
+            let names = fields.named.iter().map(|f| &f.ident);
+            quote!(#name::#ident { #(#names: ref #bindings),* })
+        }
+        Fields::Unnamed(_) => quote!(#name::#ident(#(ref #bindings),*)),
+        Fields::Unit => quote!(#name::#ident),
+    };
+    (pattern, bindings)
+}
+
+/// The names the named fields of a variant are encoded under by the tagged
+/// representations.
+fn variant_field_names(variant: &Variant) -> Vec<String> {
+    variant.fields.iter().map(|f| f.ident.as_ref().unwrap().unraw().to_string()).collect()
+}
+
+/// Emits the named fields of a variant as struct fields, from index `first`.
+fn encode_variant_fields(variant: &Variant, bindings: &[Ident], first: usize) -> Tokens {
+    let names = variant_field_names(variant);
+    let indices = first..first + names.len();
+    quote! {
+        #(
+            __s.emit_struct_field(#names, #indices, |__s| {
+                ::rustc_serialize::Encodable::encode(#bindings, __s)
+            })?;
+        )*
+    }
+}
+
+/// Reads the named fields of a variant as struct fields, from index `first`.
+fn decode_variant_fields(variant: &Variant, first: usize) -> Vec<Tokens> {
+    variant_field_names(variant).iter().enumerate().map(|(i, name)| {
+        let i = first + i;
+        quote!(__d.read_struct_field(#name, #i, ::rustc_serialize::Decodable::decode)?)
+    }).collect()
+}
+
+/// Encodes a variant as its content alone: `null` for unit variants, the
+/// value for single field tuple variants, a tuple for other tuple variants
+/// and a struct for struct variants.
+fn encode_untagged(variant: &Variant, variant_str: &str, bindings: &[Ident]) -> Tokens {
+    let len = bindings.len();
+    match variant.fields {
+        Fields::Unit => quote!(__s.emit_nil()),
+        Fields::Named(_) => {
+            let fields = encode_variant_fields(variant, bindings, 0);
+            quote! {
+                __s.emit_struct(#variant_str, #len, |__s| {
+                    #fields
+                    Ok(())
+                })
+            }
+        }
+        Fields::Unnamed(_) if len == 1 => {
+            quote!(::rustc_serialize::Encodable::encode(__self_0, __s))
+        }
+        Fields::Unnamed(_) => {
+            let indices = 0..len;
+            quote! {
+                __s.emit_tuple(#len, |__s| {
+                    #(
+                        __s.emit_tuple_arg(#indices, |__s| {
+                            ::rustc_serialize::Encodable::encode(#bindings, __s)
+                        })?;
+                    )*
+                    Ok(())
+                })
+            }
+        }
+    }
+}
+
+/// Decodes a variant encoded by `encode_untagged`.
+fn decode_untagged(name: &Ident, variant: &Variant, variant_str: &str) -> Tokens {
+    let ident = &variant.ident;
+    let len = variant.fields.len();
+    match variant.fields {
+        Fields::Unit => quote!(__d.read_nil().map(|()| #name::#ident)),
+        Fields::Named(_) => {
+            let value = construct(quote!(#name::#ident), &variant.fields,
+                                  decode_variant_fields(variant, 0));
+            quote!(__d.read_struct(#variant_str, #len, |__d| Ok(#value)))
+        }
+        Fields::Unnamed(_) if len == 1 => {
+            quote!(Ok(#name::#ident(::rustc_serialize::Decodable::decode(__d)?)))
+        }
+        Fields::Unnamed(_) => {
+            let indices = 0..len;
+            quote! {
+                __d.read_tuple(#len, |__d| {
+                    Ok(#name::#ident(#(
+                        __d.read_tuple_arg(#indices, ::rustc_serialize::Decodable::decode)?
+                    ),*))
+                })
+            }
+        }
+    }
+}
+
+/// Decodes a struct whose `tag` field tells which of `arms`, matching on the
+/// tag, reads the rest of it.
+fn decode_tagged<I>(name_str: &str, len: usize, tag: &str, arms: I) -> Tokens
+    where I: Iterator<Item = Tokens>
+{
+    quote! {
+        __d.read_struct(#name_str, #len, |__d| {
+            let __tag: ::std::string::String =
+                __d.read_struct_field(#tag, 0, ::rustc_serialize::Decodable::decode)?;
+            match &__tag[..] {
+                #(#arms)*
+                _ => Err(__d.error(&format!("unknown variant `{}`", __tag))),
+            }
+        })
+    }
+}
+
+/// Adds `bound` to every type parameter of `generics`.
+fn with_bound(generics: &Generics, bound: Tokens) -> Generics {
+    let mut generics = generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(syn::parse_quote!(#bound));
+    }
+    generics
+}
+
+/// Returns the options of the `#[serialize(...)]` attributes in `attrs`,
+/// either `key = "value"` or a lone `key`.
+fn serialize_options(attrs: &[Attribute]) -> syn::Result<Vec<(Ident, Option<LitStr>)>> {
+    let mut options = Vec::new();
+    for attr in attrs.iter().filter(|a| a.path.is_ident("serialize")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => {
+                return Err(syn::Error::new_spanned(meta,
+                    "expected `#[serialize(key = \"value\", ...)]`"));
+            }
+        };
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::Path(ref path)) if path.get_ident().is_some() => {
+                    options.push((path.get_ident().unwrap().clone(), None));
+                }
+                NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.get_ident().is_some() => {
+                    let key = nv.path.get_ident().unwrap().clone();
+                    match nv.lit {
+                        Lit::Str(ref value) => options.push((key, Some(value.clone()))),
+                        ref lit => {
+                            return Err(syn::Error::new_spanned(lit,
+                                "expected a string literal"));
+                        }
+                    }
+                }
+                nested => {
+                    return Err(syn::Error::new_spanned(nested,
+                        "expected `key = \"value\"` or `key`"));
+                }
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// Checks that the option `key` was given a value.
+fn string_value(key: &Ident, value: Option<LitStr>) -> syn::Result<LitStr> {
+    value.ok_or_else(|| {
+        syn::Error::new_spanned(key, format!("expected `{} = \"...\"`", key))
+    })
+}
+
+/// Checks that the option `key` wasn't given a value.
+fn flag(key: &Ident, value: Option<LitStr>) -> syn::Result<()> {
+    match value {
+        Some(value) => Err(syn::Error::new_spanned(value,
+                                                   format!("`{}` doesn't take a value", key))),
+        None => Ok(()),
+    }
+}
+
+/// Reads the `rename` option of a variant.
+fn rename(attrs: &[Attribute]) -> syn::Result<Option<String>> {
+    let mut name = None;
+    for (key, value) in serialize_options(attrs)? {
+        if key == "rename" {
+            name = Some(string_value(&key, value)?.value());
+        } else {
+            return Err(syn::Error::new_spanned(key, "unknown serialize option"));
+        }
+    }
+    Ok(name)
+}
+
+/// The options given to the type being derived for.
+struct ContainerOptions {
+    rule: RenameRule,
+    tagging: Tagging,
+    /// `#[serialize(transparent)]`: the struct is encoded as its only field.
+    transparent: bool,
+}
+
+/// How the variants of an enum are told apart.
+enum Tagging {
+    /// Through `emit_enum`, the default.
+    External,
+    /// `#[serialize(tag = "...")]`: by a field of the variant's struct.
+    Internal(String),
+    /// `#[serialize(tag = "...", content = "...")]`: by a field next to the
+    /// one holding the variant's content.
+    Adjacent(String, String),
+    /// `#[serialize(untagged)]`: by whichever variant decodes.
+    Untagged,
+}
+
+fn container_options(input: &DeriveInput) -> syn::Result<ContainerOptions> {
+    let mut rule = RenameRule::None;
+    let mut tag = None;
+    let mut content = None;
+    let mut untagged = None;
+    let mut transparent = false;
+    for (key, value) in serialize_options(&input.attrs)? {
+        if key == "rename_all" {
+            let value = string_value(&key, value)?;
+            rule = RenameRule::parse(&value.value()).ok_or_else(|| {
+                syn::Error::new_spanned(&value,
+                    "unknown case, expected one of \"lowercase\", \"UPPERCASE\", \
+                     \"camelCase\", \"PascalCase\", \"snake_case\", \
+                     \"SCREAMING_SNAKE_CASE\", \"kebab-case\" or \
+                     \"SCREAMING-KEBAB-CASE\"")
+            })?;
+        } else if key == "tag" {
+            tag = Some(string_value(&key, value)?.value());
+        } else if key == "content" {
+            content = Some((key.clone(), string_value(&key, value)?.value()));
+        } else if key == "untagged" {
+            flag(&key, value)?;
+            untagged = Some(key);
+        } else if key == "transparent" {
+            flag(&key, value)?;
+            transparent = true;
+        } else {
+            return Err(syn::Error::new_spanned(key, "unknown serialize option"));
+        }
+    }
+    let tagging = match (tag, content, untagged) {
+        (None, None, None) => Tagging::External,
+        (Some(tag), None, None) => Tagging::Internal(tag),
+        (Some(tag), Some((_, content)), None) => Tagging::Adjacent(tag, content),
+        (None, None, Some(_)) => Tagging::Untagged,
+        (None, Some((key, _)), _) => {
+            return Err(syn::Error::new_spanned(key, "`content` needs a `tag` to go with"));
+        }
+        (Some(_), _, Some(key)) => {
+            return Err(syn::Error::new_spanned(key, "an enum can't be both tagged and untagged"));
+        }
+    };
+    if let Tagging::External = tagging {
+    } else if let Data::Struct(_) = input.data {
+        return Err(syn::Error::new_spanned(input, "only enums can be tagged or untagged"));
+    }
+    if transparent {
+        match input.data {
+            Data::Struct(ref data) if data.fields.len() == 1 => {}
+            Data::Struct(_) => {
+                return Err(syn::Error::new_spanned(input,
+                    "a transparent struct must have exactly one field"));
+            }
+            _ => return Err(syn::Error::new_spanned(input, "only structs can be transparent")),
+        }
+    }
+    Ok(ContainerOptions { rule, tagging, transparent })
+}
+
+/// A naming convention applied to every field (of a struct) or variant (of
+/// an enum) by `#[serialize(rename_all = "...")]`.
+#[derive(Clone, Copy)]
+enum RenameRule {
+    None,
+    Lower,
+    Upper,
+    Camel,
+    Pascal,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl RenameRule {
+    fn parse(s: &str) -> Option<RenameRule> {
+        Some(match s {
+            "lowercase" => RenameRule::Lower,
+            "UPPERCASE" => RenameRule::Upper,
+            "camelCase" => RenameRule::Camel,
+            "PascalCase" => RenameRule::Pascal,
+            "snake_case" => RenameRule::Snake,
+            "SCREAMING_SNAKE_CASE" => RenameRule::ScreamingSnake,
+            "kebab-case" => RenameRule::Kebab,
+            "SCREAMING-KEBAB-CASE" => RenameRule::ScreamingKebab,
+            _ => return None,
+        })
+    }
+
+    /// Renames `name`, which may be in either `snake_case` (fields) or
+    /// `PascalCase` (variants).
+    fn apply(self, name: &str) -> String {
+        let words = words(name);
+        let capitalize = |w: &String| {
+            let mut chars = w.chars();
+            match chars.next() {
+                Some(c) => c.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        };
+        match self {
+            RenameRule::None => name.to_string(),
+            RenameRule::Lower => words.concat(),
+            RenameRule::Upper => words.concat().to_uppercase(),
+            RenameRule::Camel => {
+                let mut out = words.first().cloned().unwrap_or_default();
+                out.extend(words.iter().skip(1).map(capitalize));
+                out
+            }
+            RenameRule::Pascal => words.iter().map(capitalize).collect(),
+            RenameRule::Snake => words.join("_"),
+            RenameRule::ScreamingSnake => words.join("_").to_uppercase(),
+            RenameRule::Kebab => words.join("-"),
+            RenameRule::ScreamingKebab => words.join("-").to_uppercase(),
+        }
+    }
+}
+
+/// Splits a `snake_case` or `PascalCase` identifier into lowercase words.
+fn words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for c in name.chars() {
+        if (c == '_' || c.is_uppercase()) && !word.is_empty() {
+            words.push(word);
+            word = String::new();
+        }
+        if c != '_' {
+            word.extend(c.to_lowercase());
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
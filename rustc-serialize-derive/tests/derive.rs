@@ -0,0 +1,484 @@
+#[macro_use]
+extern crate rustc_serialize_derive;
+extern crate rustc_serialize;
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+use rustc_serialize::json;
+use rustc_serialize::{Decodable, Encodable};
+
+fn round_trip<T>(value: T, expected: &str)
+    where T: Encodable + Decodable + PartialEq + Debug
+{
+    let encoded = json::encode(&value).unwrap();
+    assert_eq!(encoded, expected);
+    assert_eq!(json::decode::<T>(&encoded).unwrap(), value);
+    assert_eq!(json::decode_str::<T>(&encoded).unwrap(), value);
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+struct Named {
+    id: u32,
+    name: String,
+    tags: Vec<String>,
+    parent: Option<Box<Named>>,
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+struct Tuple(u8, String);
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+struct Unit;
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+struct Empty {}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+struct Generic<K: Ord, V> {
+    map: BTreeMap<K, V>,
+    first: Option<(K, V)>,
+}
+
+#[derive(RustcEncodable)]
+struct Wrapper<'a, T>(T, Option<Box<Generic<&'a str, T>>>)
+    where T: Clone;
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+enum Plain {
+    North,
+    South,
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+enum Shape<T> {
+    Point,
+    Circle(T),
+    Rect { width: T, height: T },
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+struct Raw {
+    r#type: u8,
+}
+
+#[test]
+fn test_named_struct() {
+    let child = Named {
+        id: 2,
+        name: "child".to_string(),
+        tags: vec![],
+        parent: Some(Box::new(Named {
+            id: 1,
+            name: "root".to_string(),
+            tags: vec!["a".to_string()],
+            parent: None,
+        })),
+    };
+    round_trip(child, "{\"id\":2,\"name\":\"child\",\"tags\":[],\"parent\":\
+                       {\"id\":1,\"name\":\"root\",\"tags\":[\"a\"],\"parent\":null}}");
+}
+
+#[test]
+fn test_tuple_and_unit_structs() {
+    round_trip(Tuple(7, "x".to_string()), "{\"_field0\":7,\"_field1\":\"x\"}");
+    round_trip(Unit, "{}");
+    round_trip(Empty {}, "{}");
+}
+
+#[test]
+fn test_generic_structs() {
+    let mut map = BTreeMap::new();
+    map.insert("a".to_string(), 1u8);
+    round_trip(Generic { first: Some(("a".to_string(), 1)), map: map },
+               "{\"map\":{\"a\":1},\"first\":[\"a\",1]}");
+
+    let wrapper: Wrapper<'static, u8> = Wrapper(3, None);
+    assert_eq!(json::encode(&wrapper).unwrap(), "{\"_field0\":3,\"_field1\":null}");
+}
+
+#[test]
+fn test_enums() {
+    round_trip(Plain::North, "\"North\"");
+    round_trip(Plain::South, "\"South\"");
+    round_trip(Shape::<u8>::Point, "\"Point\"");
+    round_trip(Shape::Circle(2u8), "{\"variant\":\"Circle\",\"fields\":[2]}");
+    round_trip(Shape::Rect { width: 3u8, height: 4 },
+               "{\"variant\":\"Rect\",\"fields\":[3,4]}");
+
+    assert!(json::decode::<Plain>("\"East\"").is_err());
+}
+
+#[test]
+fn test_enum_tagging() {
+    let shape = Shape::Rect { width: 3u8, height: 4 };
+    let mut encoded = String::new();
+    {
+        let mut encoder = json::Encoder::new(&mut encoded);
+        encoder.set_enum_tagging(json::EnumTagging::External);
+        shape.encode(&mut encoder).unwrap();
+    }
+    assert_eq!(encoded, "{\"Rect\":{\"width\":3,\"height\":4}}");
+
+    let mut decoder = json::Decoder::new(json::Json::from_str(&encoded).unwrap());
+    decoder.set_enum_tagging(json::EnumTagging::External);
+    assert_eq!(Shape::<u8>::decode(&mut decoder).unwrap(), shape);
+}
+
+#[test]
+fn test_raw_identifiers() {
+    round_trip(Raw { r#type: 1 }, "{\"type\":1}");
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+#[serialize(rename_all = "camelCase")]
+struct Account {
+    user_id: u32,
+    display_name: String,
+    #[serialize(rename = "e-mail")]
+    email_address: Option<String>,
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+#[serialize(rename_all = "SCREAMING_SNAKE_CASE")]
+enum Status {
+    Active,
+    OnHold(String),
+    #[serialize(rename = "gone")]
+    Deleted,
+}
+
+#[test]
+fn test_rename() {
+    round_trip(Account {
+                   user_id: 7,
+                   display_name: "Ann".to_string(),
+                   email_address: None,
+               },
+               "{\"userId\":7,\"displayName\":\"Ann\",\"e-mail\":null}");
+
+    // the original field names are no longer accepted
+    match json::decode::<Account>("{\"user_id\":7,\"displayName\":\"Ann\"}") {
+        Err(json::DecoderError::MissingFieldError(ref f)) => assert_eq!(f, "userId"),
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    round_trip(Status::Active, "\"ACTIVE\"");
+    round_trip(Status::OnHold("audit".to_string()),
+               "{\"variant\":\"ON_HOLD\",\"fields\":[\"audit\"]}");
+    round_trip(Status::Deleted, "\"gone\"");
+    assert!(json::decode::<Status>("\"Deleted\"").is_err());
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+struct Entry {
+    #[serialize(skip_serializing_if = "Option::is_none")]
+    id: Option<u32>,
+    name: String,
+    #[serialize(skip)]
+    cached_len: usize,
+    #[serialize(skip_serializing_if = "is_zero")]
+    hits: u32,
+}
+
+#[test]
+fn test_skip() {
+    let entry = Entry { id: Some(1), name: "a".to_string(), cached_len: 0, hits: 2 };
+    round_trip(entry, "{\"id\":1,\"name\":\"a\",\"hits\":2}");
+
+    // a skipped field is neither encoded nor decoded
+    let entry = Entry { id: Some(1), name: "abc".to_string(), cached_len: 3, hits: 2 };
+    assert_eq!(json::encode(&entry).unwrap(), "{\"id\":1,\"name\":\"abc\",\"hits\":2}");
+    let decoded: Entry = json::decode("{\"id\":1,\"name\":\"abc\",\"cached_len\":3,\"hits\":2}")
+                              .unwrap();
+    assert_eq!(decoded.cached_len, 0);
+
+    // fields left out by their predicate, including the first one
+    let entry = Entry { id: None, name: "a".to_string(), cached_len: 0, hits: 2 };
+    assert_eq!(json::encode(&entry).unwrap(), "{\"name\":\"a\",\"hits\":2}");
+    let entry = Entry { id: None, name: "a".to_string(), cached_len: 0, hits: 0 };
+    assert_eq!(json::encode(&entry).unwrap(), "{\"name\":\"a\"}");
+    assert_eq!(json::decode::<Entry>("{\"name\":\"a\",\"hits\":0}").unwrap(), entry);
+    assert_eq!(json::as_pretty_json(&entry).to_string(), "{\n  \"name\": \"a\"\n}");
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+struct User {
+    name: String,
+    #[serialize(skip_serializing_if = "json::NullableString::is_none")]
+    nickname: json::NullableString,
+}
+
+#[test]
+fn test_skip_nullable_string() {
+    let user = User { name: "a".to_string(), nickname: json::NullableString(None) };
+    round_trip(user, "{\"name\":\"a\"}");
+    let user = User {
+        name: "a".to_string(),
+        nickname: json::NullableString(Some("b".to_string())),
+    };
+    round_trip(user, "{\"name\":\"a\",\"nickname\":\"b\"}");
+}
+
+fn default_port() -> u16 {
+    8080
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+struct Config {
+    host: String,
+    #[serialize(default = "default_port")]
+    port: u16,
+    #[serialize(default)]
+    aliases: Vec<String>,
+    #[serialize(skip, default = "default_port")]
+    fallback_port: u16,
+}
+
+#[test]
+fn test_default() {
+    let config = Config {
+        host: "localhost".to_string(),
+        port: 80,
+        aliases: vec!["local".to_string()],
+        fallback_port: 8080,
+    };
+    round_trip(config, "{\"host\":\"localhost\",\"port\":80,\"aliases\":[\"local\"]}");
+
+    let config: Config = json::decode("{\"host\":\"localhost\"}").unwrap();
+    assert_eq!(config, Config {
+        host: "localhost".to_string(),
+        port: 8080,
+        aliases: vec![],
+        fallback_port: 8080,
+    });
+    let config: Config = json::decode("{\"host\":\"a\",\"port\":null,\"aliases\":null}").unwrap();
+    assert_eq!((config.port, config.aliases), (8080, vec![]));
+
+    // fields without a default are still required
+    match json::decode::<Config>("{\"port\":1}") {
+        Err(json::DecoderError::MissingFieldError(ref f)) => assert_eq!(f, "host"),
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert!(json::decode::<Config>("{\"host\":\"a\",\"port\":true}").is_err());
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+struct Meta {
+    author: String,
+    year: u16,
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+struct Page {
+    title: String,
+    #[serialize(flatten)]
+    meta: Meta,
+    views: u32,
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+struct Clash {
+    #[serialize(flatten)]
+    meta: Meta,
+    year: u16,
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+struct Nested {
+    #[serialize(flatten)]
+    page: Page,
+    #[serialize(flatten)]
+    extra: Option<Tuple>,
+}
+
+#[derive(RustcEncodable)]
+struct NotAStruct {
+    #[serialize(flatten)]
+    tags: Vec<String>,
+}
+
+#[test]
+fn test_flatten() {
+    let page = Page {
+        title: "Home".to_string(),
+        meta: Meta { author: "ann".to_string(), year: 2017 },
+        views: 3,
+    };
+    round_trip(page, "{\"title\":\"Home\",\"author\":\"ann\",\"year\":2017,\"views\":3}");
+
+    let nested = Nested {
+        page: Page {
+            title: "Home".to_string(),
+            meta: Meta { author: "ann".to_string(), year: 2017 },
+            views: 3,
+        },
+        extra: Some(Tuple(1, "x".to_string())),
+    };
+    round_trip(nested, "{\"title\":\"Home\",\"author\":\"ann\",\"year\":2017,\"views\":3,\
+                        \"_field0\":1,\"_field1\":\"x\"}");
+    assert_eq!(json::as_pretty_json(&Page {
+                   title: "a".to_string(),
+                   meta: Meta { author: "b".to_string(), year: 1 },
+                   views: 0,
+               }).to_string(),
+               "{\n  \"title\": \"a\",\n  \"author\": \"b\",\n  \"year\": 1,\n  \"views\": 0\n}");
+
+    assert_eq!(json::to_string_sorted(&Page {
+                   title: "a".to_string(),
+                   meta: Meta { author: "b".to_string(), year: 1 },
+                   views: 0,
+               }).unwrap(),
+               "{\"author\":\"b\",\"title\":\"a\",\"views\":0,\"year\":1}");
+
+    // the order of the keys in the input doesn't matter
+    let page: Page = json::decode("{\"year\":1,\"views\":2,\"author\":\"b\",\"title\":\"a\"}")
+                         .unwrap();
+    assert_eq!(page.meta, Meta { author: "b".to_string(), year: 1 });
+
+    let page: Page = json::decode_str("{\"year\":1,\"views\":2,\"author\":\"b\",\"title\":\"a\"}")
+                         .unwrap();
+    assert_eq!(page.meta, Meta { author: "b".to_string(), year: 1 });
+
+    // a key wanted both by a flattened field and the struct itself
+    match json::decode::<Clash>("{\"author\":\"a\",\"year\":1}") {
+        Err(json::DecoderError::ConflictingFieldError(ref f)) => assert_eq!(f, "year"),
+        other => panic!("unexpected result {:?}", other),
+    }
+    match json::decode_str::<Clash>("{\"author\":\"a\",\"year\":1}") {
+        Err(json::DecoderError::ConflictingFieldError(ref f)) => assert_eq!(f, "year"),
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    let not_a_struct = NotAStruct { tags: vec!["a".to_string()] };
+    assert_eq!(json::encode(&not_a_struct), Err(json::EncoderError::BadFlattenedField));
+    assert_eq!(json::to_string_sorted(&not_a_struct),
+               Err(json::EncoderError::BadFlattenedField));
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+#[serialize(tag = "type")]
+enum Message {
+    Ping,
+    Text { to: u32, body: String },
+    Page(Meta),
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+#[serialize(tag = "t", content = "c")]
+enum Command {
+    Stop,
+    Move(i32, i32),
+    Say(String),
+    Jump { height: u8 },
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+#[serialize(untagged)]
+enum Loose {
+    Empty,
+    Pair(u8, u8),
+    Text(String),
+    Number(u32),
+    Person { name: String },
+}
+
+#[test]
+fn test_internally_tagged() {
+    round_trip(Message::Ping, "{\"type\":\"Ping\"}");
+    round_trip(Message::Text { to: 1, body: "hi".to_string() },
+               "{\"type\":\"Text\",\"to\":1,\"body\":\"hi\"}");
+    round_trip(Message::Page(Meta { author: "ann".to_string(), year: 2017 }),
+               "{\"type\":\"Page\",\"author\":\"ann\",\"year\":2017}");
+
+    // the tag doesn't have to come first
+    assert_eq!(json::decode::<Message>("{\"to\":1,\"body\":\"hi\",\"type\":\"Text\"}").unwrap(),
+               Message::Text { to: 1, body: "hi".to_string() });
+    assert!(json::decode::<Message>("{\"type\":\"Pong\"}").is_err());
+    match json::decode::<Message>("{\"to\":1}") {
+        Err(json::DecoderError::MissingFieldError(ref f)) => assert_eq!(f, "type"),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn test_adjacently_tagged() {
+    round_trip(Command::Stop, "{\"t\":\"Stop\"}");
+    round_trip(Command::Move(1, -2), "{\"t\":\"Move\",\"c\":[1,-2]}");
+    round_trip(Command::Say("hi".to_string()), "{\"t\":\"Say\",\"c\":\"hi\"}");
+    round_trip(Command::Jump { height: 3 }, "{\"t\":\"Jump\",\"c\":{\"height\":3}}");
+    assert!(json::decode::<Command>("{\"t\":\"Say\"}").is_err());
+    assert!(json::decode::<Command>("{\"t\":\"Run\",\"c\":1}").is_err());
+}
+
+#[test]
+fn test_untagged() {
+    round_trip(Loose::Empty, "null");
+    round_trip(Loose::Pair(1, 2), "[1,2]");
+    round_trip(Loose::Text("hi".to_string()), "\"hi\"");
+    round_trip(Loose::Number(3), "3");
+    round_trip(Loose::Person { name: "ann".to_string() }, "{\"name\":\"ann\"}");
+
+    // the first variant the value decodes as wins
+    assert_eq!(json::decode::<Loose>("\"3\"").unwrap(), Loose::Text("3".to_string()));
+    assert!(json::decode::<Loose>("[1,2,3]").is_err());
+    // the error is that of the last variant
+    match json::decode::<Loose>("{\"age\":3}") {
+        Err(json::DecoderError::MissingFieldError(ref f)) => assert_eq!(f, "name"),
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    // failed attempts leave nothing behind for the values after them
+    let s = "[{\"name\":\"ann\"},[1,300],[1,2],3]";
+    assert!(json::decode::<Vec<Loose>>(s).is_err());
+    let s = "[{\"name\":\"ann\"},[1,2],3,null]";
+    let expected = vec![Loose::Person { name: "ann".to_string() }, Loose::Pair(1, 2),
+                        Loose::Number(3), Loose::Empty];
+    assert_eq!(json::decode::<Vec<Loose>>(s).unwrap(), expected);
+    assert_eq!(json::decode_str::<Vec<Loose>>(s).unwrap(), expected);
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+struct Event {
+    kind: String,
+    payload: json::Json,
+}
+
+#[test]
+fn test_json_field() {
+    let payload = json::Json::from_str("{\"b\":[1,2.5,null],\"a\":{\"c\":true}}").unwrap();
+    round_trip(Event { kind: "click".to_string(), payload: payload },
+               "{\"kind\":\"click\",\"payload\":{\"a\":{\"c\":true},\"b\":[1,2.5,null]}}");
+    let event = Event { kind: "none".to_string(), payload: json::Json::Null };
+    assert_eq!(json::as_pretty_json(&event).to_string(),
+               "{\n  \"kind\": \"none\",\n  \"payload\": null\n}");
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+#[serialize(transparent)]
+struct Meters(f64);
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+#[serialize(transparent)]
+struct Tags {
+    names: Vec<String>,
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+struct Route {
+    length: Meters,
+    tags: Tags,
+}
+
+#[test]
+fn test_transparent() {
+    round_trip(Meters(3.5), "3.5");
+    round_trip(Tags { names: vec!["a".to_string()] }, "[\"a\"]");
+    round_trip(Route { length: Meters(2.0), tags: Tags { names: vec![] } },
+               "{\"length\":2.0,\"tags\":[]}");
+    assert!(json::decode::<Meters>("[3.5]").is_err());
+}
@@ -0,0 +1,600 @@
+//! The tests of `json` which need `Encodable` and `Decodable` implementations
+//! derived by this crate. The older tests inside `rustc-serialize` itself use
+//! the derive of the compiler, which keeps them running on its oldest
+//! supported Rust.
+
+#[macro_use]
+extern crate rustc_serialize_derive;
+extern crate rustc_serialize;
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::{f64, string};
+
+use rustc_serialize::json::{self, Json, DecodeResult, Decoder, Encoder,
+                            EncoderError};
+use rustc_serialize::json::Json::*;
+use rustc_serialize::json::ErrorCode::*;
+use rustc_serialize::json::ParserError::*;
+use rustc_serialize::json::DecoderError::*;
+use self::Animal::*;
+use rustc_serialize::{Decodable, Encodable};
+
+#[derive(RustcDecodable, PartialEq, Debug)]
+struct OptionalFields {
+    num: Option<u8>,
+    list: Option<Vec<u8>>,
+}
+
+#[test]
+fn test_decode_option_fields() {
+    let absent: OptionalFields = json::decode("{}").unwrap();
+    assert_eq!(absent, OptionalFields { num: None, list: None });
+
+    let null: OptionalFields =
+        json::decode("{ \"num\": null, \"list\": null }").unwrap();
+    assert_eq!(null, OptionalFields { num: None, list: None });
+
+    let value: OptionalFields = json::decode("{ \"num\": 3 }").unwrap();
+    assert_eq!(value, OptionalFields { num: Some(3), list: None });
+
+    let array: OptionalFields =
+        json::decode("{ \"num\": 0, \"list\": [1, 2] }").unwrap();
+    assert_eq!(array, OptionalFields { num: Some(0), list: Some(vec![1, 2]) });
+
+    let empty: OptionalFields = json::decode("{ \"list\": [] }").unwrap();
+    assert_eq!(empty, OptionalFields { num: None, list: Some(vec![]) });
+}
+
+#[derive(PartialEq, RustcEncodable, RustcDecodable, Debug)]
+enum Animal {
+    Dog,
+    Frog(string::String, isize)
+}
+
+#[derive(PartialEq, RustcEncodable, RustcDecodable, Debug)]
+struct Inner {
+    a: (),
+    b: usize,
+    c: Vec<string::String>,
+}
+
+#[derive(PartialEq, RustcEncodable, RustcDecodable, Debug)]
+struct Outer {
+    inner: Vec<Inner>,
+}
+
+#[test]
+fn test_json_value_encoder() {
+    use json::JsonValueEncoder;
+
+    #[derive(RustcEncodable)]
+    struct Point { y: f64, x: Option<i32>, tags: Vec<char> }
+
+    let point = Point { y: f64::NAN, x: Some(-1), tags: vec!['a'] };
+    let mut encoder = JsonValueEncoder::new();
+    point.encode(&mut encoder).unwrap();
+    let json = encoder.finish();
+    assert_eq!(json, Json::from_str("{\"x\": -1, \"y\": null, \"tags\": [\"a\"]}").unwrap());
+    assert_eq!(json.to_string(), json::to_string_sorted(&point).unwrap());
+
+    let mut encoder = JsonValueEncoder::new();
+    Some(Animal::Frog("Henry".to_string(), 349)).encode(&mut encoder).unwrap();
+    assert_eq!(encoder.finish().to_string(),
+               json::to_string_sorted(&Frog("Henry".to_string(), 349)).unwrap());
+
+    assert_eq!(JsonValueEncoder::new().finish(), Null);
+}
+
+#[test]
+fn test_to_from_value() {
+    use json::{from_value, to_value};
+    use std::collections::HashMap;
+
+    #[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+    struct Record {
+        id: u32,
+        tags: Vec<string::String>,
+        parent: Option<Box<Record>>,
+    }
+
+    let record = Record {
+        id: 1,
+        tags: vec!["a".to_string()],
+        parent: Some(Box::new(Record { id: 0, tags: vec![], parent: None })),
+    };
+    let mut json = to_value(&record).unwrap();
+    assert_eq!(json, Json::from_str(&json::encode(&record).unwrap()).unwrap());
+
+    {
+        let obj = json.as_object_mut().unwrap();
+        obj.insert("id".to_string(), U64(2));
+        obj.get_mut("tags").unwrap().as_array_mut().unwrap().push(String("b".to_string()));
+        obj.insert("parent".to_string(), Null);
+    }
+    let patched: Record = from_value(json).unwrap();
+    assert_eq!(patched, Record {
+        id: 2,
+        tags: vec!["a".to_string(), "b".to_string()],
+        parent: None,
+    });
+
+    assert_eq!(to_value(&()).unwrap(), Null);
+    let mut bad = HashMap::new();
+    bad.insert(vec![1], 2);
+    assert_eq!(to_value(&bad), Err(EncoderError::BadHashmapKey));
+    assert_eq!(from_value::<u32>(Boolean(true)),
+               Err(ExpectedError("Number".to_string(), "true".to_string())));
+}
+
+#[test]
+fn test_write_omit_null() {
+    #[derive(RustcEncodable)]
+    struct Inner {
+        a: Option<u8>,
+        b: Option<u8>,
+    }
+
+    #[derive(RustcEncodable)]
+    struct Profile {
+        nick: Option<string::String>,
+        name: string::String,
+        age: Option<u32>,
+        tags: Vec<Option<u8>>,
+        inner: Inner,
+        extra: Option<Inner>,
+    }
+
+    fn encode_omitting_null<T: Encodable>(t: &T, pretty: bool) -> string::String {
+        let mut s = string::String::new();
+        {
+            let mut encoder = if pretty {
+                Encoder::new_pretty(&mut s)
+            } else {
+                Encoder::new(&mut s)
+            };
+            encoder.set_omit_null(true);
+            t.encode(&mut encoder).unwrap();
+        }
+        s
+    }
+
+    let profile = Profile {
+        nick: None,
+        name: "Ann".to_string(),
+        age: None,
+        tags: vec![None, Some(1)],
+        inner: Inner { a: None, b: None },
+        extra: Some(Inner { a: None, b: Some(2) }),
+    };
+    assert_eq!(encode_omitting_null(&profile, false),
+               "{\"name\":\"Ann\",\"tags\":[null,1],\"inner\":{},\"extra\":{\"b\":2}}");
+    assert_eq!(encode_omitting_null(&profile, true), "\
+{
+  \"name\": \"Ann\",
+  \"tags\": [
+    null,
+    1
+  ],
+  \"inner\": {},
+  \"extra\": {
+    \"b\": 2
+  }
+}");
+    assert_eq!(encode_omitting_null(&Inner { a: Some(1), b: None }, false),
+               "{\"a\":1}");
+
+    // off by default
+    assert_eq!(json::encode(&Inner { a: None, b: Some(2) }).unwrap(),
+               "{\"a\":null,\"b\":2}");
+}
+
+#[test]
+fn test_ndjson_enum() {
+    use json::ndjson::{encode_ndjson, decode_ndjson};
+
+    let animals = vec![Dog, Frog("Henry\nthe frog".to_string(), 349)];
+    let text = encode_ndjson(&animals).unwrap();
+    assert_eq!(text, "\"Dog\"\n{\"variant\":\"Frog\",\"fields\":[\"Henry\\nthe frog\",349]}\n");
+    assert_eq!(decode_ndjson::<Animal>(&text).unwrap(), animals);
+}
+
+#[test]
+fn test_read_write_lines_enum() {
+    use json::{read_lines, write_lines};
+
+    let mut out = Vec::new();
+    write_lines(&mut out, vec![Dog, Frog("Henry\nthe frog".to_string(), 349)]).unwrap();
+    assert_eq!(out, &b"\"Dog\"\n{\"variant\":\"Frog\",\"fields\":[\"Henry\\nthe frog\",349]}\n"[..]);
+    let lines = read_lines(&out[..]).collect::<Vec<_>>();
+    assert_eq!(lines, [Ok(String("Dog".to_string())),
+                       Ok(Json::from_str("{\"variant\":\"Frog\",\"fields\":[\"Henry\\nthe frog\",349]}")
+                              .unwrap())]);
+}
+
+#[derive(PartialEq, RustcEncodable, RustcDecodable, Debug)]
+struct Radius {
+    r: u32,
+}
+
+#[derive(PartialEq, RustcEncodable, RustcDecodable, Debug)]
+enum Shape {
+    Point,
+    Circle { r: u32, label: Option<string::String> },
+    Round(Radius),
+    Square(u32),
+    Rect(u32, u32),
+    Lines(Vec<u32>),
+}
+
+#[test]
+fn test_enum_tagging() {
+    use json::EnumTagging;
+    use self::Shape::*;
+
+    fn encode(shape: &Shape, tagging: EnumTagging) -> Result<string::String, EncoderError> {
+        let mut s = string::String::new();
+        {
+            let mut encoder = Encoder::new(&mut s);
+            encoder.set_enum_tagging(tagging);
+            shape.encode(&mut encoder)?;
+        }
+        Ok(s)
+    }
+    fn decode(s: &str, tagging: EnumTagging) -> DecodeResult<Shape> {
+        let mut decoder = Decoder::new(Json::from_str(s).unwrap());
+        decoder.set_enum_tagging(tagging);
+        Decodable::decode(&mut decoder)
+    }
+
+    let circle = Circle { r: 1, label: None };
+    let external = [
+        (Point, "\"Point\""),
+        (circle, "{\"Circle\":{\"r\":1,\"label\":null}}"),
+        (Round(Radius { r: 3 }), "{\"Round\":{\"r\":3}}"),
+        (Square(2), "{\"Square\":2}"),
+        (Rect(1, 2), "{\"Rect\":[1,2]}"),
+        (Lines(vec![1, 2]), "{\"Lines\":[1,2]}"),
+        (Lines(vec![]), "{\"Lines\":[]}"),
+    ];
+    for &(ref shape, json) in external.iter() {
+        assert_eq!(encode(shape, EnumTagging::External).unwrap(), json);
+        assert_eq!(decode(json, EnumTagging::External).unwrap(), *shape);
+    }
+    assert_eq!(decode("{\"Rect\":[1]}", EnumTagging::External),
+               Err(ExpectedError("at least 2 arguments".to_string(), "1".to_string())));
+    assert_eq!(decode("{\"Rect\":[1,2,3]}", EnumTagging::External),
+               Err(ExpectedError("2 arguments".to_string(), "3".to_string())));
+    assert_eq!(decode("{\"Square\":[2]}", EnumTagging::External),
+               Err(ExpectedError("Number".to_string(), "[2]".to_string())));
+    assert_eq!(decode("{\"Hexagon\":1}", EnumTagging::External),
+               Err(UnknownVariantError("Hexagon".to_string())));
+
+    let tag = || EnumTagging::Internal("type".to_string());
+    let internal = [
+        (Point, "{\"type\":\"Point\"}"),
+        (Circle { r: 1, label: None }, "{\"type\":\"Circle\",\"r\":1,\"label\":null}"),
+        (Round(Radius { r: 3 }), "{\"type\":\"Round\",\"r\":3}"),
+    ];
+    for &(ref shape, json) in internal.iter() {
+        assert_eq!(encode(shape, tag()).unwrap(), json);
+        assert_eq!(decode(json, tag()).unwrap(), *shape);
+    }
+    assert_eq!(decode("{\"r\":1}", tag()), Err(MissingFieldError("type".to_string())));
+    assert_eq!(encode(&Square(2), tag()), Err(EncoderError::BadTaggedVariant));
+    assert_eq!(encode(&Rect(1, 2), tag()), Err(EncoderError::BadTaggedVariant));
+
+    // the default stays as it was
+    let circle = Circle { r: 1, label: Some("c".to_string()) };
+    let json = "{\"variant\":\"Circle\",\"fields\":[1,\"c\"]}";
+    assert_eq!(encode(&circle, EnumTagging::VariantFields).unwrap(), json);
+    assert_eq!(decode(json, EnumTagging::VariantFields).unwrap(), circle);
+
+    let mut s = string::String::new();
+    {
+        let mut encoder = Encoder::new_pretty(&mut s);
+        encoder.set_enum_tagging(EnumTagging::External);
+        vec![Square(2), Rect(1, 2)].encode(&mut encoder).unwrap();
+    }
+    assert_eq!(s, "[\n  {\n    \"Square\": 2\n  },\n  {\n    \"Rect\": [\n      1,\n      2\n    ]\n  }\n]");
+}
+
+#[test]
+fn test_decode_str_streaming() {
+    use json::decode_str;
+
+    fn check<T: Decodable + PartialEq + ::std::fmt::Debug>(s: &str) {
+        assert_eq!(decode_str::<T>(s), json::decode::<T>(s));
+    }
+
+    check::<Outer>("{\"inner\": [{\"a\": null, \"b\": 2, \"c\": [\"abc\", \"xyz\"]}]}");
+    // fields out of order, unknown and missing ones
+    check::<Outer>("{\"x\": {\"y\": [1, {}]}, \"inner\": [{\"c\": [], \"z\": \"}\", \
+                     \"b\": 1, \"a\": null}, {\"b\": 3, \"c\": [\"[,\\\"\"], \"a\": null}]}");
+    check::<OptionalFields>("{}");
+    check::<OptionalFields>("{\"list\": [1, 2], \"num\": null}");
+    check::<OptionalFields>("{\"list\": null, \"num\": 7}");
+    check::<Vec<Vec<u8>>>("[[ ], [1], [2, 3]]");
+    check::<BTreeMap<string::String, Vec<u8>>>("{\"a\": [1, 2], \"b\": [], \"c\": [3]}");
+    check::<BTreeMap<u8, bool>>("{\"1\": true, \"2\": false}");
+    check::<Vec<Animal>>("[\"Dog\", {\"fields\": [\"Henry\", 349], \"variant\": \"Frog\"}]");
+    check::<(u8, string::String, Option<f64>)>("[1, \"a\", null]");
+    check::<Json>("{\"a\": [1, 2.5, null], \"b\": {\"c\": true}}");
+
+    check::<Outer>("{\"inner\": [{\"a\": null, \"b\": 2}]}");
+    check::<Outer>("{\"inner\": [{\"a\": null, \"b\": -2, \"c\": []}]}");
+    check::<Outer>("{\"inner\": {}}");
+    check::<(u8, u8)>("[1, 2, 3]");
+    check::<Vec<u8>>("[1, 2");
+    check::<Vec<u8>>("[1, 2] 3");
+    check::<Animal>("\"Cat\"");
+    check::<OptionalFields>("{\"num\": 1,}");
+    check::<OptionalFields>("");
+
+    // unlike `decode`, the first of repeated keys wins
+    let repeated: OptionalFields = decode_str("{\"num\": 7, \"num\": 8}").unwrap();
+    assert_eq!(repeated.num, Some(7));
+}
+
+#[test]
+fn test_null_strings() {
+    use json::{encode, decode, NullableString, NullString};
+
+    #[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+    struct Contact {
+        phone: Option<string::String>,
+        email: NullableString,
+        fax: NullString,
+    }
+
+    fn encode_omitting_null(c: &Contact) -> string::String {
+        let mut s = string::String::new();
+        {
+            let mut encoder = Encoder::new(&mut s);
+            encoder.set_omit_null(true);
+            c.encode(&mut encoder).unwrap();
+        }
+        s
+    }
+
+    let none = Contact {
+        phone: None,
+        email: NullableString(None),
+        fax: NullString(None),
+    };
+    assert_eq!(encode(&none).unwrap(),
+               "{\"phone\":null,\"email\":null,\"fax\":\"null\"}");
+    assert_eq!(encode_omitting_null(&none), "{\"fax\":\"null\"}");
+    assert_eq!(decode::<Contact>("{\"phone\":null,\"email\":null,\"fax\":\"null\"}")
+                   .unwrap(), none);
+    assert_eq!(decode::<Contact>("{\"fax\":\"null\"}").unwrap(), none);
+    assert_eq!(decode::<Contact>("{\"fax\":null}").unwrap(), none);
+    assert_eq!(decode::<Contact>("{}").unwrap(), none);
+
+    let some = Contact {
+        phone: Some("555".to_string()),
+        email: NullableString(Some("a@b.c".to_string())),
+        fax: NullString(Some("556".to_string())),
+    };
+    let s = "{\"phone\":\"555\",\"email\":\"a@b.c\",\"fax\":\"556\"}";
+    assert_eq!(encode(&some).unwrap(), s);
+    assert_eq!(encode_omitting_null(&some), s);
+    assert_eq!(decode::<Contact>(s).unwrap(), some);
+
+    assert!(NullableString(None).is_none());
+    assert!(!NullableString::from(Some("x".to_string())).is_none());
+    assert!(decode::<NullString>("1").is_err());
+}
+
+#[test]
+fn test_json_number() {
+    use json::{decode_str, JsonNumber, ToJson};
+
+    #[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+    struct Price {
+        amount: JsonNumber,
+        currency: string::String,
+    }
+
+    let s = "{\"amount\":1.000000000000000001,\"currency\":\"EUR\"}";
+    let price: Price = decode_str(s).unwrap();
+    assert_eq!(price.amount.as_str(), "1.000000000000000001");
+    assert_eq!(json::encode(&price).unwrap(), s);
+    let price: Price = decode_str("{\"currency\": \"EUR\", \"amount\": -2.50e+3}").unwrap();
+    assert_eq!(price.amount.as_str(), "-2.50e+3");
+    assert_eq!(price.amount.as_f64(), Some(-2500.0));
+    assert_eq!(price.amount.as_i64(), None);
+
+    // through `Json`, decimals are f64 already, and aren't handed out rounded
+    assert!(json::decode::<Price>(s).is_err());
+    assert!(json::decode::<JsonNumber>("1.5").is_err());
+    let n: JsonNumber = json::decode("-12").unwrap();
+    assert_eq!((n.as_i64(), n.as_u64(), n.as_f64()), (Some(-12), None, Some(-12.0)));
+    let n: JsonNumber = json::decode("\"18446744073709551615\"").unwrap();
+    assert_eq!(n.as_u64(), Some(u64::MAX));
+    assert!(json::decode::<JsonNumber>("\"12a\"").is_err());
+    assert!(decode_str::<JsonNumber>("true").is_err());
+    assert!(decode_str::<Vec<JsonNumber>>("[1, 2").is_err());
+
+    for &s in ["0", "-0", "12", "1.5", "1e5", "1E-5", "-0.0e+10",
+               "1234567890123456789012345678901234567890"].iter() {
+        assert_eq!(s.parse::<JsonNumber>().unwrap().as_str(), s);
+    }
+    for &s in ["", "-", "01", "1.", ".5", "1e", "1e+", "+1", " 1", "1 ", "0x1", "NaN"].iter() {
+        assert!(s.parse::<JsonNumber>().is_err(), "{}", s);
+    }
+    let big: JsonNumber = "1234567890123456789012345678901234567890".parse().unwrap();
+    assert_eq!(big.as_f64(), Some(1234567890123456789012345678901234567890.0));
+    assert_eq!(big.to_json(), String("1234567890123456789012345678901234567890".to_string()));
+    assert_eq!(JsonNumber::from(7u64).to_json(), U64(7));
+    assert_eq!(json::encode(&JsonNumber::from(-3i64)).unwrap(), "-3");
+
+    let mut map = BTreeMap::new();
+    map.insert("1.50".parse::<JsonNumber>().unwrap(), vec![big.clone()]);
+    let encoded = json::encode(&map).unwrap();
+    assert_eq!(encoded, "{\"1.50\":[1234567890123456789012345678901234567890]}");
+    assert_eq!(json::decode_str::<BTreeMap<JsonNumber, Vec<u8>>>("{\"1.50\": [1]}")
+                   .unwrap().keys().next().unwrap().as_str(),
+               "1.50");
+}
+
+#[test]
+fn test_decode_struct_renamed_fields() {
+    use json::RenamingDecoder;
+
+    #[derive(RustcDecodable, PartialEq, Debug)]
+    struct User {
+        first_name: string::String,
+        user_id: u32,
+        nick: Option<string::String>,
+    }
+
+    fn camel_case(name: &str) -> string::String {
+        let mut parts = name.split('_');
+        let mut key = parts.next().unwrap_or("").to_string();
+        for part in parts {
+            let mut chars = part.chars();
+            if let Some(c) = chars.next() {
+                key.extend(c.to_uppercase());
+                key.push_str(chars.as_str());
+            }
+        }
+        key
+    }
+
+    let json = Json::from_str("[{\"firstName\": \"Ann\", \"userId\": 7}]").unwrap();
+    let mut decoder = RenamingDecoder::new(Decoder::new(json), camel_case);
+    let users: Vec<User> = Decodable::decode(&mut decoder).unwrap();
+    assert_eq!(users, vec![User {
+        first_name: "Ann".to_string(),
+        user_id: 7,
+        nick: None,
+    }]);
+
+    let json = Json::from_str("{\"first_name\": \"Ann\"}").unwrap();
+    let mut decoder = RenamingDecoder::new(Decoder::new(json), camel_case);
+    let res: DecodeResult<User> = Decodable::decode(&mut decoder);
+    assert_eq!(res, Err(MissingFieldError("firstName".to_string())));
+}
+
+#[test]
+fn test_from_str_with_config_enum_tagging() {
+    use json::{from_str_with_config, DecodeConfig, EnumTagging};
+
+    let config = DecodeConfig::new().enum_tagging(EnumTagging::External);
+    assert_eq!(from_str_with_config::<Animal>("{\"Frog\":[\"Henry\",349]}", &config),
+               Ok(Frog("Henry".to_string(), 349)));
+}
+
+#[derive(RustcDecodable, PartialEq, Debug)]
+struct Envelope {
+    kind: string::String,
+    payload: Json,
+}
+
+#[test]
+fn test_from_json() {
+    let mut json = Json::from_str("{\"kind\": \"point\", \"payload\": 1}").unwrap();
+    let envelope: Envelope = json::from_json(json.clone()).unwrap();
+    assert_eq!(envelope.payload, U64(1));
+
+    if let Object(ref mut obj) = json {
+        obj.insert("kind".to_string(), String("line".to_string()));
+    }
+    let envelope: Envelope = json::from_json(json).unwrap();
+    assert_eq!(envelope.kind, "line");
+
+    assert_eq!(json::from_json::<u8>(String("x".to_string())),
+               Err(ExpectedError("Number".to_string(), "x".to_string())));
+}
+
+#[test]
+fn test_decode_json() {
+    let s = "{\"a\": [1, -2, 3.5, true, null, \"x\"], \"b\": {}}";
+    assert_eq!(json::decode::<Json>(s).unwrap(), Json::from_str(s).unwrap());
+    assert_eq!(json::decode_str::<Json>(s).unwrap(), Json::from_str(s).unwrap());
+    assert_eq!(json::decode::<Json>("null").unwrap(), Null);
+    // values come back in the variants they are in
+    assert_eq!(json::from_json::<Json>(I64(2)).unwrap(), I64(2));
+    assert_eq!(json::from_json::<Json>(String("2".to_string())).unwrap(),
+               String("2".to_string()));
+    assert_eq!(json::decode::<Json>("[1,"),
+               Err(ParseError(SyntaxError(EOFWhileParsingValue, 1, 4))));
+
+    // the dynamic parts of a typed value
+    let envelope: Envelope =
+        json::decode("{\"kind\": \"point\", \"payload\": {\"x\": 1}}").unwrap();
+    assert_eq!(envelope.kind, "point");
+    assert_eq!(envelope.payload, Json::from_str("{\"x\": 1}").unwrap());
+    let values: Vec<Json> = json::decode("[1, \"a\", [2]]").unwrap();
+    assert_eq!(values, vec![U64(1), String("a".to_string()), Array(vec![U64(2)])]);
+
+    // through the renaming decoder too
+    use json::RenamingDecoder;
+    let json = Json::from_str("{\"KIND\": \"point\", \"PAYLOAD\": [1]}").unwrap();
+    let mut decoder = RenamingDecoder::new(Decoder::new(json), |name| name.to_uppercase());
+    let envelope: Envelope = Decodable::decode(&mut decoder).unwrap();
+    assert_eq!(envelope.payload, Array(vec![U64(1)]));
+}
+
+#[derive(RustcEncodable, RustcDecodable, PartialEq, Debug)]
+struct Counter {
+    hits: Cell<u32>,
+    names: RefCell<Vec<string::String>>,
+}
+
+#[test]
+fn test_cells() {
+    let counter = Counter { hits: Cell::new(1), names: RefCell::new(vec![]) };
+    counter.hits.set(2);
+    counter.names.borrow_mut().push("a".to_string());
+    let s = json::encode(&counter).unwrap();
+    assert_eq!(s, "{\"hits\":2,\"names\":[\"a\"]}");
+    assert_eq!(json::decode::<Counter>(&s).unwrap(), counter);
+
+    // shared borrows don't get in the way
+    let _names = counter.names.borrow();
+    assert_eq!(json::encode(&counter).unwrap(), s);
+}
+
+#[test]
+fn test_to_string_sorted() {
+    use std::collections::HashMap;
+    use json::to_string_sorted;
+
+    #[derive(RustcEncodable)]
+    struct Unsorted {
+        zeta: HashMap<string::String, u32>,
+        alpha: Vec<Animal>,
+        mid: Option<f64>,
+    }
+
+    let mut a = HashMap::new();
+    let mut b = HashMap::new();
+    for i in 0..50 {
+        a.insert(format!("key{}", i), i);
+        b.insert(format!("key{}", 49 - i), 49 - i);
+    }
+    assert_eq!(to_string_sorted(&a).unwrap(), to_string_sorted(&b).unwrap());
+
+    let mut zeta = HashMap::new();
+    zeta.insert("b".to_string(), 2);
+    zeta.insert("a".to_string(), 1);
+    let value = Unsorted {
+        zeta: zeta,
+        alpha: vec![Dog, Frog("Henry".to_string(), 349)],
+        mid: Some(0.5),
+    };
+    assert_eq!(to_string_sorted(&value).unwrap(),
+               "{\"alpha\":[\"Dog\",{\"fields\":[\"Henry\",349],\"variant\":\"Frog\"}],\
+                \"mid\":0.5,\"zeta\":{\"a\":1,\"b\":2}}");
+
+    let mut numeric_keys = HashMap::new();
+    numeric_keys.insert(10, "ten");
+    numeric_keys.insert(2, "two");
+    assert_eq!(to_string_sorted(&numeric_keys).unwrap(), "{\"10\":\"ten\",\"2\":\"two\"}");
+
+    let mut bad_keys = HashMap::new();
+    bad_keys.insert(true, 1);
+    assert_eq!(to_string_sorted(&bad_keys), Err(EncoderError::BadHashmapKey));
+}
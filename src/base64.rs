@@ -15,8 +15,18 @@
 pub use self::FromBase64Error::*;
 pub use self::CharacterSet::*;
 
-use std::fmt;
-use std::error;
+#[cfg(any(feature = "std", test))] use std::fmt;
+#[cfg(not(any(feature = "std", test)))] use core::fmt;
+#[cfg(feature = "std")] use std::error;
+#[cfg(feature = "std")] use std::io::{self, Read, Write};
+#[cfg(feature = "std")] use std::ops::Deref;
+#[cfg(all(feature = "alloc", not(feature = "std")))] use alloc::string::String;
+#[cfg(all(feature = "alloc", not(feature = "std")))] use alloc::vec::Vec;
+#[cfg(all(any(feature = "std", feature = "alloc"), any(feature = "std", test)))]
+use std::slice;
+#[cfg(all(feature = "alloc", not(any(feature = "std", test))))] use core::slice;
+
+#[cfg(feature = "std")] use {Decodable, Decoder, Encodable, Encoder};
 
 /// Available encoding character sets
 #[derive(Clone, Copy, Debug)]
@@ -27,16 +37,40 @@ pub enum CharacterSet {
     UrlSafe
 }
 
+impl fmt::Display for CharacterSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
 /// Available newline types
 #[derive(Clone, Copy, Debug)]
 pub enum Newline {
     /// A linefeed (i.e. Unix-style newline)
     LF,
     /// A carriage return and a linefeed (i.e. Windows-style newline)
-    CRLF
+    CRLF,
+    /// Any other separator, such as `"\r"`. Decoding only skips carriage
+    /// returns and linefeeds, so other separators have to be removed first.
+    Custom(&'static str),
+}
+
+impl Newline {
+    /// Returns the separator written between lines.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Newline::LF => "\n",
+            Newline::CRLF => "\r\n",
+            Newline::Custom(separator) => separator,
+        }
+    }
 }
 
 /// Contains configuration parameters for `to_base64`.
+///
+/// Build one from a preset, as in `Config { line_length: Some(64), ..STANDARD }`,
+/// rather than naming every field: literals naming them all broke when
+/// `final_newline` was added in 0.4.
 #[derive(Clone, Copy, Debug)]
 pub struct Config {
     /// Character set to use
@@ -45,37 +79,117 @@ pub struct Config {
     pub newline: Newline,
     /// True to pad output with `=` characters
     pub pad: bool,
-    /// `Some(len)` to wrap lines at `len`, `None` to disable line wrapping
-    pub line_length: Option<usize>
+    /// `Some(len)` to wrap lines at `len`, `None` to disable line wrapping.
+    /// Lines are separated by `newline`, which is never written after the
+    /// last line unless `final_newline` asks for it.
+    pub line_length: Option<usize>,
+    /// True to end the output with `newline`, as formats like PEM want, unless
+    /// there is no output at all
+    pub final_newline: bool,
+}
+
+impl fmt::Display for Config {
+    /// Describes the configuration, such as `Standard, padded, CRLF line
+    /// breaks every 76 characters` for `MIME`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}, {}", self.char_set, if self.pad { "padded" } else { "unpadded" }));
+        try!(match self.line_length {
+            Some(len) => write!(f, ", {:?} line breaks every {} characters", self.newline, len),
+            None => write!(f, ", no line breaks"),
+        });
+        if self.final_newline {
+            try!(write!(f, ", final {:?} line break", self.newline));
+        }
+        Ok(())
+    }
 }
 
 /// Configuration for RFC 4648 standard base64 encoding
 pub static STANDARD: Config =
-    Config {char_set: Standard, newline: Newline::CRLF, pad: true, line_length: None};
+    Config {char_set: Standard, newline: Newline::CRLF, pad: true, line_length: None,
+           final_newline: false};
+
+// Shared by `URL_SAFE` and its alias, as a static can't be initialized
+// with another static's value.
+const URL_SAFE_CONFIG: Config =
+    Config {char_set: UrlSafe, newline: Newline::CRLF, pad: false, line_length: None,
+           final_newline: false};
 
 /// Configuration for RFC 4648 base64url encoding
-pub static URL_SAFE: Config =
-    Config {char_set: UrlSafe, newline: Newline::CRLF, pad: false, line_length: None};
+pub static URL_SAFE: Config = URL_SAFE_CONFIG;
 
 /// Configuration for RFC 2045 MIME base64 encoding
 pub static MIME: Config =
-    Config {char_set: Standard, newline: Newline::CRLF, pad: true, line_length: Some(76)};
+    Config {char_set: Standard, newline: Newline::CRLF, pad: true, line_length: Some(76),
+           final_newline: false};
+
+/// Configuration for RFC 4648 base64url encoding without padding, spelled
+/// out. This is an alias of `URL_SAFE`, which is already unpadded.
+pub static URL_SAFE_NO_PAD: Config = URL_SAFE_CONFIG;
 
-static STANDARD_CHARS: &'static[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
-                                        abcdefghijklmnopqrstuvwxyz\
-                                        0123456789+/";
+/// Configuration for RFC 7468 PEM base64 encoding, wrapping lines at 64
+/// characters with linefeeds and ending the last line with one, so that the
+/// `-----END` line can follow directly
+pub static PEM: Config =
+    Config {char_set: Standard, newline: Newline::LF, pad: true, line_length: Some(64),
+           final_newline: true};
 
-static URLSAFE_CHARS: &'static[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
-                                       abcdefghijklmnopqrstuvwxyz\
-                                       0123456789-_";
+/// The characters of the `Standard` character set, indexed by the 6-bit
+/// values they encode.
+pub const STANDARD_CHARS: &'static [u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
+                                                abcdefghijklmnopqrstuvwxyz\
+                                                0123456789+/";
+
+/// The characters of the `UrlSafe` character set, indexed by the 6-bit
+/// values they encode.
+pub const URLSAFE_CHARS: &'static [u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
+                                               abcdefghijklmnopqrstuvwxyz\
+                                               0123456789-_";
+
+/// Returns the character encoding the 6-bit value `val` in `set`.
+///
+/// This isn't a `const fn`, as this crate still builds with Rust 1.0; the
+/// tables behind it, `STANDARD_CHARS`, `URLSAFE_CHARS` and `DECODE_TABLE`,
+/// are constants though.
+///
+/// # Panics
+///
+/// Panics if `val` doesn't fit in 6 bits.
+pub fn encode_char(val: u8, set: CharacterSet) -> u8 {
+    match set {
+        Standard => STANDARD_CHARS[val as usize],
+        UrlSafe => URLSAFE_CHARS[val as usize],
+    }
+}
 
 /// A trait for converting a value to base64 encoding.
+#[cfg(any(feature = "std", feature = "alloc"))]
 pub trait ToBase64 {
     /// Converts the value of `self` to a base64 value following the specified
     /// format configuration, returning the owned string.
     fn to_base64(&self, config: Config) -> String;
+
+    /// Converts the value of `self` to a base64 value following the specified
+    /// format configuration, appending it to `out`.
+    ///
+    /// The default implementation appends the result of `to_base64`;
+    /// implementors should override it to avoid the intermediate allocation.
+    fn encode_to(&self, config: Config, out: &mut String) {
+        out.push_str(&self.to_base64(config));
+    }
+
+    /// Converts the value of `self` to a base64 value following the specified
+    /// format configuration, replacing the contents of `out`.
+    ///
+    /// `out` keeps its capacity, so a string reused across many conversions
+    /// only reallocates when it has to grow.
+    fn to_base64_reuse(&self, config: Config, out: &mut String) {
+        out.clear();
+        self.encode_to(config, out)
+    }
 }
 
+#[cfg(any(feature = "std", feature = "alloc"))]
 impl ToBase64 for [u8] {
     /// Turn a vector of `u8` bytes into a base64 string.
     ///
@@ -91,117 +205,579 @@ impl ToBase64 for [u8] {
     /// }
     /// ```
     fn to_base64(&self, config: Config) -> String {
-        let bytes = match config.char_set {
-            Standard => STANDARD_CHARS,
-            UrlSafe => URLSAFE_CHARS
-        };
-
-        let len = self.len();
-        let newline = match config.newline {
-            Newline::LF => "\n",
-            Newline::CRLF => "\r\n",
-        };
+        let mut out = String::with_capacity(encoded_len(self.len(), config));
+        self.encode_to(config, &mut out);
+        out
+    }
 
-        // Preallocate memory.
-        let mut prealloc_len = (len + 2) / 3 * 4;
-        if let Some(line_length) = config.line_length {
-            let num_lines = match prealloc_len {
-                0 => 0,
-                n => (n - 1) / line_length
-            };
-            prealloc_len += num_lines * newline.bytes().count();
+    /// Append the base64 encoding of a vector of `u8` bytes to a string.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// use rustc_serialize::base64::{ToBase64, STANDARD};
+    ///
+    /// fn main () {
+    ///     let mut out = "data:;base64,".to_string();
+    ///     [52,32].encode_to(STANDARD, &mut out);
+    ///     println!("output: {}", out);
+    /// }
+    /// ```
+    fn encode_to(&self, config: Config, out: &mut String) {
+        // Only ASCII bytes and whole newline strings are ever written, so
+        // `out` stays valid UTF-8.
+        let out_bytes = unsafe { out.as_mut_vec() };
+        out_bytes.reserve(encoded_len(self.len(), config));
+        GroupEncoder::new(config).encode(self, |b| out_bytes.push(b));
+        if config.final_newline && !self.is_empty() {
+            out_bytes.extend(config.newline.as_str().bytes());
         }
+    }
+}
 
-        let mut out_bytes = vec![b'='; prealloc_len];
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl ToBase64 for str {
+    /// Encodes the UTF-8 bytes of the string.
+    fn to_base64(&self, config: Config) -> String {
+        self.as_bytes().to_base64(config)
+    }
 
-        // Deal with padding bytes
-        let mod_len = len % 3;
+    fn encode_to(&self, config: Config, out: &mut String) {
+        self.as_bytes().encode_to(config, out)
+    }
+}
 
-        // Use iterators to reduce branching
-        {
-            let mut cur_length = 0;
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl ToBase64 for String {
+    fn to_base64(&self, config: Config) -> String {
+        self.as_bytes().to_base64(config)
+    }
 
-            let mut s_in = self[..len - mod_len].iter().map(|&x| x as u32);
-            let mut s_out = out_bytes.iter_mut();
+    fn encode_to(&self, config: Config, out: &mut String) {
+        self.as_bytes().encode_to(config, out)
+    }
+}
 
-            // Convenient shorthand
-            let enc = |val| bytes[val as usize];
-            let mut write = |val| *s_out.next().unwrap() = val;
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<'a, T: ?Sized + ToBase64> ToBase64 for &'a T {
+    fn to_base64(&self, config: Config) -> String {
+        (**self).to_base64(config)
+    }
 
-            // Iterate though blocks of 4
-            while let (Some(first), Some(second), Some(third)) =
-                        (s_in.next(), s_in.next(), s_in.next()) {
+    fn encode_to(&self, config: Config, out: &mut String) {
+        (**self).encode_to(config, out)
+    }
+}
 
-                // Line break if needed
-                if let Some(line_length) = config.line_length {
-                    if cur_length >= line_length {
-                        for b in newline.bytes() { write(b) };
-                        cur_length = 0;
-                    }
-                }
+/// Encodes `input` as base64 with the `STANDARD` configuration.
+///
+/// This is `input.to_base64(STANDARD)`, without importing `ToBase64`.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::base64;
+///
+/// fn main () {
+///     assert_eq!(base64::encode(b"Hello"), "SGVsbG8=");
+/// }
+/// ```
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub fn encode(input: &[u8]) -> String {
+    input.to_base64(STANDARD)
+}
+
+/// Encodes `input` as base64 following the specified format configuration.
+///
+/// This is `input.to_base64(config)`, without importing `ToBase64`.
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub fn encode_config(input: &[u8], config: Config) -> String {
+    input.to_base64(config)
+}
 
-                let n = first << 16 | second << 8 | third;
+/// Encodes data to base64 a chunk at a time, for protocols sending it in
+/// frames of bounded size.
+///
+/// Each chunk is encoded on its own, padding included. With a `chunk_size`
+/// which is a multiple of 3 and no line wrapping, only the last chunk can be
+/// padded, so the chunks put together are the encoding of the whole data.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::base64::{ChunkedEncoder, STANDARD};
+///
+/// fn main () {
+///     let encoder = ChunkedEncoder::new(STANDARD, 3);
+///     let chunks: Vec<String> = encoder.chunks(b"foobar!").collect();
+///     assert_eq!(chunks, ["Zm9v", "YmFy", "IQ=="]);
+/// }
+/// ```
+#[cfg(any(feature = "std", feature = "alloc"))]
+#[derive(Clone, Copy, Debug)]
+pub struct ChunkedEncoder {
+    config: Config,
+    chunk_size: usize,
+}
 
-                // This 24-bit number gets separated into four 6-bit numbers.
-                write(enc((n >> 18) & 63));
-                write(enc((n >> 12) & 63));
-                write(enc((n >> 6 ) & 63));
-                write(enc((n >> 0 ) & 63));
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl ChunkedEncoder {
+    /// Creates an encoder following `config`, taking `chunk_size` bytes of
+    /// input for each chunk.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub fn new(config: Config, chunk_size: usize) -> ChunkedEncoder {
+        assert!(chunk_size != 0, "chunk size must not be 0");
+        ChunkedEncoder { config: config, chunk_size: chunk_size }
+    }
 
-                cur_length += 4;
-            }
+    /// Returns an iterator over the encoded chunks of `data`, the last of
+    /// which may be shorter.
+    pub fn chunks<'a>(&self, data: &'a [u8]) -> ChunkIter<'a> {
+        ChunkIter { config: self.config, chunks: data.chunks(self.chunk_size) }
+    }
+}
 
-            // Line break only needed if padding is required
-            if mod_len != 0 {
-                if let Some(line_length) = config.line_length {
-                    if cur_length >= line_length {
-                        for b in newline.bytes() { write(b) };
-                    }
+/// An iterator over the base64 encoded chunks of some data, created by
+/// `ChunkedEncoder::chunks`.
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub struct ChunkIter<'a> {
+    config: Config,
+    chunks: slice::Chunks<'a, u8>,
+}
+
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<'a> Iterator for ChunkIter<'a> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.chunks.next().map(|chunk| chunk.to_base64(self.config))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chunks.size_hint()
+    }
+}
+
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<'a> ExactSizeIterator for ChunkIter<'a> {}
+
+// Encodes groups of up to three bytes one after the other, breaking lines as
+// `config` asks. Encoding to a string, to a slice and a piece at a time all
+// go through it.
+struct GroupEncoder {
+    config: Config,
+    // Characters written since the last line break.
+    cur_length: usize,
+}
+
+impl GroupEncoder {
+    fn new(config: Config) -> GroupEncoder {
+        GroupEncoder { config: config, cur_length: 0 }
+    }
+
+    // Encodes all of `input`, handing each character to `write`.
+    fn encode<F: FnMut(u8)>(&mut self, input: &[u8], mut write: F) {
+        for group in input.chunks(3) {
+            self.group(group, &mut write);
+        }
+    }
+
+    // Encodes a group of one to three bytes, padding it if it is short.
+    fn group<F: FnMut(u8)>(&mut self, group: &[u8], mut write: F) {
+        if let Some(line_length) = self.config.line_length {
+            if self.cur_length >= line_length {
+                for b in self.config.newline.as_str().bytes() {
+                    write(b);
                 }
+                self.cur_length = 0;
             }
+        }
 
-            // Heh, would be cool if we knew this was exhaustive
-            // (the dream of bounded integer types)
-            match mod_len {
-                0 => (),
-                1 => {
-                    let n = (self[len-1] as u32) << 16;
-                    write(enc((n >> 18) & 63));
-                    write(enc((n >> 12) & 63));
-                }
-                2 => {
-                    let n = (self[len-2] as u32) << 16 |
-                            (self[len-1] as u32) << 8;
-                    write(enc((n >> 18) & 63));
-                    write(enc((n >> 12) & 63));
-                    write(enc((n >> 6 ) & 63));
-                }
-                _ => panic!("Algebra is broken, please alert the math police")
+        let chars = match self.config.char_set {
+            Standard => STANDARD_CHARS,
+            UrlSafe => URLSAFE_CHARS
+        };
+        // The group as a 24-bit number, separated into four 6-bit numbers.
+        let mut n = 0u32;
+        for (i, &b) in group.iter().enumerate() {
+            n |= (b as u32) << (16 - 8 * i);
+        }
+        for i in 0..4 {
+            if i <= group.len() {
+                write(chars[((n >> (18 - 6 * i)) & 63) as usize]);
+            } else if self.config.pad {
+                write(b'=');
+            }
+        }
+        self.cur_length += 4;
+    }
+}
+
+// Encodes data handed over a piece at a time, giving the same output as
+// encoding all of it at once with `to_base64`.
+#[cfg(feature = "std")]
+struct StreamEncoder {
+    encoder: GroupEncoder,
+    // Bytes waiting for the rest of their group of three.
+    partial: Vec<u8>,
+    empty: bool,
+}
+
+#[cfg(feature = "std")]
+impl StreamEncoder {
+    fn new(config: Config) -> StreamEncoder {
+        StreamEncoder { encoder: GroupEncoder::new(config),
+                        partial: Vec::with_capacity(3), empty: true }
+    }
+
+    // Encodes every whole group of `input`, keeping what is left over for
+    // the next call.
+    fn push(&mut self, mut input: &[u8], out: &mut Vec<u8>) {
+        if input.is_empty() {
+            return
+        }
+        self.empty = false;
+
+        if !self.partial.is_empty() {
+            let n = ::std::cmp::min(3 - self.partial.len(), input.len());
+            self.partial.extend(input[..n].iter().cloned());
+            input = &input[n..];
+            if self.partial.len() < 3 {
+                return
             }
+            let group = [self.partial[0], self.partial[1], self.partial[2]];
+            self.partial.clear();
+            self.encoder.group(&group, |b| out.push(b));
+        }
+
+        let whole = input.len() - input.len() % 3;
+        self.encoder.encode(&input[..whole], |b| out.push(b));
+        self.partial.extend(input[whole..].iter().cloned());
+    }
+
+    // Encodes the last, possibly short, group and the final line break.
+    fn finish(&mut self, out: &mut Vec<u8>) {
+        if !self.partial.is_empty() {
+            self.encoder.group(&self.partial, |b| out.push(b));
+            self.partial.clear();
+        }
+        let config = self.encoder.config;
+        if config.final_newline && !self.empty {
+            out.extend(config.newline.as_str().bytes());
+        }
+        self.empty = true;
+    }
+}
+
+/// A reader encoding the bytes of another reader to base64.
+///
+/// Reading it to the end gives the same output as `to_base64` on everything
+/// the inner reader returns.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::base64::{Base64Read, STANDARD};
+/// use std::io::Read;
+///
+/// fn main () {
+///     let mut encoded = String::new();
+///     Base64Read::new(&b"foobar!"[..], STANDARD)
+///         .read_to_string(&mut encoded).unwrap();
+///     assert_eq!(encoded, "Zm9vYmFyIQ==");
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub struct Base64Read<R> {
+    reader: R,
+    encoder: StreamEncoder,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Base64Read<R> {
+    /// Creates a reader encoding the bytes of `reader` following `config`.
+    pub fn new(reader: R, config: Config) -> Base64Read<R> {
+        Base64Read {
+            reader: reader,
+            encoder: StreamEncoder::new(config),
+            input: vec![0; 3 * 1024],
+            output: Vec::new(),
+            pos: 0,
+            done: false,
         }
+    }
+
+    /// Unwraps this reader, returning the inner reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
 
-        // We get padding for "free", so only have to drop it if unwanted.
-        if !config.pad {
-            while let Some(&b'=') = out_bytes.last() {
-                out_bytes.pop();
+#[cfg(feature = "std")]
+impl<R: Read> Read for Base64Read<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.output.len() {
+            if self.done || buf.is_empty() {
+                return Ok(0)
+            }
+            self.output.clear();
+            self.pos = 0;
+            let n = try!(self.reader.read(&mut self.input));
+            if n == 0 {
+                self.encoder.finish(&mut self.output);
+                self.done = true;
+            } else {
+                self.encoder.push(&self.input[..n], &mut self.output);
             }
         }
 
-        unsafe { String::from_utf8_unchecked(out_bytes) }
+        let n = ::std::cmp::min(buf.len(), self.output.len() - self.pos);
+        for (b, &c) in buf.iter_mut().zip(&self.output[self.pos..self.pos + n]) {
+            *b = c;
+        }
+        self.pos += n;
+        Ok(n)
     }
 }
 
-impl<'a, T: ?Sized + ToBase64> ToBase64 for &'a T {
-    fn to_base64(&self, config: Config) -> String {
-        (**self).to_base64(config)
+/// A writer encoding the bytes written to it to base64, and writing that to
+/// another writer.
+///
+/// Up to two bytes of input are held back until the rest of their group of
+/// three comes along, so `finish` has to be called after the last write to
+/// get the end of the output. The whole output is the same as `to_base64`
+/// on everything written.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::base64::{Base64Write, STANDARD};
+/// use std::io::Write;
+///
+/// fn main () {
+///     let mut writer = Base64Write::new(Vec::new(), STANDARD);
+///     writer.write_all(b"foo").unwrap();
+///     writer.write_all(b"bar!").unwrap();
+///     let encoded = writer.finish().unwrap();
+///     assert_eq!(encoded, b"Zm9vYmFyIQ==");
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub struct Base64Write<W: Write> {
+    writer: W,
+    encoder: StreamEncoder,
+    output: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Base64Write<W> {
+    /// Creates a writer encoding to `writer` following `config`.
+    pub fn new(writer: W, config: Config) -> Base64Write<W> {
+        Base64Write {
+            writer: writer,
+            encoder: StreamEncoder::new(config),
+            output: Vec::new(),
+        }
+    }
+
+    /// Writes out the held back bytes with any padding and final line
+    /// break, then flushes and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.output.clear();
+        self.encoder.finish(&mut self.output);
+        try!(self.writer.write_all(&self.output));
+        try!(self.writer.flush());
+        Ok(self.writer)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Write for Base64Write<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.clear();
+        self.encoder.push(buf, &mut self.output);
+        try!(self.writer.write_all(&self.output));
+        Ok(buf.len())
+    }
+
+    /// Flushes the inner writer. Bytes held back for a whole group of three
+    /// are not written until more input or `finish`.
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Returns the length of the base64 encoding of `len` bytes following the
+/// specified format configuration, including any padding.
+///
+/// This is the size of the buffer needed by `encode_to_slice`.
+pub fn encoded_len(len: usize, config: Config) -> usize {
+    let mut encoded_len = encoded_body_len(len, config);
+    if config.final_newline && len != 0 {
+        encoded_len += config.newline.as_str().len();
+    }
+    encoded_len
+}
+
+// Returns the length of the encoding, padding included, leaving out any final
+// newline.
+fn encoded_body_len(len: usize, config: Config) -> usize {
+    let newline = config.newline.as_str();
+
+    let mut encoded_len = (len + 2) / 3 * 4;
+    if let Some(line_length) = config.line_length {
+        let num_lines = match encoded_len {
+            0 => 0,
+            n => (n - 1) / line_length
+        };
+        encoded_len += num_lines * newline.len();
     }
+    encoded_len
+}
+
+/// Encodes `input` to base64 following the specified format configuration,
+/// writing the output to the start of `output` instead of allocating a new
+/// string.
+///
+/// Returns the number of bytes written, which is the same output
+/// `to_base64` would produce, or `None` if `output` is shorter than
+/// `encoded_len(input.len(), config)`.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::base64::{encode_to_slice, STANDARD};
+/// use std::str;
+///
+/// fn main () {
+///     let nonce = [7u8; 16];
+///     let mut buf = [0u8; 24];
+///     let n = encode_to_slice(&nonce, STANDARD, &mut buf).unwrap();
+///     println!("base 64 output: {}", str::from_utf8(&buf[..n]).unwrap());
+/// }
+/// ```
+pub fn encode_to_slice(input: &[u8], config: Config, output: &mut [u8]) -> Option<usize> {
+    let len = encoded_len(input.len(), config);
+    if output.len() < len {
+        return None;
+    }
+
+    let mut written = 0;
+    {
+        let mut write = |b| {
+            output[written] = b;
+            written += 1;
+        };
+        GroupEncoder::new(config).encode(input, &mut write);
+        if config.final_newline && !input.is_empty() {
+            for b in config.newline.as_str().bytes() {
+                write(b);
+            }
+        }
+    }
+    Some(written)
 }
 
 /// A trait for converting from base64 encoded values.
+#[cfg(any(feature = "std", feature = "alloc"))]
 pub trait FromBase64 {
     /// Converts the value of `self`, interpreted as base64 encoded data, into
     /// an owned vector of bytes, returning the vector.
     fn from_base64(&self) -> Result<Vec<u8>, FromBase64Error>;
+
+    /// Converts the value of `self`, interpreted as base64 encoded data in
+    /// the character set of `config` only, into an owned vector of bytes.
+    ///
+    /// `from_base64` accepts the characters of both character sets, even
+    /// mixed up. Here those particular to the other set are reported as
+    /// `InvalidBase64Byte`. `from_base64` also takes any number of `=`
+    /// characters as padding, while here padding may be left out but has to
+    /// be complete when present, or fails with `InvalidBase64Padding`. Line
+    /// breaks are skipped whatever `config` says, as with `from_base64`.
+    fn from_base64_with(&self, config: Config) -> Result<Vec<u8>, FromBase64Error>
+        where Self: AsRef<[u8]>
+    {
+        let input = self.as_ref();
+        let mut r = Vec::with_capacity(decoded_len_max(input.len()));
+        try!(decode_with(input, Some(config), |b| r.push(b)));
+        Ok(r)
+    }
+
+    /// Converts the value of `self` as `from_base64` does, adding `offset`
+    /// to the position reported by `InvalidBase64Byte`.
+    ///
+    /// This suits input sliced out of a larger buffer at `offset`, the
+    /// position of an invalid byte then being relative to the whole buffer.
+    fn from_base64_at(&self, offset: usize) -> Result<Vec<u8>, FromBase64Error> {
+        self.from_base64().map_err(|err| match err {
+            InvalidBase64Byte(ch, idx) => InvalidBase64Byte(ch, offset + idx),
+            err => err,
+        })
+    }
+
+    /// Converts the value of `self` as `from_base64` does, replacing the
+    /// contents of `out` with the decoded bytes.
+    ///
+    /// `out` keeps its capacity, so a vector reused across many conversions
+    /// only reallocates when it has to grow. On error the contents of `out`
+    /// are unspecified.
+    ///
+    /// The default implementation copies the result of `from_base64`;
+    /// implementors should override it to avoid the intermediate allocation.
+    fn from_base64_reuse(&self, out: &mut Vec<u8>) -> Result<(), FromBase64Error> {
+        let bytes = try!(self.from_base64());
+        out.clear();
+        out.extend(bytes);
+        Ok(())
+    }
+
+    /// Converts the base64 at the start of `self`, returning the decoded
+    /// bytes along with how many bytes of `self` the base64 took up, so that
+    /// whatever follows it can be read on from there.
+    ///
+    /// The base64 ends after the last `=` of its padding, or without
+    /// padding, at the first byte which isn't a base64 character of either
+    /// set, or at the end of `self`. Line breaks are skipped as with
+    /// `from_base64`, and only counted as taken up when more base64 follows
+    /// them. Invalid bytes are thus never an error, but padding with too few
+    /// `=` and data of the wrong length still are.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// use rustc_serialize::base64::FromBase64;
+    ///
+    /// fn main () {
+    ///     let frame = "Zm9vYg==Zm9v data";
+    ///     let (first, len) = frame.from_base64_consuming().unwrap();
+    ///     assert_eq!((&first[..], len), (&b"foob"[..], 8));
+    ///     let (second, len2) = frame[len..].from_base64_consuming().unwrap();
+    ///     assert_eq!((&second[..], len2), (&b"foo"[..], 4));
+    ///     assert_eq!(&frame[len + len2..], " data");
+    /// }
+    /// ```
+    fn from_base64_consuming(&self) -> Result<(Vec<u8>, usize), FromBase64Error>
+        where Self: AsRef<[u8]>
+    {
+        let input = self.as_ref();
+        let mut r = Vec::with_capacity(decoded_len_max(input.len()));
+        let len = try!(decode_prefix(input, |b| r.push(b)));
+        Ok((r, len))
+    }
 }
 
 /// Errors that can occur when decoding a base64 encoded string
@@ -209,8 +785,20 @@ pub trait FromBase64 {
 pub enum FromBase64Error {
     /// The input contained a character not part of the base64 format
     InvalidBase64Byte(u8, usize),
-    /// The input had an invalid length
+    /// The input had an invalid length: leaving out padding and line
+    /// breaks, a single character was left over after the last full group
+    /// of four, which can't encode a whole byte
     InvalidBase64Length,
+    /// The input was padded with the wrong number of `=` characters. Padding
+    /// may be left out, but when present it has to be complete. Only the
+    /// stricter decoders check this, such as `from_base64_with`; `from_base64`
+    /// takes any number of `=`.
+    InvalidBase64Padding {
+        /// The number of `=` characters the length of the data calls for
+        expected: u8,
+        /// The number of `=` characters found, at most 255
+        got: u8,
+    },
 }
 
 impl fmt::Debug for FromBase64Error {
@@ -219,15 +807,19 @@ impl fmt::Debug for FromBase64Error {
             InvalidBase64Byte(ch, idx) =>
                 write!(f, "Invalid character '{}' at position {}", ch, idx),
             InvalidBase64Length => write!(f, "Invalid length"),
+            InvalidBase64Padding { expected, got } =>
+                write!(f, "Invalid padding: expected {} '=', found {}", expected, got),
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl error::Error for FromBase64Error {
     fn description(&self) -> &str {
         match *self {
             InvalidBase64Byte(_, _) => "invalid character",
             InvalidBase64Length => "invalid length",
+            InvalidBase64Padding { .. } => "invalid padding",
         }
     }
 }
@@ -238,6 +830,7 @@ impl fmt::Display for FromBase64Error {
     }
 }
 
+#[cfg(any(feature = "std", feature = "alloc"))]
 impl FromBase64 for str {
     /// Convert any base64 encoded string (literal, `@`, `&`, or `~`)
     /// to the byte values it encodes.
@@ -269,67 +862,475 @@ impl FromBase64 for str {
     fn from_base64(&self) -> Result<Vec<u8>, FromBase64Error> {
         self.as_bytes().from_base64()
     }
+
+    fn from_base64_reuse(&self, out: &mut Vec<u8>) -> Result<(), FromBase64Error> {
+        self.as_bytes().from_base64_reuse(out)
+    }
 }
 
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl FromBase64 for String {
+    #[inline]
+    fn from_base64(&self) -> Result<Vec<u8>, FromBase64Error> {
+        self.as_bytes().from_base64()
+    }
+
+    fn from_base64_reuse(&self, out: &mut Vec<u8>) -> Result<(), FromBase64Error> {
+        self.as_bytes().from_base64_reuse(out)
+    }
+}
+
+#[cfg(any(feature = "std", feature = "alloc"))]
 impl FromBase64 for [u8] {
     fn from_base64(&self) -> Result<Vec<u8>, FromBase64Error> {
-        let mut r = Vec::with_capacity(self.len());
-        let mut buf: u32 = 0;
-        let mut modulus = 0;
-
-        let mut it = self.iter();
-        for byte in it.by_ref() {
-            let code = DECODE_TABLE[*byte as usize];
-            if code >= SPECIAL_CODES_START {
-                match code {
-                    NEWLINE_CODE => continue,
-                    EQUALS_CODE => break,
-                    INVALID_CODE => return Err(InvalidBase64Byte(
-                            *byte, (byte as *const _ as usize) - self.as_ptr() as usize)),
-                    _ => unreachable!(),
+        let mut r = Vec::with_capacity(decoded_len_max(self.len()));
+        try!(decode_with(self, None, |b| r.push(b)));
+        Ok(r)
+    }
+
+    fn from_base64_reuse(&self, out: &mut Vec<u8>) -> Result<(), FromBase64Error> {
+        out.clear();
+        out.reserve(decoded_len_max(self.len()));
+        decode_with(self, None, |b| out.push(b))
+    }
+}
+
+/// Returns the most bytes that `len` bytes of base64 can decode to, which is
+/// the size of the buffer needed by `decode_to_slice`.
+pub fn decoded_len_max(len: usize) -> usize {
+    len / 4 * 3 + len % 4 * 3 / 4
+}
+
+/// Decodes the base64 in `input`, writing the bytes it encodes to the start
+/// of `output` instead of allocating a new vector.
+///
+/// Returns the number of bytes written, which are the same bytes
+/// `from_base64` would return.
+///
+/// # Panics
+///
+/// Panics if `output` is shorter than `decoded_len_max(input.len())`.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::base64::{decode_to_slice, decoded_len_max};
+///
+/// fn main () {
+///     let input = b"SGVsbG8=";
+///     let mut buf = [0u8; 6];
+///     assert_eq!(decoded_len_max(input.len()), buf.len());
+///     let n = decode_to_slice(input, &mut buf).unwrap();
+///     assert_eq!(&buf[..n], b"Hello");
+/// }
+/// ```
+pub fn decode_to_slice(input: &[u8], output: &mut [u8]) -> Result<usize, FromBase64Error> {
+    assert!(output.len() >= decoded_len_max(input.len()),
+            "output buffer too short for the decoded base64");
+    let mut written = 0;
+    try!(decode_with(input, None, |b| {
+        output[written] = b;
+        written += 1;
+    }));
+    Ok(written)
+}
+
+// Decodes `input`, handing each decoded byte to `push` in order. With a
+// `config`, the characters particular to the other character set are
+// invalid, and padding has to be complete if there is any.
+fn decode_with<F>(input: &[u8], config: Option<Config>, mut push: F)
+                  -> Result<(), FromBase64Error>
+    where F: FnMut(u8)
+{
+    let mut buf: u32 = 0;
+    let mut modulus = 0;
+    let mut padding: u8 = 0;
+
+    let mut it = input.iter();
+    for byte in it.by_ref() {
+        let code = DECODE_TABLE[*byte as usize];
+        if code >= SPECIAL_CODES_START {
+            match code {
+                NEWLINE_CODE => continue,
+                EQUALS_CODE => {
+                    padding = 1;
+                    break;
                 }
+                INVALID_CODE => return Err(InvalidBase64Byte(
+                        *byte, (byte as *const _ as usize) - input.as_ptr() as usize)),
+                _ => unreachable!(),
             }
-            buf = (buf | code as u32) << 6;
-            modulus += 1;
-            if modulus == 4 {
-                modulus = 0;
-                r.push((buf >> 22) as u8);
-                r.push((buf >> 14) as u8);
-                r.push((buf >> 6 ) as u8);
+        }
+        if let Some(config) = config {
+            if code >= 62 && *byte != encode_char(code, config.char_set) {
+                return Err(InvalidBase64Byte(
+                        *byte, (byte as *const _ as usize) - input.as_ptr() as usize));
             }
         }
+        buf = (buf | code as u32) << 6;
+        modulus += 1;
+        if modulus == 4 {
+            modulus = 0;
+            push((buf >> 22) as u8);
+            push((buf >> 14) as u8);
+            push((buf >> 6 ) as u8);
+        }
+    }
 
-        for byte in it {
-            match *byte {
-                b'=' | b'\r' | b'\n' => continue,
-                _ => return Err(InvalidBase64Byte(
-                        *byte, (byte as *const _ as usize) - self.as_ptr() as usize)),
-            }
+    for byte in it {
+        match *byte {
+            b'=' => padding = padding.saturating_add(1),
+            b'\r' | b'\n' => continue,
+            _ => return Err(InvalidBase64Byte(
+                    *byte, (byte as *const _ as usize) - input.as_ptr() as usize)),
         }
+    }
+    if config.is_some() {
+        try!(check_padding(modulus, padding));
+    }
 
-        match modulus {
-            2 => {
-                r.push((buf >> 10) as u8);
+    match modulus {
+        2 => {
+            push((buf >> 10) as u8);
+        }
+        3 => {
+            push((buf >> 16) as u8);
+            push((buf >> 8 ) as u8);
+        }
+        0 => (),
+        _ => return Err(InvalidBase64Length),
+    }
+
+    Ok(())
+}
+
+// Decodes the base64 at the start of `input` for `from_base64_consuming`,
+// handing each decoded byte to `push` in order, and returns the length of
+// the base64.
+#[cfg(any(feature = "std", feature = "alloc"))]
+fn decode_prefix<F>(input: &[u8], mut push: F) -> Result<usize, FromBase64Error>
+    where F: FnMut(u8)
+{
+    let mut buf: u32 = 0;
+    let mut modulus = 0;
+    // Just past the last base64 character.
+    let mut end = 0;
+    let mut padding_at = None;
+
+    for (i, byte) in input.iter().enumerate() {
+        let code = DECODE_TABLE[*byte as usize];
+        if code >= SPECIAL_CODES_START {
+            match code {
+                NEWLINE_CODE => continue,
+                // Padding can only follow a partial group.
+                EQUALS_CODE if modulus != 0 => padding_at = Some(i),
+                _ => {}
             }
-            3 => {
-                r.push((buf >> 16) as u8);
-                r.push((buf >> 8 ) as u8);
+            break;
+        }
+        buf = (buf | code as u32) << 6;
+        modulus += 1;
+        end = i + 1;
+        if modulus == 4 {
+            modulus = 0;
+            push((buf >> 22) as u8);
+            push((buf >> 14) as u8);
+            push((buf >> 6 ) as u8);
+        }
+    }
+
+    let expected = match modulus {
+        0 => 0,
+        2 => 2,
+        3 => 1,
+        _ => return Err(InvalidBase64Length),
+    };
+    if let Some(start) = padding_at {
+        let mut got = 0;
+        for (i, byte) in input[start..].iter().enumerate() {
+            match *byte {
+                b'=' => {
+                    got += 1;
+                    end = start + i + 1;
+                    if got == expected {
+                        break;
+                    }
+                }
+                b'\r' | b'\n' => continue,
+                _ => break,
             }
-            0 => (),
-            _ => return Err(InvalidBase64Length),
         }
+        if got != expected {
+            return Err(InvalidBase64Padding { expected: expected, got: got });
+        }
+    }
+
+    match modulus {
+        2 => {
+            push((buf >> 10) as u8);
+        }
+        3 => {
+            push((buf >> 16) as u8);
+            push((buf >> 8 ) as u8);
+        }
+        _ => (),
+    }
 
-        Ok(r)
+    Ok(end)
+}
+
+// Checks the number of `=` characters padding data whose length leaves
+// `modulus` characters in the last group of four, unless there are none.
+fn check_padding(modulus: usize, got: u8) -> Result<(), FromBase64Error> {
+    let expected = match modulus {
+        0 => 0,
+        2 => 2,
+        3 => 1,
+        _ => return Err(InvalidBase64Length),
+    };
+    if got != 0 && got != expected {
+        return Err(InvalidBase64Padding { expected: expected, got: got });
+    }
+    Ok(())
+}
+
+/// Decodes base64 without branching on or indexing tables with the
+/// characters of the input, for decoding secrets such as keys and tokens.
+///
+/// The usual decoding looks each character up in `DECODE_TABLE`, which
+/// takes a varying time depending on which parts of the table are cached,
+/// and can leak the data being decoded through timing. Here the value of
+/// each character is worked out with arithmetic alone, so the time taken
+/// depends only on the length of the input and on whether it is valid.
+///
+/// Both character sets are accepted, as with `from_base64`, along with
+/// trailing `=` padding, which has to be complete when present as with
+/// `from_base64_with`. Unlike `from_base64`, line breaks are not skipped
+/// but rejected, since where they fall would show in the timing.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::base64::decode_ct;
+///
+/// fn main () {
+///     assert_eq!(decode_ct(b"c2VjcmV0").unwrap(), b"secret");
+///     assert!(decode_ct(b"c2Vj\ncmV0").is_err());
+/// }
+/// ```
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub fn decode_ct(input: &[u8]) -> Result<Vec<u8>, FromBase64Error> {
+    let mut len = input.len();
+    while len > 0 && input[len - 1] == b'=' {
+        len -= 1;
+    }
+    let data = &input[..len];
+
+    let mut out = Vec::with_capacity(decoded_len_max(len));
+    let mut buf: u32 = 0;
+    let mut invalid: i32 = 0;
+    for (i, byte) in data.iter().enumerate() {
+        let val = decode_char_ct(*byte);
+        invalid |= val;
+        buf = (buf << 6) | (val & 0x3F) as u32;
+        if i % 4 == 3 {
+            out.push((buf >> 16) as u8);
+            out.push((buf >> 8) as u8);
+            out.push(buf as u8);
+        }
     }
+    if invalid < 0 {
+        // Only now, with the input known to be bad, find where.
+        let idx = data.iter().position(|b| decode_char_ct(*b) < 0).unwrap();
+        return Err(InvalidBase64Byte(data[idx], idx));
+    }
+    let padding = input.len() - len;
+    try!(check_padding(len % 4, if padding > 255 { 255 } else { padding as u8 }));
+    match len % 4 {
+        2 => out.push((buf >> 4) as u8),
+        3 => {
+            out.push((buf >> 10) as u8);
+            out.push((buf >> 2) as u8);
+        }
+        0 => (),
+        _ => return Err(InvalidBase64Length),
+    }
+    Ok(out)
+}
+
+// Returns the 6-bit value of a base64 character of either character set,
+// or -1 if it isn't one. `((lo - c) & (c - hi)) >> 8` is all ones if
+// `lo < c < hi` and zero otherwise, so exactly one range adds its offset.
+#[cfg(any(feature = "std", feature = "alloc"))]
+fn decode_char_ct(c: u8) -> i32 {
+    let c = c as i32;
+    let mut val = -1;
+    val += (((b'A' as i32 - 1 - c) & (c - b'Z' as i32 - 1)) >> 8) & (c - b'A' as i32 + 1);
+    val += (((b'a' as i32 - 1 - c) & (c - b'z' as i32 - 1)) >> 8) & (c - b'a' as i32 + 27);
+    val += (((b'0' as i32 - 1 - c) & (c - b'9' as i32 - 1)) >> 8) & (c - b'0' as i32 + 53);
+    val += (((b'+' as i32 - 1 - c) & (c - b'+' as i32 - 1)) >> 8) & 63;
+    val += (((b'-' as i32 - 1 - c) & (c - b'-' as i32 - 1)) >> 8) & 63;
+    val += (((b'/' as i32 - 1 - c) & (c - b'/' as i32 - 1)) >> 8) & 64;
+    val += (((b'_' as i32 - 1 - c) & (c - b'_' as i32 - 1)) >> 8) & 64;
+    val
 }
 
+#[cfg(any(feature = "std", feature = "alloc"))]
 impl<'a, T: ?Sized + FromBase64> FromBase64 for &'a T {
     fn from_base64(&self) -> Result<Vec<u8>, FromBase64Error> {
         (**self).from_base64()
     }
+
+    fn from_base64_reuse(&self, out: &mut Vec<u8>) -> Result<(), FromBase64Error> {
+        (**self).from_base64_reuse(out)
+    }
+}
+
+/// Decodes the base64 in `input`.
+///
+/// This is `input.from_base64()`, without importing `FromBase64`, and as
+/// such accepts either character set, with or without padding and line
+/// breaks.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::base64;
+///
+/// fn main () {
+///     assert_eq!(base64::decode("SGVsbG8=").unwrap(), b"Hello");
+/// }
+/// ```
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub fn decode(input: &str) -> Result<Vec<u8>, FromBase64Error> {
+    input.from_base64()
+}
+
+/// Decodes the base64 in `input`, accepting only the character set of
+/// `config`.
+///
+/// This is `input.from_base64_with(config)`, without importing
+/// `FromBase64`.
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub fn decode_config(input: &str, config: Config) -> Result<Vec<u8>, FromBase64Error> {
+    input.from_base64_with(config)
 }
 
-/// Base64 decoding lookup table, generated using:
+/// Decodes the base64 in `input` as `decode` does, once it has been through
+/// URL percent-encoding: `%3D` is taken as `=` padding, and `%2B` and `%2F`
+/// as `+` and `/`, in either case.
+///
+/// Any other `%` is reported as `InvalidBase64Byte`, and positions in errors
+/// are those in `input`, before the escapes are turned back.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::base64;
+///
+/// fn main () {
+///     assert_eq!(base64::decode_percent_encoded("Pz8%2fPw%3D%3d").unwrap(), b"???\x3f");
+///     assert!(base64::decode_percent_encoded("Pz8%20").is_err());
+/// }
+/// ```
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub fn decode_percent_encoded(input: &str) -> Result<Vec<u8>, FromBase64Error> {
+    let input = input.as_bytes();
+    let mut unescaped = Vec::with_capacity(input.len());
+    // The position in `input` of each byte of `unescaped`.
+    let mut positions = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        positions.push(i);
+        if input[i] != b'%' {
+            unescaped.push(input[i]);
+            i += 1;
+            continue;
+        }
+        if i + 2 >= input.len() {
+            return Err(InvalidBase64Byte(b'%', i));
+        }
+        unescaped.push(match (input[i + 1], input[i + 2]) {
+            (b'3', b'D') | (b'3', b'd') => b'=',
+            (b'2', b'B') | (b'2', b'b') => b'+',
+            (b'2', b'F') | (b'2', b'f') => b'/',
+            _ => return Err(InvalidBase64Byte(b'%', i)),
+        });
+        i += 3;
+    }
+
+    let mut out = Vec::with_capacity(decoded_len_max(unescaped.len()));
+    match decode_with(&unescaped, None, |b| out.push(b)) {
+        Ok(()) => Ok(out),
+        Err(InvalidBase64Byte(ch, idx)) => Err(InvalidBase64Byte(ch, positions[idx])),
+        Err(err) => Err(err),
+    }
+}
+
+/// A byte buffer which is serialized as a base64 encoded string (using the
+/// `STANDARD` configuration) rather than as a sequence of numbers.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::base64::Base64Bytes;
+/// use rustc_serialize::json;
+///
+/// fn main () {
+///     let bytes = Base64Bytes::from(b"Hello".to_vec());
+///     assert_eq!(json::encode(&bytes).unwrap(), "\"SGVsbG8=\"");
+///     let decoded: Base64Bytes = json::decode("\"SGVsbG8=\"").unwrap();
+///     assert_eq!(&decoded[..], b"Hello");
+/// }
+/// ```
+#[cfg(feature = "std")]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Base64Bytes(pub Vec<u8>);
+
+#[cfg(feature = "std")]
+impl Deref for Base64Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<Vec<u8>> for Base64Bytes {
+    fn from(v: Vec<u8>) -> Base64Bytes {
+        Base64Bytes(v)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Encodable for Base64Bytes {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_str(&self.0.to_base64(STANDARD))
+    }
+}
+
+#[cfg(feature = "std")]
+impl Decodable for Base64Bytes {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Base64Bytes, D::Error> {
+        let s = try!(d.read_str());
+        match s.from_base64() {
+            Ok(v) => Ok(Base64Bytes(v)),
+            Err(e) => Err(d.error(&e.to_string())),
+        }
+    }
+}
+
+/// Base64 decoding lookup table, giving the 6-bit value of every character of
+/// both character sets, and `EQUALS_CODE`, `NEWLINE_CODE` or `INVALID_CODE`
+/// for every other byte.
+///
+/// It was generated using:
 ///
 /// ```
 /// let mut ch = 0u8;
@@ -352,7 +1353,7 @@ impl<'a, T: ?Sized + FromBase64> FromBase64 for &'a T {
 /// }
 /// println!("");
 /// ```
-const DECODE_TABLE: [u8; 256] = [
+pub const DECODE_TABLE: [u8; 256] = [
     0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFD, 0xFF, 0xFF, 0xFD, 0xFF, 0xFF,
     0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
     0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x3E, 0xFF, 0x3E, 0xFF, 0x3F,
@@ -370,14 +1371,60 @@ const DECODE_TABLE: [u8; 256] = [
     0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
     0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
 ];
-const INVALID_CODE: u8 = 0xFF;
-const EQUALS_CODE: u8 = 0xFE;
-const NEWLINE_CODE: u8 = 0xFD;
+/// The `DECODE_TABLE` entry of bytes which are never valid base64.
+pub const INVALID_CODE: u8 = 0xFF;
+/// The `DECODE_TABLE` entry of `=`.
+pub const EQUALS_CODE: u8 = 0xFE;
+/// The `DECODE_TABLE` entry of `\r` and `\n`.
+pub const NEWLINE_CODE: u8 = 0xFD;
 const SPECIAL_CODES_START: u8 = NEWLINE_CODE;
 
+// The slice based API works without `std` or `alloc`, so its tests do too.
 #[cfg(test)]
+mod slice_tests {
+    use base64::{STANDARD, URL_SAFE, encode_to_slice, decode_to_slice, decoded_len_max};
+
+    #[test]
+    fn test_encode_to_slice() {
+        let mut buf = [0; 24];
+        let n = encode_to_slice(b"foobar", STANDARD, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"Zm9vYmFy");
+        let n = encode_to_slice(b"f", STANDARD, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"Zg==");
+        let n = encode_to_slice(b"f", URL_SAFE, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"Zg");
+        assert_eq!(encode_to_slice(b"", STANDARD, &mut []), Some(0));
+        assert_eq!(encode_to_slice(&[0; 18], STANDARD, &mut buf[..23]), None);
+    }
+
+    #[test]
+    fn test_decode_to_slice_exact() {
+        let mut buf = [0; 3];
+        assert_eq!(decode_to_slice(b"Zm9v", &mut buf).unwrap(), 3);
+        assert_eq!(&buf, b"foo");
+        assert!(decode_to_slice(b"Zm$v", &mut buf).is_err());
+        assert!(decode_to_slice(b"Z", &mut buf).is_err());
+        assert_eq!(decoded_len_max(7), 5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_decode_to_slice_short_output() {
+        let mut buf = [0; 2];
+        let _ = decode_to_slice(b"Zm9v", &mut buf);
+    }
+}
+
+#[cfg(all(test, any(feature = "std", feature = "alloc")))]
 mod tests {
-    use base64::{Config, Newline, FromBase64, ToBase64, STANDARD, URL_SAFE};
+    use base64::{Config, Newline, FromBase64, ToBase64, STANDARD, URL_SAFE, MIME};
+    use base64::{URL_SAFE_NO_PAD, PEM, ChunkedEncoder};
+    use base64::{encode_to_slice, encoded_len, decode_to_slice, decoded_len_max, decode_ct};
+    use base64::decode_percent_encoded;
+    #[cfg(feature = "std")] use base64::{Base64Read, Base64Write};
+    use base64::FromBase64Error;
+    use base64::{encode_char, DECODE_TABLE, STANDARD_CHARS, URLSAFE_CHARS};
+    use base64::{INVALID_CODE, EQUALS_CODE, NEWLINE_CODE, Standard, UrlSafe};
 
     #[test]
     fn test_to_base64_basic() {
@@ -411,6 +1458,95 @@ mod tests {
                    "Zm9v\nYmFy");
     }
 
+    #[test]
+    fn test_to_base64_custom_line_break() {
+        for &(separator, expected) in [("\r", "Zm9v\rYmFy\rYg=="),
+                                       ("", "Zm9vYmFyYg=="),
+                                       ("<br>", "Zm9v<br>YmFy<br>Yg=="),
+                                       ("\u{2028}", "Zm9v\u{2028}YmFy\u{2028}Yg==")].iter() {
+            let config = Config {line_length: Some(4),
+                                 newline: Newline::Custom(separator),
+                                 ..STANDARD};
+            assert_eq!(b"foobarb".to_base64(config), expected);
+            assert_eq!(encoded_len(7, config), expected.len());
+        }
+        assert_eq!(Newline::LF.as_str(), "\n");
+        assert_eq!(Newline::CRLF.as_str(), "\r\n");
+    }
+
+    #[test]
+    fn test_to_base64_final_newline() {
+        // no separator follows the last line, even when it is full
+        for &(line_length, expected) in [(4, "Zm9v\nYmFy"), (8, "Zm9vYmFy"),
+                                         (16, "Zm9vYmFy")].iter() {
+            let config = Config {line_length: Some(line_length), final_newline: false, ..PEM};
+            assert_eq!(b"foobar".to_base64(config), expected);
+            assert_eq!(encoded_len(6, config), expected.len());
+        }
+        let config = Config {line_length: Some(4), final_newline: false, ..PEM};
+        assert_eq!(b"foob".to_base64(config), "Zm9v\nYg==");
+
+        // unless one is asked for
+        for &(line_length, expected) in [(Some(4), "Zm9v\nYmFy\n"), (Some(8), "Zm9vYmFy\n"),
+                                         (None, "Zm9vYmFy\n")].iter() {
+            let config = Config {line_length: line_length, ..PEM};
+            assert_eq!(b"foobar".to_base64(config), expected);
+            assert_eq!(encoded_len(6, config), expected.len());
+            let mut buf = [0; 16];
+            let n = encode_to_slice(b"foobar", config, &mut buf).unwrap();
+            assert_eq!(&buf[..n], expected.as_bytes());
+            assert_eq!(expected.from_base64().unwrap(), b"foobar");
+        }
+        let config = Config {line_length: Some(4), final_newline: true, pad: false, ..MIME};
+        assert_eq!(b"foob".to_base64(config), "Zm9v\r\nYg\r\n");
+        let mut buf = [0; 16];
+        let n = encode_to_slice(b"foob", config, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"Zm9v\r\nYg\r\n");
+        let mut out = "x".to_string();
+        b"foob".encode_to(config, &mut out);
+        assert_eq!(out, "xZm9v\r\nYg\r\n");
+
+        // there are no lines to end in empty output
+        assert_eq!(b"".to_base64(config), "");
+        assert_eq!(encoded_len(0, config), 0);
+    }
+
+    #[test]
+    fn test_to_base64_str() {
+        fn encode<T: ToBase64>(input: T) -> String {
+            input.to_base64(STANDARD)
+        }
+        assert_eq!("foobar".to_base64(STANDARD), "Zm9vYmFy");
+        assert_eq!(encode("fo"), "Zm8=");
+        assert_eq!(encode("\u{e9}".to_string()), "w6k=");
+        assert_eq!(encode(&"f".to_string()), "Zg==");
+
+        let mut out = "data:;base64,".to_string();
+        "foo".encode_to(STANDARD, &mut out);
+        assert_eq!(out, "data:;base64,Zm9v");
+    }
+
+    #[test]
+    fn test_chunked_encoder() {
+        let v: Vec<u8> = (0..100).map(|i| (i * 7) as u8).collect();
+        for &size in [3, 6, 57, 99, 300].iter() {
+            let encoder = ChunkedEncoder::new(STANDARD, size);
+            let chunks = encoder.chunks(&v);
+            assert_eq!(chunks.len(), (v.len() + size - 1) / size);
+            assert_eq!(chunks.collect::<Vec<_>>().concat(), v.to_base64(STANDARD));
+        }
+
+        let encoder = ChunkedEncoder::new(URL_SAFE, 2);
+        assert_eq!(encoder.chunks(&[251, 255, 251]).collect::<Vec<_>>(), ["-_8", "-w"]);
+        assert_eq!(encoder.chunks(&[]).next(), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_chunked_encoder_empty_chunks() {
+        ChunkedEncoder::new(STANDARD, 0);
+    }
+
     #[test]
     fn test_to_base64_padding() {
         assert_eq!("f".as_bytes().to_base64(Config {pad: false, ..STANDARD}), "Zg");
@@ -423,11 +1559,51 @@ mod tests {
         assert_eq!([251, 255].to_base64(STANDARD), "+/8=");
     }
 
+    #[test]
+    fn test_to_base64_presets() {
+        assert_eq!([251, 255].to_base64(URL_SAFE_NO_PAD), "-_8");
+        assert!(![0; 1000].to_base64(URL_SAFE_NO_PAD).contains("\n"));
+
+        let pem = [0; 100].to_base64(PEM);
+        let lines: Vec<&str> = pem.split('\n').collect();
+        assert_eq!(lines.iter().map(|l| l.len()).collect::<Vec<_>>(), vec![64, 64, 8, 0]);
+        assert!(!pem.contains("\r"));
+        assert!(pem.ends_with("AA==\n"));
+        assert_eq!(pem.from_base64().unwrap(), &[0; 100][..]);
+    }
+
     #[test]
     fn test_to_base64_empty_line_length() {
         [].to_base64(Config {line_length: Some(72), ..STANDARD});
     }
 
+    #[test]
+    fn test_encode_to() {
+        let mut out = "Zg==".to_string();
+        b"foobar".encode_to(STANDARD, &mut out);
+        assert_eq!(out, "Zg==Zm9vYmFy");
+        b"f".encode_to(URL_SAFE, &mut out);
+        assert_eq!(out, "Zg==Zm9vYmFyZg");
+        b"".encode_to(STANDARD, &mut out);
+        assert_eq!(out, "Zg==Zm9vYmFyZg");
+        b"fo".encode_to(Config {line_length: Some(4), ..STANDARD}, &mut out);
+        assert_eq!(out, "Zg==Zm9vYmFyZgZm8=");
+    }
+
+    #[test]
+    fn test_encode_to_slice_matches_to_base64() {
+        let lf = Config {line_length: Some(4), newline: Newline::LF, ..STANDARD};
+        let unpadded = Config {line_length: Some(6), pad: false, ..STANDARD};
+        let v: Vec<u8> = (0..200).map(|i| (i * 37) as u8).collect();
+        for &config in [STANDARD, URL_SAFE, MIME, lf, unpadded].iter() {
+            for len in 0..v.len() {
+                let mut buf = vec![0; encoded_len(len, config)];
+                let n = encode_to_slice(&v[..len], config, &mut buf).unwrap();
+                assert_eq!(&buf[..n], v[..len].to_base64(config).as_bytes());
+            }
+        }
+    }
+
     #[test]
     fn test_from_base64_basic() {
         assert_eq!("".from_base64().unwrap(), b"");
@@ -461,6 +1637,69 @@ mod tests {
         assert_eq!("-_8".from_base64().unwrap(), "+/8=".from_base64().unwrap());
     }
 
+    #[test]
+    fn test_encode_decode_functions() {
+        use base64::{encode, encode_config, decode, decode_config};
+
+        assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(encode(b"\xFB\xFF"), "+/8=");
+        assert_eq!(encode_config(b"\xFB\xFF", URL_SAFE), "-_8");
+        assert_eq!(decode("Zm9v\r\nYmFy").unwrap(), b"foobar");
+        assert_eq!(decode("-_8").unwrap(), b"\xFB\xFF");
+        assert_eq!(decode_config("+/8=", STANDARD).unwrap(), b"\xFB\xFF");
+        assert_eq!(decode_config("-_8", URL_SAFE).unwrap(), b"\xFB\xFF");
+        match decode_config("+/8=", URL_SAFE) {
+            Err(FromBase64Error::InvalidBase64Byte(b'+', 0)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match decode_config("ab-_", STANDARD) {
+            Err(FromBase64Error::InvalidBase64Byte(b'-', 2)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(decode("Zm$=").is_err());
+    }
+
+    #[test]
+    fn test_from_base64_with() {
+        assert_eq!("+/8=".from_base64_with(STANDARD).unwrap(), b"\xFB\xFF");
+        assert_eq!(b"-_8".from_base64_with(URL_SAFE).unwrap(), b"\xFB\xFF");
+        assert_eq!("Zm9v\r\nYmFy".from_base64_with(URL_SAFE).unwrap(), b"foobar");
+        // mixing the sets is fine for `from_base64` only
+        assert_eq!("+_8=".from_base64().unwrap(), b"\xFB\xFF");
+        match "+_8=".from_base64_with(STANDARD) {
+            Err(FromBase64Error::InvalidBase64Byte(b'_', 1)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match "+_8=".from_base64_with(URL_SAFE) {
+            Err(FromBase64Error::InvalidBase64Byte(b'+', 0)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match "$-".from_base64_with(STANDARD) {
+            Err(FromBase64Error::InvalidBase64Byte(b'$', 0)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // through references, and for types only implementing `from_base64`
+        assert_eq!((&&"-_8").from_base64_with(URL_SAFE).unwrap(), b"\xFB\xFF");
+        struct Encoded(String);
+        impl AsRef<[u8]> for Encoded {
+            fn as_ref(&self) -> &[u8] {
+                self.0.as_bytes()
+            }
+        }
+        impl FromBase64 for Encoded {
+            fn from_base64(&self) -> Result<Vec<u8>, FromBase64Error> {
+                self.0.from_base64()
+            }
+        }
+        let encoded = Encoded("+_8=".to_string());
+        assert!(encoded.from_base64().is_ok());
+        match encoded.from_base64_with(URL_SAFE) {
+            Err(FromBase64Error::InvalidBase64Byte(b'+', 0)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
     #[test]
     fn test_from_base64_invalid_char() {
         assert!("Zm$=".from_base64().is_err());
@@ -472,6 +1711,268 @@ mod tests {
         assert!("Z===".from_base64().is_err());
     }
 
+    #[test]
+    fn test_decode_to_slice() {
+        for &s in ["", "Zg==", "Zm8", "Zm9v", "Zm9vYg==", "Zm9vYmE=", "Zm9v\r\nYmFy"].iter() {
+            let mut buf = [0xAA; 16];
+            assert!(decoded_len_max(s.len()) >= s.from_base64().unwrap().len());
+            let n = decode_to_slice(s.as_bytes(), &mut buf).unwrap();
+            assert_eq!(&buf[..n], &s.from_base64().unwrap()[..]);
+        }
+    }
+
+    #[test]
+    fn test_decode_ct() {
+        for &s in ["", "Zg==", "Zm8", "Zm9v", "Zm9vYg==", "Zm9vYmE=", "-_8", "+/8="].iter() {
+            assert_eq!(decode_ct(s.as_bytes()).unwrap(), s.from_base64().unwrap());
+        }
+        for c in 0..256 {
+            let s = [b'Q', c as u8, b'=', b'='];
+            assert!(decode_ct(&s).ok() == s.from_base64().ok(), "{:?}", s);
+        }
+        let v: Vec<u8> = (0..200).map(|i| (i * 37) as u8).collect();
+        for len in 0..v.len() {
+            for &config in [STANDARD, URL_SAFE].iter() {
+                let encoded = v[..len].to_base64(config);
+                assert_eq!(decode_ct(encoded.as_bytes()).unwrap(), &v[..len]);
+            }
+        }
+
+        match decode_ct(b"Zm$=") {
+            Err(FromBase64Error::InvalidBase64Byte(b'$', 2)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match decode_ct(b"Zm9v\nYmFy") {
+            Err(FromBase64Error::InvalidBase64Byte(b'\n', 4)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match decode_ct(b"Z===") {
+            Err(FromBase64Error::InvalidBase64Length) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_from_base64_string() {
+        fn decode<T: FromBase64>(input: T) -> Vec<u8> {
+            input.from_base64().unwrap()
+        }
+        let owned = "Zm9vYmFy".to_string();
+        assert_eq!(decode(&owned), b"foobar");
+        assert_eq!(decode(owned.clone()), b"foobar");
+        assert_eq!(owned.from_base64_with(URL_SAFE).unwrap(), b"foobar");
+        assert!("+/8=".to_string().from_base64_with(URL_SAFE).is_err());
+    }
+
+    #[test]
+    fn test_config_display() {
+        assert_eq!(Standard.to_string(), "Standard");
+        assert_eq!(UrlSafe.to_string(), "UrlSafe");
+        assert_eq!(MIME.to_string(), "Standard, padded, CRLF line breaks every 76 characters");
+        assert_eq!(URL_SAFE.to_string(), "UrlSafe, unpadded, no line breaks");
+        let config = Config { newline: Newline::Custom("\r"), ..PEM };
+        assert_eq!(config.to_string(),
+                   "Standard, padded, Custom(\"\\r\") line breaks every 64 characters, \
+                    final Custom(\"\\r\") line break");
+        assert_eq!(format!("{:?}", URL_SAFE),
+                   "Config { char_set: UrlSafe, newline: CRLF, pad: false, line_length: None, \
+                    final_newline: false }");
+        assert_eq!(PEM.to_string(),
+                   "Standard, padded, LF line breaks every 64 characters, final LF line break");
+    }
+
+    #[test]
+    fn test_base64_reuse() {
+        let mut out = Vec::with_capacity(64);
+        "Zm9vYmFy".from_base64_reuse(&mut out).unwrap();
+        assert_eq!(out, b"foobar");
+        b"Zm8=".from_base64_reuse(&mut out).unwrap();
+        assert_eq!(out, b"fo");
+        "Zg".to_string().from_base64_reuse(&mut out).unwrap();
+        assert_eq!(out, b"f");
+        assert_eq!(out.capacity(), 64);
+        assert!("Zm$v".from_base64_reuse(&mut out).is_err());
+
+        let mut out = String::with_capacity(64);
+        b"foobar".to_base64_reuse(STANDARD, &mut out);
+        assert_eq!(out, "Zm9vYmFy");
+        "f".to_base64_reuse(STANDARD, &mut out);
+        assert_eq!(out, "Zg==");
+        assert_eq!(out.capacity(), 64);
+    }
+
+    #[test]
+    fn test_from_base64_padding() {
+        for &s in ["Zg", "Zg==", "Zg==\r\n", "Zg=\r\n="].iter() {
+            assert_eq!(s.from_base64().unwrap(), b"f");
+            assert_eq!(s.from_base64_with(STANDARD).unwrap(), b"f");
+        }
+        // `from_base64` takes any padding
+        for &(s, decoded) in [("Zg=", &b"f"[..]), ("Zg===", b"f"), ("Zm8==", b"fo"),
+                              ("Zm9v=", b"foo"), ("Zm9v====", b"foo")].iter() {
+            assert_eq!(s.from_base64().unwrap(), decoded);
+        }
+        for &(s, expected, got) in [("Zg=", 2, 1), ("Zg===", 2, 3), ("Zm8==", 1, 2),
+                                    ("Zm9v=", 0, 1), ("Zm9v====", 0, 4)].iter() {
+            match s.from_base64_with(STANDARD) {
+                Err(FromBase64Error::InvalidBase64Padding { expected: e, got: g })
+                    if e == expected && g == got => {}
+                other => panic!("unexpected {:?} for {}", other, s),
+            }
+            assert_eq!(decode_ct(s.as_bytes()).err().map(|e| e.to_string()),
+                       Some(format!("Invalid padding: expected {} '=', found {}",
+                                    expected, got)));
+        }
+        let long = format!("Zg{}", ::std::iter::repeat("=").take(300).collect::<String>());
+        assert_eq!(long.from_base64().unwrap(), b"f");
+        match long.from_base64_with(STANDARD) {
+            Err(FromBase64Error::InvalidBase64Padding { expected: 2, got: 255 }) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_from_base64_length() {
+        fn decode_all(s: &str) -> Vec<Result<Vec<u8>, FromBase64Error>> {
+            let mut buf = [0; 16];
+            vec![s.from_base64(),
+                 s.from_base64_with(STANDARD),
+                 decode_to_slice(s.as_bytes(), &mut buf).map(|n| buf[..n].to_vec()),
+                 decode_ct(s.as_bytes())]
+        }
+
+        // the length of the data, leaving out padding, is 0, 2 or 3 mod 4
+        for &(s, bytes) in [("", &b""[..]), ("Zm9v", b"foo"), ("Zm9vYg", b"foob"),
+                            ("Zm9vYg==", b"foob"), ("Zm9vYmE", b"fooba"),
+                            ("Zm9vYmE=", b"fooba")].iter() {
+            for res in decode_all(s) {
+                assert_eq!(res.unwrap(), bytes);
+            }
+        }
+
+        // and 1 mod 4 is always an error, whatever padding follows, rather
+        // than the stray character being dropped
+        for &s in ["Z", "Zm9vY", "Zm9vY=", "Zm9vY==", "Zm9vY===", "Zm9vYmFyY"].iter() {
+            for res in decode_all(s) {
+                match res {
+                    Err(FromBase64Error::InvalidBase64Length) => {}
+                    other => panic!("unexpected {:?} for {}", other, s),
+                }
+            }
+        }
+        match "Zm9v\r\nY\r\n".from_base64() {
+            Err(FromBase64Error::InvalidBase64Length) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decode_percent_encoded() {
+        for &(s, expected) in [("Zg%3D%3D", &b"f"[..]), ("Zg%3d%3d", b"f"), ("Zg%3D=", b"f"),
+                               ("Zm8%3D", b"fo"), ("Zm9v", b"foo"), ("", b""),
+                               ("-_8%3D", b"\xfb\xff"), ("%2B%2f8%3D", b"\xfb\xff"),
+                               ("%2b%2F8=", b"\xfb\xff"), ("Zm9v\r\nZg%3D%3D", b"foof")].iter() {
+            assert_eq!(decode_percent_encoded(s).unwrap(), expected);
+        }
+
+        // other escapes are errors, at their position in the input
+        for &(s, idx) in [("Zg%3E%3D", 2), ("Zg%20", 2), ("Zg%", 2), ("Zg%3", 2),
+                          ("Zm9v%2", 4), ("%%3D", 0), ("Zg%3D%3D%", 8)].iter() {
+            match decode_percent_encoded(s) {
+                Err(FromBase64Error::InvalidBase64Byte(b'%', i)) if i == idx => {}
+                other => panic!("unexpected {:?} for {}", other, s),
+            }
+        }
+        match decode_percent_encoded("%2B%2B$") {
+            Err(FromBase64Error::InvalidBase64Byte(b'$', 6)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(decode_percent_encoded("Zg%3D%3D%3D").unwrap(), b"f");
+
+        // nothing but the escapes is decoded differently
+        assert!("Zg%3D%3D".from_base64().is_err());
+    }
+
+    #[test]
+    fn test_from_base64_consuming() {
+        for &(s, expected, len) in [("", &b""[..], 0), ("Zm9v", b"foo", 4),
+                                    ("Zg==;rest", b"f", 4), ("Zm8=Zm8=", b"fo", 4),
+                                    ("Zg===", b"f", 4), ("Zm9v=", b"foo", 4),
+                                    ("Zm9v;Zm9v", b"foo", 4), ("Zg rest", b"f", 2),
+                                    ("Zm9v\r\nYg==\r\n", b"foob", 10),
+                                    ("Zg=\r\n=x", b"f", 6), ("Zm9v\r\n", b"foo", 4),
+                                    ("-_8=+/8=", b"\xfb\xff", 4), ("\r\nZg", b"f", 4),
+                                    ("$", b"", 0)].iter() {
+            match s.from_base64_consuming() {
+                Ok((ref bytes, l)) if &bytes[..] == expected && l == len => {}
+                other => panic!("unexpected {:?} for {:?}", other, s),
+            }
+        }
+        assert_eq!(b"Zg==".to_vec().from_base64_consuming().unwrap(), (b"f".to_vec(), 4));
+        assert_eq!("Zg==".to_string().from_base64_consuming().unwrap(), (b"f".to_vec(), 4));
+
+        for &(s, expected, got) in [("Zg=", 2, 1), ("Zg=x=", 2, 1), ("Zg=\r\n", 2, 1)].iter() {
+            match s.from_base64_consuming() {
+                Err(FromBase64Error::InvalidBase64Padding { expected: e, got: g })
+                    if e == expected && g == got => {}
+                other => panic!("unexpected {:?} for {:?}", other, s),
+            }
+        }
+        for &s in ["Z", "Zm9vY;", "Z="].iter() {
+            match s.from_base64_consuming() {
+                Err(FromBase64Error::InvalidBase64Length) => {}
+                other => panic!("unexpected {:?} for {:?}", other, s),
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_base64_at() {
+        let frame = b"len=8;Zm9vYmFy;Zm$v";
+        assert_eq!(frame[6..14].from_base64_at(6).unwrap(), b"foobar");
+        match frame[15..].from_base64_at(15) {
+            Err(FromBase64Error::InvalidBase64Byte(b'$', 17)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match "Z".from_base64_at(10) {
+            Err(FromBase64Error::InvalidBase64Length) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tables() {
+        for val in 0..64u8 {
+            let standard = encode_char(val, Standard);
+            let url_safe = encode_char(val, UrlSafe);
+            assert_eq!(standard, STANDARD_CHARS[val as usize]);
+            assert_eq!(url_safe, URLSAFE_CHARS[val as usize]);
+            assert_eq!(DECODE_TABLE[standard as usize], val);
+            assert_eq!(DECODE_TABLE[url_safe as usize], val);
+        }
+        assert_eq!(encode_char(62, Standard), b'+');
+        assert_eq!(encode_char(63, UrlSafe), b'_');
+        assert_eq!(DECODE_TABLE[b'=' as usize], EQUALS_CODE);
+        assert_eq!(DECODE_TABLE[b'\n' as usize], NEWLINE_CODE);
+        assert_eq!(DECODE_TABLE[b'*' as usize], INVALID_CODE);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_base64_bytes_json() {
+        use base64::Base64Bytes;
+        use json;
+
+        let bytes = Base64Bytes::from(b"foobar".to_vec());
+        assert_eq!(json::encode(&bytes).unwrap(), "\"Zm9vYmFy\"");
+        assert_eq!(json::decode::<Base64Bytes>("\"Zm9vYmFy\"").unwrap(), bytes);
+        assert_eq!(&bytes[..3], b"foo");
+
+        assert_eq!(json::encode(&Base64Bytes(vec![])).unwrap(), "\"\"");
+        assert!(json::decode::<Base64Bytes>("\"Zm$=\"").is_err());
+        assert!(json::decode::<Base64Bytes>("[102, 111]").is_err());
+    }
+
     #[test]
     fn test_base64_random() {
         use rand::{thread_rng, Rng};
@@ -486,4 +1987,68 @@ mod tests {
                        v);
         }
     }
+
+    #[test]
+    fn test_base64_round_trip_property() {
+        use quickcheck::quickcheck;
+
+        fn prop(v: Vec<u8>) -> bool {
+            v.to_base64(STANDARD).from_base64().ok() == Some(v.clone()) &&
+                v.to_base64(URL_SAFE).from_base64().ok() == Some(v)
+        }
+        quickcheck(prop as fn(Vec<u8>) -> bool);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_base64_read_write() {
+        use std::io::{self, Read, Write};
+
+        // Hands out its bytes one at a time.
+        struct Trickle<'a>(&'a [u8]);
+        impl<'a> Read for Trickle<'a> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.0.is_empty() || buf.is_empty() {
+                    return Ok(0)
+                }
+                buf[0] = self.0[0];
+                self.0 = &self.0[1..];
+                Ok(1)
+            }
+        }
+
+        let data: Vec<u8> = (0..5000u32).map(|i| (i * 7 + i / 13) as u8).collect();
+        for &config in &[STANDARD, URL_SAFE, MIME, PEM] {
+            for &len in &[0, 1, 2, 3, 4, 57, 58, 100, 5000] {
+                let input = &data[..len];
+                let expected = input.to_base64(config);
+
+                let mut encoded = String::new();
+                Base64Read::new(input, config).read_to_string(&mut encoded).unwrap();
+                assert_eq!(encoded, expected);
+
+                let mut encoded = String::new();
+                Base64Read::new(Trickle(input), config)
+                    .read_to_string(&mut encoded).unwrap();
+                assert_eq!(encoded, expected);
+
+                for &size in &[1, 2, 5, 1000] {
+                    let mut writer = Base64Write::new(Vec::new(), config);
+                    for chunk in input.chunks(size) {
+                        writer.write_all(chunk).unwrap();
+                    }
+                    let encoded = writer.finish().unwrap();
+                    assert_eq!(String::from_utf8(encoded).unwrap(), expected);
+                }
+            }
+        }
+
+        let mut reader = Base64Read::new(&b"foo"[..], STANDARD);
+        let mut buf = [0u8; 3];
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf, b"Zm9");
+        assert_eq!(reader.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], b'v');
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    }
 }
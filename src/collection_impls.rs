@@ -97,6 +97,7 @@ impl<
     }
 }
 
+/// Elements are emitted as a sequence in ascending order.
 impl<
     T: Encodable + Ord
 > Encodable for BTreeSet<T> {
@@ -112,6 +113,7 @@ impl<
     }
 }
 
+/// Duplicate elements in the decoded sequence are silently merged.
 impl<
     T: Decodable + Ord
 > Decodable for BTreeSet<T> {
@@ -160,6 +162,7 @@ impl<K, V> Decodable for HashMap<K, V>
     }
 }
 
+/// Elements are emitted as a sequence in an unspecified order.
 impl<T> Encodable for HashSet<T> where T: Encodable + Hash + Eq {
     fn encode<E: Encoder>(&self, s: &mut E) -> Result<(), E::Error> {
         s.emit_seq(self.len(), |s| {
@@ -173,6 +176,7 @@ impl<T> Encodable for HashSet<T> where T: Encodable + Hash + Eq {
     }
 }
 
+/// Duplicate elements in the decoded sequence are silently merged.
 impl<T> Decodable for HashSet<T> where T: Decodable + Hash + Eq, {
     fn decode<D: Decoder>(d: &mut D) -> Result<HashSet<T>, D::Error> {
         d.read_seq(|d, len| {
@@ -14,10 +14,19 @@
 
 pub use self::FromHexError::*;
 
-use std::fmt;
-use std::error;
+#[cfg(any(feature = "std", test))] use std::fmt;
+#[cfg(not(any(feature = "std", test)))] use core::fmt;
+#[cfg(feature = "std")] use std::error;
+#[cfg(feature = "std")] use std::io;
+#[cfg(feature = "std")] use std::borrow::Cow;
+#[cfg(feature = "std")] use std::ops::Deref;
+#[cfg(all(feature = "alloc", not(feature = "std")))] use alloc::string::String;
+#[cfg(all(feature = "alloc", not(feature = "std")))] use alloc::vec::Vec;
+
+#[cfg(feature = "std")] use {Decodable, Decoder, Encodable, Encoder};
 
 /// A trait for converting a value to hexadecimal encoding
+#[cfg(any(feature = "std", feature = "alloc"))]
 pub trait ToHex {
     /// Converts the value of `self` to a hex value, returning the owned
     /// string.
@@ -26,6 +35,7 @@ pub trait ToHex {
 
 static CHARS: &'static[u8] = b"0123456789abcdef";
 
+#[cfg(any(feature = "std", feature = "alloc"))]
 impl ToHex for [u8] {
     /// Turn a vector of `u8` bytes into a hexadecimal string.
     ///
@@ -41,25 +51,161 @@ impl ToHex for [u8] {
     /// }
     /// ```
     fn to_hex(&self) -> String {
-        let mut v = Vec::with_capacity(self.len() * 2);
-        for &byte in self.iter() {
-            v.push(CHARS[(byte >> 4) as usize]);
-            v.push(CHARS[(byte & 0xf) as usize]);
-        }
+        iter_to_hex(self.iter().cloned())
+    }
+}
+
+#[cfg(any(feature = "std", feature = "alloc"))]
+fn push_hex(v: &mut Vec<u8>, byte: u8) {
+    v.push(CHARS[(byte >> 4) as usize]);
+    v.push(CHARS[(byte & 0xf) as usize]);
+}
+
+/// Turns the bytes of an iterator into a hexadecimal string, as `to_hex`
+/// does for a slice, without collecting them first.
+///
+/// # Example
+///
+/// ```rust
+/// use rustc_serialize::hex::iter_to_hex;
+///
+/// assert_eq!(iter_to_hex((0..4).map(|i| i * 5)), "00050a0f");
+/// ```
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub fn iter_to_hex<I: IntoIterator<Item = u8>>(bytes: I) -> String {
+    let bytes = bytes.into_iter();
+    let mut v = Vec::with_capacity(bytes.size_hint().0 * 2);
+    for byte in bytes {
+        push_hex(&mut v, byte);
+    }
 
-        unsafe {
-            String::from_utf8_unchecked(v)
+    unsafe {
+        String::from_utf8_unchecked(v)
+    }
+}
+
+/// Hex encodes everything `r` yields, writing it to `w` as it comes, and
+/// returns the number of bytes read.
+///
+/// The output is the same as that of `to_hex` on all the bytes at once.
+///
+/// # Example
+///
+/// ```rust
+/// use rustc_serialize::hex::encode_reader;
+///
+/// let mut out = Vec::new();
+/// assert_eq!(encode_reader(&b"foobar"[..], &mut out).unwrap(), 6);
+/// assert_eq!(out, b"666f6f626172");
+/// ```
+#[cfg(feature = "std")]
+pub fn encode_reader<R: io::Read, W: io::Write>(mut r: R, mut w: W) -> io::Result<u64> {
+    let mut buf = [0; 4096];
+    let mut out = Vec::with_capacity(buf.len() * 2);
+    let mut total = 0;
+    loop {
+        let n = match r.read(&mut buf) {
+            Ok(0) => return Ok(total),
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        out.clear();
+        for &byte in buf[..n].iter() {
+            push_hex(&mut out, byte);
         }
+        try!(w.write_all(&out));
+        total += n as u64;
     }
 }
 
+#[cfg(any(feature = "std", feature = "alloc"))]
 impl<'a, T: ?Sized + ToHex> ToHex for &'a T {
     fn to_hex(&self) -> String {
         (**self).to_hex()
     }
 }
 
+/// Encodes `input` as lowercase hex.
+///
+/// This is `input.to_hex()`, without importing `ToHex`.
+///
+/// # Example
+///
+/// ```rust
+/// use rustc_serialize::hex;
+///
+/// assert_eq!(hex::encode(&[0x0f, 0xa0]), "0fa0");
+/// ```
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub fn encode(input: &[u8]) -> String {
+    input.to_hex()
+}
+
+/// Formats `bytes` for reading, the way `hexdump -C` does, 16 bytes a line.
+///
+/// Each line starts with the offset of its first byte, followed by the bytes
+/// in hex, in groups of 8, and then as ASCII, with `.` standing for
+/// characters which aren't printable. A last line holds the length of the
+/// input. Unlike `hexdump`, repeated lines are all written out.
+///
+/// # Example
+///
+/// ```rust
+/// use rustc_serialize::hex;
+///
+/// assert_eq!(hex::dump(b"Hello, World!\n\x00\x01\x02"),
+///            "00000000  48 65 6c 6c 6f 2c 20 57  6f 72 6c 64 21 0a 00 01  \
+///             |Hello, World!...|\n\
+///             00000010  02                                                \
+///             |.|\n\
+///             00000011\n");
+/// ```
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub fn dump(bytes: &[u8]) -> String {
+    dump_width(bytes, 16)
+}
+
+/// Formats `bytes` as `dump` does, with `bytes_per_line` bytes a line.
+///
+/// # Panics
+///
+/// Panics if `bytes_per_line` is 0.
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub fn dump_width(bytes: &[u8], bytes_per_line: usize) -> String {
+    assert!(bytes_per_line != 0, "bytes per line must not be 0");
+    // two columns and a space for each byte, a space between groups
+    let hex_width = bytes_per_line * 3 + (bytes_per_line - 1) / 8;
+    let lines = (bytes.len() + bytes_per_line - 1) / bytes_per_line;
+    let mut out = String::with_capacity((lines + 1) * (hex_width + bytes_per_line + 14));
+    for (i, line) in bytes.chunks(bytes_per_line).enumerate() {
+        let _ = fmt::Write::write_fmt(&mut out, format_args!("{:08x}  ", i * bytes_per_line));
+        let start = out.len();
+        for (j, &byte) in line.iter().enumerate() {
+            if j != 0 && j % 8 == 0 {
+                out.push(' ');
+            }
+            out.push(CHARS[(byte >> 4) as usize] as char);
+            out.push(CHARS[(byte & 0xf) as usize] as char);
+            out.push(' ');
+        }
+        while out.len() - start < hex_width {
+            out.push(' ');
+        }
+        out.push_str(" |");
+        for &byte in line.iter() {
+            out.push(if byte >= 0x20 && byte < 0x7f { byte as char } else { '.' });
+        }
+        out.push_str("|\n");
+    }
+    if !bytes.is_empty() {
+        let _ = fmt::Write::write_fmt(&mut out, format_args!("{:08x}\n", bytes.len()));
+    }
+    out
+}
+
 /// A trait for converting hexadecimal encoded values
+#[cfg(any(feature = "std", feature = "alloc"))]
 pub trait FromHex {
     /// Converts the value of `self`, interpreted as hexadecimal encoded data,
     /// into an owned vector of bytes, returning the vector.
@@ -71,8 +217,9 @@ pub trait FromHex {
 pub enum FromHexError {
     /// The input contained a character not part of the hex format
     InvalidHexCharacter(char, usize),
-    /// The input had an invalid length
-    InvalidHexLength,
+    /// The input had an invalid length, i.e. the contained number of hex
+    /// digits (not counting whitespace) was odd
+    InvalidHexLength(usize),
 }
 
 impl fmt::Debug for FromHexError {
@@ -80,16 +227,18 @@ impl fmt::Debug for FromHexError {
         match *self {
             InvalidHexCharacter(ch, idx) =>
                 write!(f, "Invalid character '{}' at position {}", ch, idx),
-            InvalidHexLength => write!(f, "Invalid input length"),
+            InvalidHexLength(len) =>
+                write!(f, "Invalid input length {} (must be even)", len),
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl error::Error for FromHexError {
     fn description(&self) -> &str {
         match *self {
             InvalidHexCharacter(_, _) => "invalid character",
-            InvalidHexLength => "invalid length",
+            InvalidHexLength(_) => "invalid length",
         }
     }
 }
@@ -100,6 +249,7 @@ impl fmt::Display for FromHexError {
     }
 }
 
+#[cfg(any(feature = "std", feature = "alloc"))]
 impl FromHex for str {
     /// Convert any hexadecimal encoded string (literal, `@`, `&`, or `~`)
     /// to the byte values it encodes.
@@ -127,55 +277,305 @@ impl FromHex for str {
     fn from_hex(&self) -> Result<Vec<u8>, FromHexError> {
         // This may be an overestimate if there is any whitespace
         let mut b = Vec::with_capacity(self.len() / 2);
-        let mut modulus = 0;
-        let mut buf = 0;
-
-        for (idx, byte) in self.bytes().enumerate() {
-            buf <<= 4;
-
-            match byte {
-                b'A'...b'F' => buf |= byte - b'A' + 10,
-                b'a'...b'f' => buf |= byte - b'a' + 10,
-                b'0'...b'9' => buf |= byte - b'0',
-                b' '|b'\r'|b'\n'|b'\t' => {
-                    buf >>= 4;
-                    continue
-                }
-                _ => {
-                    let ch = self[idx..].chars().next().unwrap();
-                    return Err(InvalidHexCharacter(ch, idx))
-                }
-            }
+        try!(decode_with(self, |byte| b.push(byte)));
+        Ok(b)
+    }
+}
 
-            modulus += 1;
-            if modulus == 2 {
-                modulus = 0;
-                b.push(buf);
+/// Hex encodes `input`, writing the output to the start of `output` instead
+/// of allocating a new string.
+///
+/// Returns the number of bytes written, which is always `input.len() * 2`
+/// and the same output `to_hex` would produce, or `None` if `output` is
+/// shorter than that.
+///
+/// # Example
+///
+/// ```rust
+/// use rustc_serialize::hex::encode_to_slice;
+///
+/// let mut buf = [0u8; 8];
+/// assert_eq!(encode_to_slice(&[0xde, 0xad, 0xbe, 0xef], &mut buf), Some(8));
+/// assert_eq!(&buf, b"deadbeef");
+/// ```
+pub fn encode_to_slice(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    if output.len() / 2 < input.len() {
+        return None;
+    }
+    for (&byte, out) in input.iter().zip(output.chunks_mut(2)) {
+        out[0] = CHARS[(byte >> 4) as usize];
+        out[1] = CHARS[(byte & 0xf) as usize];
+    }
+    Some(input.len() * 2)
+}
+
+/// Decodes the hex in `input`, writing the bytes it encodes to the start of
+/// `output` instead of allocating a new vector.
+///
+/// Returns the number of bytes written, which are the same bytes `from_hex`
+/// would return.
+///
+/// # Panics
+///
+/// Panics if `output` is shorter than `input.len() / 2`, the most bytes
+/// `input` can decode to.
+///
+/// # Example
+///
+/// ```rust
+/// use rustc_serialize::hex::decode_to_slice;
+///
+/// let mut buf = [0u8; 4];
+/// assert_eq!(decode_to_slice("DEAD beef", &mut buf).unwrap(), 4);
+/// assert_eq!(buf, [0xde, 0xad, 0xbe, 0xef]);
+/// ```
+pub fn decode_to_slice(input: &str, output: &mut [u8]) -> Result<usize, FromHexError> {
+    assert!(output.len() >= input.len() / 2,
+            "output buffer too short for the decoded hex");
+    let mut written = 0;
+    try!(decode_with(input, |byte| {
+        output[written] = byte;
+        written += 1;
+    }));
+    Ok(written)
+}
+
+// Decodes `input`, handing each decoded byte to `push` in order.
+fn decode_with<F: FnMut(u8)>(input: &str, mut push: F) -> Result<(), FromHexError> {
+    let mut modulus = 0;
+    let mut digits = 0;
+    let mut buf = 0;
+
+    for (idx, byte) in input.bytes().enumerate() {
+        buf <<= 4;
+
+        match byte {
+            b'A'...b'F' => buf |= byte - b'A' + 10,
+            b'a'...b'f' => buf |= byte - b'a' + 10,
+            b'0'...b'9' => buf |= byte - b'0',
+            b' '|b'\r'|b'\n'|b'\t' => {
+                buf >>= 4;
+                continue
+            }
+            _ => {
+                let ch = input[idx..].chars().next().unwrap();
+                return Err(InvalidHexCharacter(ch, idx))
             }
         }
 
-        match modulus {
-            0 => Ok(b.into_iter().collect()),
-            _ => Err(InvalidHexLength),
+        digits += 1;
+        modulus += 1;
+        if modulus == 2 {
+            modulus = 0;
+            push(buf);
         }
     }
+
+    match modulus {
+        0 => Ok(()),
+        _ => Err(InvalidHexLength(digits)),
+    }
 }
 
+#[cfg(any(feature = "std", feature = "alloc"))]
 impl<'a, T: ?Sized + FromHex> FromHex for &'a T {
     fn from_hex(&self) -> Result<Vec<u8>, FromHexError> {
         (**self).from_hex()
     }
 }
 
+/// Decodes the hex in `input`, in either case.
+///
+/// This is `input.from_hex()`, without importing `FromHex`.
+///
+/// # Example
+///
+/// ```rust
+/// use rustc_serialize::hex;
+///
+/// assert_eq!(hex::decode("0fA0").unwrap(), [0x0f, 0xa0]);
+/// ```
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub fn decode(input: &str) -> Result<Vec<u8>, FromHexError> {
+    input.from_hex()
+}
+
+/// A byte buffer which is serialized as a lowercase hex string rather than
+/// as a sequence of numbers. Either case is accepted when decoding.
+///
+/// `Cow<[u8]>` itself encodes as a sequence of numbers, like any other
+/// `Cow`; convert it into a `HexBytes` for the hex form.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::hex::HexBytes;
+/// use rustc_serialize::json;
+///
+/// fn main () {
+///     let bytes = HexBytes::from(vec![0xde, 0xad, 0xbe, 0xef]);
+///     assert_eq!(json::encode(&bytes).unwrap(), "\"deadbeef\"");
+///     let decoded: HexBytes = json::decode("\"DEADBEEF\"").unwrap();
+///     assert_eq!(decoded, bytes);
+/// }
+/// ```
+#[cfg(feature = "std")]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct HexBytes(pub Vec<u8>);
+
+#[cfg(feature = "std")]
+impl Deref for HexBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<Vec<u8>> for HexBytes {
+    fn from(v: Vec<u8>) -> HexBytes {
+        HexBytes(v)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> From<Cow<'a, [u8]>> for HexBytes {
+    fn from(v: Cow<'a, [u8]>) -> HexBytes {
+        HexBytes(v.into_owned())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Encodable for HexBytes {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_str(&self.0.to_hex())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Decodable for HexBytes {
+    fn decode<D: Decoder>(d: &mut D) -> Result<HexBytes, D::Error> {
+        let s = try!(d.read_str());
+        match s.from_hex() {
+            Ok(v) => Ok(HexBytes(v)),
+            Err(e) => Err(d.error(&e.to_string())),
+        }
+    }
+}
+
+// The slice based API works without `std` or `alloc`, so its tests do too.
 #[cfg(test)]
+mod slice_tests {
+    use hex::{encode_to_slice, decode_to_slice};
+    use hex::FromHexError::*;
+
+    #[test]
+    pub fn test_encode_to_slice() {
+        let mut buf = [b'x'; 14];
+        assert_eq!(encode_to_slice(b"foobar", &mut buf), Some(12));
+        assert_eq!(&buf, b"666f6f626172xx");
+        assert_eq!(encode_to_slice(b"", &mut []), Some(0));
+        assert_eq!(encode_to_slice(b"foobar", &mut buf[..11]), None);
+    }
+
+    #[test]
+    pub fn test_decode_to_slice() {
+        let mut buf = [0; 7];
+        assert_eq!(decode_to_slice("666f 6F62\n6172", &mut buf).unwrap(), 6);
+        assert_eq!(&buf[..6], b"foobar");
+        assert_eq!(decode_to_slice("", &mut []).unwrap(), 0);
+        match decode_to_slice("66y6", &mut buf) {
+            Err(InvalidHexCharacter('y', 2)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match decode_to_slice("666", &mut buf) {
+            Err(InvalidHexLength(3)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn test_decode_to_slice_short_output() {
+        let _ = decode_to_slice("666f6f", &mut [0; 2]);
+    }
+}
+
+#[cfg(all(test, any(feature = "std", feature = "alloc")))]
 mod tests {
-    use hex::{FromHex, ToHex};
+    use hex::{FromHex, ToHex, iter_to_hex, dump, dump_width};
+    #[cfg(feature = "std")] use hex::encode_reader;
+    #[cfg(feature = "std")] use std::io::{self, Read};
+    use hex::FromHexError::*;
 
     #[test]
     pub fn test_to_hex() {
         assert_eq!("foobar".as_bytes().to_hex(), "666f6f626172");
     }
 
+    #[test]
+    pub fn test_iter_to_hex() {
+        assert_eq!(iter_to_hex("foobar".bytes()), "666f6f626172");
+        assert_eq!(iter_to_hex(vec![]), "");
+        let all: Vec<u8> = (0..256).map(|i| i as u8).collect();
+        assert_eq!(iter_to_hex(all.iter().cloned().filter(|b| b % 3 == 0)),
+                   all.iter().cloned().filter(|b| b % 3 == 0).collect::<Vec<_>>().to_hex());
+    }
+
+    #[test]
+    pub fn test_encode_decode_functions() {
+        use hex::{encode, decode};
+
+        assert_eq!(encode(b"foobar"), "666f6f626172");
+        assert_eq!(encode(&[0xAB, 0xCD]), "abcd");
+        assert_eq!(encode(&[]), "");
+        assert_eq!(decode("666F6f626172").unwrap(), b"foobar");
+        match decode("66y6") {
+            Err(InvalidHexCharacter('y', 2)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    // Yields its bytes a few at a time, and is interrupted in between.
+    #[cfg(feature = "std")]
+    struct Trickle<'a> {
+        bytes: &'a [u8],
+        interrupt: bool,
+    }
+
+    #[cfg(feature = "std")]
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "try again"));
+            }
+            let n = (&self.bytes[..::std::cmp::min(3, self.bytes.len())]).read(buf).unwrap();
+            self.bytes = &self.bytes[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    pub fn test_encode_reader() {
+        let bytes: Vec<u8> = (0..10000).map(|i| (i * 7) as u8).collect();
+        let mut out = Vec::new();
+        assert_eq!(encode_reader(&bytes[..], &mut out).unwrap(), 10000);
+        assert_eq!(out, bytes.to_hex().into_bytes());
+
+        let mut out = Vec::new();
+        let trickle = Trickle { bytes: b"foobar", interrupt: false };
+        assert_eq!(encode_reader(trickle, &mut out).unwrap(), 6);
+        assert_eq!(out, b"666f6f626172");
+
+        let mut out = Vec::new();
+        assert_eq!(encode_reader(io::empty(), &mut out).unwrap(), 0);
+        assert!(out.is_empty());
+    }
+
     #[test]
     pub fn test_from_hex_okay() {
         assert_eq!("666f6f626172".from_hex().unwrap(),
@@ -190,11 +590,31 @@ mod tests {
         assert!("66 6".from_hex().is_err());
     }
 
+    #[test]
+    pub fn test_from_hex_odd_len_reports_length() {
+        match "66 6".from_hex() {
+            Err(InvalidHexLength(3)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert_eq!("abcde".from_hex().unwrap_err().to_string(),
+                   "Invalid input length 5 (must be even)");
+    }
+
     #[test]
     pub fn test_from_hex_invalid_char() {
         assert!("66y6".from_hex().is_err());
     }
 
+    #[test]
+    pub fn test_from_hex_invalid_char_reports_position() {
+        match "66y6".from_hex() {
+            Err(InvalidHexCharacter('y', 2)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert_eq!("66y6".from_hex().unwrap_err().to_string(),
+                   "Invalid character 'y' at position 2");
+    }
+
     #[test]
     pub fn test_from_hex_ignores_whitespace() {
         assert_eq!("666f 6f6\r\n26172 ".from_hex().unwrap(),
@@ -218,4 +638,60 @@ mod tests {
                        ii);
         }
     }
+
+    #[test]
+    pub fn test_dump() {
+        assert_eq!(dump(b""), "");
+        assert_eq!(dump(b"Hello, World!\n\x00\x01\x02"),
+                   "00000000  48 65 6c 6c 6f 2c 20 57  6f 72 6c 64 21 0a 00 01  \
+                    |Hello, World!...|\n\
+                    00000010  02                                                \
+                    |.|\n\
+                    00000011\n");
+        assert_eq!(dump_width(b"abcde\x7f", 4),
+                   "00000000  61 62 63 64  |abcd|\n\
+                    00000004  65 7f        |e.|\n\
+                    00000006\n");
+        assert_eq!(dump_width(b"0123456789", 10),
+                   "00000000  30 31 32 33 34 35 36 37  38 39  |0123456789|\n\
+                    0000000a\n");
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn test_dump_zero_width() {
+        dump_width(b"abc", 0);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    pub fn test_hex_bytes_json() {
+        use hex::HexBytes;
+        use json;
+        use std::borrow::Cow;
+
+        let bytes = HexBytes::from(b"foo\xff".to_vec());
+        assert_eq!(json::encode(&bytes).unwrap(), "\"666f6fff\"");
+        assert_eq!(json::decode::<HexBytes>("\"666f6fff\"").unwrap(), bytes);
+        assert_eq!(json::decode::<HexBytes>("\"666F6FFF\"").unwrap(), bytes);
+        assert_eq!(&bytes[..3], b"foo");
+
+        let borrowed: Cow<[u8]> = Cow::Borrowed(b"foo\xff");
+        assert_eq!(HexBytes::from(borrowed), bytes);
+
+        assert_eq!(json::encode(&HexBytes(vec![])).unwrap(), "\"\"");
+        assert!(json::decode::<HexBytes>("\"666\"").is_err());
+        assert!(json::decode::<HexBytes>("\"zz\"").is_err());
+        assert!(json::decode::<HexBytes>("[102, 111]").is_err());
+    }
+
+    #[test]
+    pub fn test_hex_round_trip_property() {
+        use quickcheck::quickcheck;
+
+        fn prop(v: Vec<u8>) -> bool {
+            v.to_hex().from_hex().ok() == Some(v)
+        }
+        quickcheck(prop as fn(Vec<u8>) -> bool);
+    }
 }
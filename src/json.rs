@@ -244,20 +244,27 @@ use self::DecoderError::*;
 use self::ParserState::*;
 use self::InternalStackElement::*;
 
-use std::collections::{HashMap, BTreeMap};
+use std::cell::Cell;
+use std::cmp::{self, Ordering};
+use std::collections::{HashMap, BTreeMap, BTreeSet};
+use std::hash::{Hash, Hasher};
 use std::error::Error as StdError;
 use std::i64;
+#[cfg(feature = "i128")]
+use std::{i128, u64};
 use std::io::prelude::*;
-use std::mem::swap;
+use std::mem::{self, swap};
 use std::ops::Index;
 use std::str::FromStr;
 use std::string;
 use std::{char, f64, fmt, io, str};
 
-use Encodable;
+use {Encodable, ValueKind};
+use base64::{self, Base64Bytes, FromBase64, ToBase64};
+use hex::{HexBytes, ToHex};
 
 /// Represents a json value
-#[derive(Clone, PartialEq, PartialOrd, Debug)]
+#[derive(Clone, Debug)]
 pub enum Json {
     I64(i64),
     U64(u64),
@@ -292,12 +299,27 @@ pub enum ErrorCode {
     TrailingComma,
     InvalidEscape,
     InvalidUnicodeCodePoint,
+    /// No longer reported, lone surrogates are reported as `LoneSurrogate`.
     LoneLeadingSurrogateInHexEscape,
+    /// No longer reported, lone surrogates are reported as `LoneSurrogate`.
     UnexpectedEndOfHexEscape,
     UnrecognizedHex,
     NotFourDigit,
     ControlCharacterInString,
     NotUtf8,
+    /// A number had more digits than the parser allows, see
+    /// `Parser::set_max_number_len`.
+    NumberTooLong,
+    /// A `\u` escape of a UTF-16 surrogate wasn't part of a pair, see
+    /// `Parser::set_replace_lone_surrogates`.
+    LoneSurrogate,
+    /// Arrays and objects were nested deeper than the parser allows, see
+    /// `Parser::set_max_depth`.
+    NestingTooDeep,
+    /// A key was repeated within an object, see `Builder::set_strict_keys`.
+    DuplicateKey,
+    /// The input was longer than allowed, see `DecodeConfig::max_bytes`.
+    InputTooLong,
 }
 
 #[derive(Debug)]
@@ -321,6 +343,7 @@ impl PartialEq for ParserError {
 // Builder and Parser have the same errors.
 pub type BuilderError = ParserError;
 
+/// The errors that can arise while decoding.
 #[derive(PartialEq, Debug)]
 pub enum DecoderError {
     ParseError(ParserError),
@@ -329,12 +352,48 @@ pub enum DecoderError {
     UnknownVariantError(string::String),
     ApplicationError(string::String),
     EOF,
+    /// A key of an object is claimed both by a struct and a struct
+    /// flattened into it, or by two flattened structs.
+    ConflictingFieldError(string::String),
 }
 
+/// The errors that can arise while encoding.
 #[derive(Copy, Debug)]
 pub enum EncoderError {
     FmtError(fmt::Error),
     BadHashmapKey,
+    /// A flattened struct field was not encoded as a struct.
+    BadFlattenedField,
+    /// With `EnumTagging::Internal`, a tuple variant does not hold exactly
+    /// one struct.
+    BadTaggedVariant,
+    /// An element or field was emitted outside of a sequence, struct or map
+    /// to hold it, or a map value without a key before it.
+    MisplacedValue,
+}
+
+/// The reasons a lookup through `Json::get` or `Json::get_index` can fail.
+#[derive(Clone, PartialEq, Debug)]
+pub enum LookupError {
+    /// A key was looked up in a value which isn't an object.
+    ExpectedObject,
+    /// An index was looked up in a value which isn't an array.
+    ExpectedArray,
+    /// The object has no such key.
+    MissingKey(string::String),
+    /// The index, given first, is past the end of the array, whose length
+    /// comes second.
+    IndexOutOfBounds(usize, usize),
+}
+
+/// The error of the `TryFrom` conversions out of `Json`, when the value
+/// isn't of the type converted to, or a number is out of its range.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct JsonTypeMismatch {
+    /// The type converted to, such as `"i64"`.
+    pub expected: &'static str,
+    /// The variant of the value, such as `"U64"`.
+    pub got: &'static str,
 }
 
 impl PartialEq for EncoderError {
@@ -342,6 +401,9 @@ impl PartialEq for EncoderError {
         match (*self, *other) {
             (EncoderError::FmtError(_), EncoderError::FmtError(_)) => true,
             (EncoderError::BadHashmapKey, EncoderError::BadHashmapKey) => true,
+            (EncoderError::BadFlattenedField, EncoderError::BadFlattenedField) => true,
+            (EncoderError::BadTaggedVariant, EncoderError::BadTaggedVariant) => true,
+            (EncoderError::MisplacedValue, EncoderError::MisplacedValue) => true,
             _ => false,
         }
     }
@@ -372,6 +434,11 @@ pub fn error_str(error: ErrorCode) -> &'static str {
         InvalidUnicodeCodePoint => "invalid Unicode code point",
         LoneLeadingSurrogateInHexEscape => "lone leading surrogate in hex escape",
         UnexpectedEndOfHexEscape => "unexpected end of hex escape",
+        NumberTooLong => "number too long",
+        LoneSurrogate => "lone surrogate in hex escape",
+        NestingTooDeep => "nesting too deep",
+        DuplicateKey => "duplicate key",
+        InputTooLong => "input too long",
     }
 }
 
@@ -386,6 +453,233 @@ pub fn decode<T: ::Decodable>(s: &str) -> DecodeResult<T> {
     ::Decodable::decode(&mut decoder)
 }
 
+/// Shortcut function to decode a JSON `&str` into an object in a single
+/// pass, without building the `Json` value `decode` goes through.
+///
+/// Values are decoded as the parser reads them. Only what can't be decoded
+/// in document order is built into `Json` values along the way: the members
+/// of an object coming before the struct field being looked for, enum
+/// values, and single scalars.
+///
+/// The result is the same as with `decode`, except that errors are reported
+/// as they are met: a document with both a syntax error and a value of the
+/// wrong type may fail with a different error, and a struct field whose key
+/// is repeated in the object takes the first value rather than the last.
+pub fn decode_str<T: ::Decodable>(s: &str) -> DecodeResult<T> {
+    let mut decoder = StreamDecoder::new(s);
+    let value = try!(::Decodable::decode(&mut decoder));
+    try!(decoder.finish());
+    Ok(value)
+}
+
+/// Shortcut function to decode an already parsed `Json` value into an object,
+/// e.g. after patching it up.
+///
+/// ```
+/// use rustc_serialize::json::{self, Json};
+///
+/// let mut json = Json::from_str("{\"xs\": [1, 2]}").unwrap();
+/// if let Json::Object(ref mut obj) = json {
+///     obj.insert("xs".to_string(), Json::Array(vec![Json::U64(3)]));
+/// }
+/// let decoded: ::std::collections::BTreeMap<String, Vec<u8>> =
+///     json::from_json(json).unwrap();
+/// assert_eq!(decoded["xs"], [3]);
+/// ```
+pub fn from_json<T: ::Decodable>(json: Json) -> DecodeResult<T> {
+    let mut decoder = Decoder::new(json);
+    ::Decodable::decode(&mut decoder)
+}
+
+/// Shortcut function to encode a `T` into a `Json` value with a
+/// `JsonValueEncoder`, e.g. to patch it up before decoding it back with
+/// `from_value`.
+///
+/// This fails where `encode` would, such as on a map whose keys aren't
+/// strings or numbers.
+///
+/// ```
+/// use rustc_serialize::json::{self, Json};
+///
+/// let mut json = json::to_value(&(1, "a")).unwrap();
+/// json.as_array_mut().unwrap()[0] = Json::U64(2);
+/// let value: (u8, String) = json::from_value(json).unwrap();
+/// assert_eq!(value, (2, "a".to_string()));
+/// ```
+pub fn to_value<T: ::Encodable>(object: &T) -> EncodeResult<Json> {
+    let mut encoder = JsonValueEncoder::new();
+    try!(object.encode(&mut encoder));
+    Ok(encoder.finish())
+}
+
+/// Shortcut function to decode a `Json` value into an object. This is
+/// `from_json`, under a name pairing it with `to_value`.
+pub fn from_value<T: ::Decodable>(json: Json) -> DecodeResult<T> {
+    from_json(json)
+}
+
+/// Shortcut function to decode a JSON object in a `&str` into its members,
+/// in the order they appear in the document.
+///
+/// Duplicate keys are all kept. Nested objects are decoded as plain
+/// `Json::Object` values.
+pub fn decode_pairs(s: &str) -> DecodeResult<Vec<(string::String, Json)>> {
+    let mut builder = Builder::new(s.chars());
+    builder.build_pairs()
+}
+
+/// Options for `from_str_with_config`, set with chained calls starting from
+/// `DecodeConfig::new()`. Options left alone keep the behavior of `decode`.
+///
+/// # Example
+///
+/// ```rust
+/// use rustc_serialize::json::{self, DecodeConfig};
+///
+/// let config = DecodeConfig::new().max_depth(2).allow_trailing_comma(true);
+/// let v: Vec<Vec<u32>> = json::from_str_with_config("[[1], [2, 3],]", &config).unwrap();
+/// assert_eq!(v, [vec![1], vec![2, 3]]);
+/// assert!(json::from_str_with_config::<Vec<Vec<Vec<u32>>>>("[[[1]]]", &config).is_err());
+/// ```
+#[derive(Clone, Debug)]
+pub struct DecodeConfig {
+    max_depth: Option<usize>,
+    max_bytes: Option<usize>,
+    max_number_len: usize,
+    strict_keys: bool,
+    allow_trailing_comma: bool,
+    allow_comments: bool,
+    relaxed: bool,
+    case_insensitive_literals: bool,
+    replace_lone_surrogates: bool,
+    enum_tagging: EnumTagging,
+}
+
+impl DecodeConfig {
+    /// Creates a configuration decoding as `decode` does.
+    pub fn new() -> DecodeConfig {
+        DecodeConfig {
+            max_depth: None,
+            max_bytes: None,
+            max_number_len: DEFAULT_MAX_NUMBER_LEN,
+            strict_keys: false,
+            allow_trailing_comma: false,
+            allow_comments: false,
+            relaxed: false,
+            case_insensitive_literals: false,
+            replace_lone_surrogates: false,
+            enum_tagging: EnumTagging::VariantFields,
+        }
+    }
+
+    /// Limits how deeply arrays and objects may be nested, see
+    /// `Parser::set_max_depth`.
+    pub fn max_depth(mut self, max: usize) -> DecodeConfig {
+        self.max_depth = Some(max);
+        self
+    }
+
+    /// Limits the length of the input, in bytes. Longer input is reported
+    /// as `InputTooLong`, at the position of the first byte over the limit,
+    /// without being parsed.
+    pub fn max_bytes(mut self, max: usize) -> DecodeConfig {
+        self.max_bytes = Some(max);
+        self
+    }
+
+    /// Limits the digits of numbers, see `Parser::set_max_number_len`.
+    pub fn max_number_len(mut self, max: usize) -> DecodeConfig {
+        self.max_number_len = max;
+        self
+    }
+
+    /// Rejects keys repeated within an object, see
+    /// `Builder::set_strict_keys`.
+    pub fn strict_keys(mut self, strict: bool) -> DecodeConfig {
+        self.strict_keys = strict;
+        self
+    }
+
+    /// Accepts trailing commas, see `Parser::set_allow_trailing_comma`.
+    pub fn allow_trailing_comma(mut self, allow: bool) -> DecodeConfig {
+        self.allow_trailing_comma = allow;
+        self
+    }
+
+    /// Skips `//` and `/* */` comments, see `Parser::set_allow_comments`.
+    pub fn allow_comments(mut self, allow: bool) -> DecodeConfig {
+        self.allow_comments = allow;
+        self
+    }
+
+    /// Accepts relaxed number forms, see `Parser::set_relaxed`.
+    pub fn relaxed(mut self, relaxed: bool) -> DecodeConfig {
+        self.relaxed = relaxed;
+        self
+    }
+
+    /// Accepts `true`, `false` and `null` in any case, see
+    /// `Parser::set_case_insensitive_literals`.
+    pub fn case_insensitive_literals(mut self, allow: bool) -> DecodeConfig {
+        self.case_insensitive_literals = allow;
+        self
+    }
+
+    /// Replaces lone surrogates rather than failing, see
+    /// `Parser::set_replace_lone_surrogates`.
+    pub fn replace_lone_surrogates(mut self, replace: bool) -> DecodeConfig {
+        self.replace_lone_surrogates = replace;
+        self
+    }
+
+    /// Sets how the variants of enums are read, see
+    /// `Decoder::set_enum_tagging`.
+    pub fn enum_tagging(mut self, tagging: EnumTagging) -> DecodeConfig {
+        self.enum_tagging = tagging;
+        self
+    }
+}
+
+impl Default for DecodeConfig {
+    fn default() -> DecodeConfig {
+        DecodeConfig::new()
+    }
+}
+
+/// Decodes a JSON `&str` into an object as `decode` does, with the options
+/// of `config`.
+pub fn from_str_with_config<T: ::Decodable>(s: &str, config: &DecodeConfig) -> DecodeResult<T> {
+    if let Some(max) = config.max_bytes {
+        if s.len() > max {
+            // Report the position of the first byte over the limit, backing
+            // off over UTF-8 continuation bytes to the start of its char.
+            let mut end = max;
+            while s.as_bytes()[end] & 0xC0 == 0x80 {
+                end -= 1;
+            }
+            let before = &s[..end];
+            let line = before.split('\n').count();
+            let col = before.split('\n').last().map_or(0, |l| l.chars().count()) + 1;
+            return Err(ParseError(SyntaxError(InputTooLong, line, col)));
+        }
+    }
+
+    let mut builder = Builder::new(s.chars());
+    builder.set_max_depth(config.max_depth);
+    builder.set_max_number_len(config.max_number_len);
+    builder.set_strict_keys(config.strict_keys);
+    builder.set_allow_trailing_comma(config.allow_trailing_comma);
+    builder.set_allow_comments(config.allow_comments);
+    builder.set_relaxed(config.relaxed);
+    builder.set_case_insensitive_literals(config.case_insensitive_literals);
+    builder.set_replace_lone_surrogates(config.replace_lone_surrogates);
+    let json = try!(builder.build().map_err(ParseError));
+
+    let mut decoder = Decoder::new(json);
+    decoder.set_enum_tagging(config.enum_tagging.clone());
+    ::Decodable::decode(&mut decoder)
+}
+
 /// Shortcut function to encode a `T` into a JSON `String`
 pub fn encode<T: ::Encodable>(object: &T) -> EncodeResult<string::String> {
     let mut s = String::new();
@@ -396,12 +690,144 @@ pub fn encode<T: ::Encodable>(object: &T) -> EncodeResult<string::String> {
     Ok(s)
 }
 
+/// Shortcut function to encode a `T` into a JSON `String` as with `encode`,
+/// along with its length in bytes.
+pub fn encode_with_len<T: ::Encodable>(object: &T) -> EncodeResult<(string::String, usize)> {
+    let s = try!(encode(object));
+    let len = s.len();
+    Ok((s, len))
+}
+
+/// Shortcut function to encode a `T` as JSON to an `io::Write`, as with
+/// `encode`, returning how many bytes were written.
+///
+/// The JSON is written as it is encoded, so a failure to encode, reported as
+/// an `io::ErrorKind::InvalidInput` error wrapping the `EncoderError`, may
+/// leave part of it written.
+///
+/// ```
+/// use rustc_serialize::json;
+///
+/// let mut out = Vec::new();
+/// assert_eq!(json::to_writer(&mut out, &vec![1, 2]).unwrap(), 5);
+/// assert_eq!(out, b"[1,2]");
+/// ```
+pub fn to_writer<W: io::Write, T: ::Encodable>(writer: &mut W, object: &T) -> io::Result<usize> {
+    let mut counter = CountingWriter { writer: writer, count: 0, error: None };
+    let res = object.encode(&mut Encoder::new(&mut counter));
+    if let Some(e) = counter.error {
+        return Err(e);
+    }
+    match res {
+        Ok(()) => Ok(counter.count),
+        Err(e) => Err(io::Error::new(io::ErrorKind::InvalidInput, e)),
+    }
+}
+
+// Passes what an `Encoder` writes on to an `io::Write`, counting the bytes,
+// and keeping the error if writing fails.
+struct CountingWriter<'a, W: 'a> {
+    writer: &'a mut W,
+    count: usize,
+    error: Option<io::Error>,
+}
+
+impl<'a, W: io::Write> fmt::Write for CountingWriter<'a, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match self.writer.write_all(s.as_bytes()) {
+            Ok(()) => {
+                self.count += s.len();
+                Ok(())
+            }
+            Err(e) => {
+                self.error = Some(e);
+                Err(fmt::Error)
+            }
+        }
+    }
+}
+
+/// Shortcut function to encode a `T` into JSON bytes, as with `encode`.
+pub fn encode_to_bytes<T: ::Encodable>(object: &T) -> EncodeResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    {
+        let mut writer = BytesWriter::new(&mut bytes);
+        let mut encoder = Encoder::new(&mut writer);
+        try!(object.encode(&mut encoder));
+    }
+    Ok(bytes)
+}
+
+/// Shortcut function to count the bytes `encode` would produce for a `T`,
+/// without keeping them around.
+pub fn encoded_len<T: ::Encodable>(object: &T) -> EncodeResult<usize> {
+    let mut counter = ByteCounter::new();
+    {
+        let mut encoder = Encoder::new(&mut counter);
+        try!(object.encode(&mut encoder));
+    }
+    Ok(counter.byte_count())
+}
+
+/// Shortcut function to encode a `T` into a JSON `String` with the keys of
+/// every object sorted lexicographically, e.g. for deterministic output.
+///
+/// Unlike `encode`, which emits struct fields in declaration order and map
+/// entries in iteration order, this produces the same output for equal
+/// values regardless of how they were built.
+pub fn to_string_sorted<T: ::Encodable>(object: &T) -> EncodeResult<string::String> {
+    let mut encoder = JsonValueEncoder::new();
+    try!(object.encode(&mut encoder));
+    Ok(encoder.finish().to_string())
+}
+
+/// Removes all insignificant whitespace from a JSON document.
+///
+/// The document is validated first, and is otherwise left untouched: object
+/// keys keep their order, numbers their spelling and strings their contents.
+pub fn minify(s: &str) -> Result<string::String, ParserError> {
+    for event in Parser::new(s.chars()) {
+        if let Error(e) = event {
+            return Err(e);
+        }
+    }
+
+    let mut out = string::String::with_capacity(s.len());
+    let mut in_string = false;
+    let mut escape = false;
+    for c in s.chars() {
+        if in_string {
+            if escape {
+                escape = false;
+            } else if c == '\\' {
+                escape = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else {
+            match c {
+                ' ' | '\t' | '\n' | '\r' => continue,
+                '"' => in_string = true,
+                _ => {}
+            }
+        }
+        out.push(c);
+    }
+    Ok(out)
+}
+
 impl fmt::Debug for ErrorCode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         error_str(*self).fmt(f)
     }
 }
 
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(error_str(*self))
+    }
+}
+
 impl StdError for DecoderError {
     fn description(&self) -> &str { "decoder error" }
     fn cause(&self) -> Option<&StdError> {
@@ -410,11 +836,30 @@ impl StdError for DecoderError {
             _ => None,
         }
     }
+    #[cfg(feature = "error-source")]
+    fn source(&self) -> Option<&(StdError + 'static)> {
+        match *self {
+            DecoderError::ParseError(ref e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for DecoderError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Debug::fmt(&self, f)
+        match *self {
+            ParseError(ref e) => fmt::Display::fmt(e, f),
+            ExpectedError(ref expected, ref found) => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            MissingFieldError(ref field) => write!(f, "missing field `{}`", field),
+            UnknownVariantError(ref variant) => write!(f, "unknown variant `{}`", variant),
+            ApplicationError(ref msg) => f.write_str(msg),
+            EOF => f.write_str("unexpected end of input"),
+            ConflictingFieldError(ref field) => {
+                write!(f, "field `{}` is wanted by more than one struct", field)
+            }
+        }
     }
 }
 
@@ -426,11 +871,29 @@ impl From<ParserError> for DecoderError {
 
 impl StdError for ParserError {
     fn description(&self) -> &str { "failed to parse json" }
+    fn cause(&self) -> Option<&StdError> {
+        match *self {
+            IoError(ref e) => Some(e),
+            _ => None,
+        }
+    }
+    #[cfg(feature = "error-source")]
+    fn source(&self) -> Option<&(StdError + 'static)> {
+        match *self {
+            IoError(ref e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for ParserError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Debug::fmt(&self, f)
+        match *self {
+            SyntaxError(code, line, col) => {
+                write!(f, "{} at line {} column {}", error_str(code), line, col)
+            }
+            IoError(ref e) => fmt::Display::fmt(e, f),
+        }
     }
 }
 
@@ -442,11 +905,57 @@ impl From<io::Error> for ParserError {
 
 impl StdError for EncoderError {
     fn description(&self) -> &str { "encoder error" }
+    #[cfg(feature = "error-source")]
+    fn source(&self) -> Option<&(StdError + 'static)> {
+        match *self {
+            EncoderError::FmtError(ref e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for EncoderError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Debug::fmt(&self, f)
+        match *self {
+            EncoderError::FmtError(_) => f.write_str("failed to write json"),
+            EncoderError::BadHashmapKey => f.write_str("map key is not a string or number"),
+            EncoderError::BadFlattenedField => {
+                f.write_str("flattened field is not encoded as a struct")
+            }
+            EncoderError::BadTaggedVariant => {
+                f.write_str("internally tagged variant does not hold a single struct")
+            }
+            EncoderError::MisplacedValue => {
+                f.write_str("value emitted outside of a sequence, struct or map")
+            }
+        }
+    }
+}
+
+impl StdError for LookupError {
+    fn description(&self) -> &str { "json lookup error" }
+}
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LookupError::ExpectedObject => write!(f, "expected an object"),
+            LookupError::ExpectedArray => write!(f, "expected an array"),
+            LookupError::MissingKey(ref key) => write!(f, "missing key `{}`", key),
+            LookupError::IndexOutOfBounds(index, len) => {
+                write!(f, "index {} out of bounds for an array of length {}", index, len)
+            }
+        }
+    }
+}
+
+impl StdError for JsonTypeMismatch {
+    fn description(&self) -> &str { "json type mismatch" }
+}
+
+impl fmt::Display for JsonTypeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected {}, found {}", self.expected, self.got)
     }
 }
 
@@ -457,9 +966,63 @@ impl From<fmt::Error> for EncoderError {
 pub type EncodeResult<T> = Result<T, EncoderError>;
 pub type DecodeResult<T> = Result<T, DecoderError>;
 
-fn escape_str(wr: &mut fmt::Write, v: &str) -> EncodeResult<()> {
+/// Escapes `s` the way the encoder writes strings, without the surrounding
+/// quotes. This is the inverse of `unescape_str`.
+///
+/// ```
+/// use rustc_serialize::json;
+///
+/// assert_eq!(json::escape_str("say \"hi\"\n"), "say \\\"hi\\\"\\n");
+/// ```
+pub fn escape_str(s: &str) -> string::String {
+    let mut escaped = string::String::with_capacity(s.len());
+    // writing to a `String` can't fail
+    let _ = write_escaped(&mut escaped, s, false);
+    escaped
+}
+
+/// Turns the contents of a JSON string, without the surrounding quotes, back
+/// into the string they stand for, following the same rules as the parser:
+/// `\uXXXX` escapes of UTF-16 surrogate pairs make up a single character,
+/// while quotes and control characters must be escaped.
+///
+/// The column of an error on the first line counts from the start of `s`.
+///
+/// ```
+/// use rustc_serialize::json;
+///
+/// assert_eq!(json::unescape_str("caf\\u00e9 \\ud83d\\ude00").unwrap(),
+///            "caf\u{e9} \u{1f600}");
+/// assert!(json::unescape_str("\\ud83d").is_err());
+/// ```
+pub fn unescape_str(s: &str) -> Result<string::String, ParserError> {
+    let chars = Some('"').into_iter().chain(s.chars()).chain(Some('"').into_iter());
+    let mut parser = Parser::new(chars);
+    let res = match parser.parse_str() {
+        Ok(res) => {
+            if parser.eof() {
+                return Ok(res);
+            }
+            parser.error(InvalidSyntax)
+        }
+        Err(e) => Err(e),
+    };
+    // leave the opening quote out of the column
+    match res {
+        Err(SyntaxError(code, 1, col)) => Err(SyntaxError(code, 1, col - 1)),
+        res => res,
+    }
+}
+
+fn write_escaped_str(wr: &mut fmt::Write, v: &str, minimal: bool) -> EncodeResult<()> {
+    try!(wr.write_str("\""));
+    try!(write_escaped(wr, v, minimal));
     try!(wr.write_str("\""));
+    Ok(())
+}
 
+// With `minimal`, only what JSON requires is escaped, leaving DEL raw.
+fn write_escaped(wr: &mut fmt::Write, v: &str, minimal: bool) -> EncodeResult<()> {
     let mut start = 0;
 
     for (i, byte) in v.bytes().enumerate() {
@@ -498,7 +1061,7 @@ fn escape_str(wr: &mut fmt::Write, v: &str) -> EncodeResult<()> {
             b'\x1d' => "\\u001d",
             b'\x1e' => "\\u001e",
             b'\x1f' => "\\u001f",
-            b'\x7f' => "\\u007f",
+            b'\x7f' if !minimal => "\\u007f",
             _ => { continue; }
         };
 
@@ -515,15 +1078,14 @@ fn escape_str(wr: &mut fmt::Write, v: &str) -> EncodeResult<()> {
         try!(wr.write_str(&v[start..]));
     }
 
-    try!(wr.write_str("\""));
     Ok(())
 }
 
-fn escape_char(writer: &mut fmt::Write, v: char) -> EncodeResult<()> {
+fn escape_char(writer: &mut fmt::Write, v: char, minimal: bool) -> EncodeResult<()> {
     let mut buf = [0; 4];
     let _ = write!(&mut &mut buf[..], "{}", v);
     let buf = unsafe { str::from_utf8_unchecked(&buf[..v.len_utf8()]) };
-    escape_str(writer, buf)
+    write_escaped_str(writer, buf, minimal)
 }
 
 fn spaces(wr: &mut fmt::Write, n: u32) -> EncodeResult<()> {
@@ -554,7 +1116,8 @@ fn fmt_number_or_null(v: f64) -> string::String {
 }
 
 macro_rules! emit_enquoted_if_mapkey {
-    ($enc:ident,$e:expr) => {
+    ($enc:ident,$e:expr) => ({
+        try!($enc.emit_pending_field());
         if $enc.is_emitting_map_key {
             try!(write!($enc.writer, "\"{}\"", $e));
             Ok(())
@@ -562,127 +1125,583 @@ macro_rules! emit_enquoted_if_mapkey {
             try!(write!($enc.writer, "{}", $e));
             Ok(())
         }
-    }
+    })
 }
 
-enum EncodingFormat {
-    Compact,
-    Pretty {
-        curr_indent: u32,
-        indent: u32
-    }
-}
+// The largest integer every integer up to which an `f64` holds exactly.
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
 
-/// A structure for implementing serialization to JSON.
-pub struct Encoder<'a> {
-    writer: &'a mut (fmt::Write+'a),
-    format : EncodingFormat,
-    is_emitting_map_key: bool,
+fn safe_integer(v: i64) -> bool {
+    v >= -(MAX_SAFE_INTEGER as i64) && v <= MAX_SAFE_INTEGER as i64
 }
 
-impl<'a> Encoder<'a> {
-    /// Creates a new encoder whose output will be written in human-readable
-    /// JSON to the specified writer
-    pub fn new_pretty(writer: &'a mut fmt::Write) -> Encoder<'a> {
-        Encoder {
-            writer: writer,
-            format: EncodingFormat::Pretty {
-                curr_indent: 0,
-                indent: 2,
-            },
-            is_emitting_map_key: false,
+macro_rules! emit_integer {
+    ($enc:ident, $v:expr, $safe:expr) => ({
+        if $enc.quote_large_integers && !$safe {
+            try!($enc.emit_pending_field());
+            try!(write!($enc.writer, "\"{}\"", $v));
+            Ok(())
+        } else {
+            emit_enquoted_if_mapkey!($enc, $v)
         }
-    }
+    })
+}
 
-    /// Creates a new encoder whose output will be written in compact
-    /// JSON to the specified writer
-    pub fn new(writer: &'a mut fmt::Write) -> Encoder<'a> {
-        Encoder {
-            writer: writer,
-            format: EncodingFormat::Compact,
-            is_emitting_map_key: false,
-        }
+/// A writer appending to a byte vector, so that an `Encoder` can write to a
+/// buffer meant for I/O without going through a `String` first.
+///
+/// ```
+/// use rustc_serialize::json::{BytesWriter, Encoder};
+/// use rustc_serialize::Encodable;
+///
+/// let mut buf = b"data: ".to_vec();
+/// {
+///     let mut writer = BytesWriter::new(&mut buf);
+///     vec![1, 2].encode(&mut Encoder::new(&mut writer)).unwrap();
+/// }
+/// assert_eq!(buf, b"data: [1,2]");
+/// ```
+pub struct BytesWriter<'a> {
+    bytes: &'a mut Vec<u8>,
+}
+
+impl<'a> BytesWriter<'a> {
+    /// Creates a writer appending to `bytes`.
+    pub fn new(bytes: &'a mut Vec<u8>) -> BytesWriter<'a> {
+        BytesWriter { bytes: bytes }
     }
+}
 
-    /// Set the number of spaces to indent for each level.
-    /// This is safe to set during encoding.
-    pub fn set_indent(&mut self, new_indent: u32) -> Result<(), ()> {
-        if let EncodingFormat::Pretty{ref mut curr_indent, ref mut indent} = self.format {
-            // self.indent very well could be 0 so we need to use checked division.
-            let level = curr_indent.checked_div(*indent).unwrap_or(0);
-            *indent = new_indent;
-            *curr_indent = level * *indent;
-            Ok(())
-        } else {
-            Err(())
-        }
+impl<'a> fmt::Write for BytesWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.bytes.extend(s.bytes());
+        Ok(())
     }
 }
 
-impl<'a> ::Encoder for Encoder<'a> {
-    type Error = EncoderError;
+/// A writer which discards what an `Encoder` writes to it, only counting the
+/// bytes. This measures the size of the JSON for a value before writing it
+/// out, or the cost of encoding apart from that of I/O.
+///
+/// `Encoder::new(&mut ByteCounter::new())` makes the no-op encoder: an
+/// `Encoder` which writes nothing out, after which `byte_count()` returns
+/// the number of bytes it would have written.
+///
+/// ```
+/// use rustc_serialize::json::{Encoder, ByteCounter};
+/// use rustc_serialize::Encodable;
+///
+/// let mut counter = ByteCounter::new();
+/// vec!["a", "b"].encode(&mut Encoder::new_pretty(&mut counter)).unwrap();
+/// assert_eq!(counter.byte_count(), 16);
+/// ```
+#[derive(Default)]
+pub struct ByteCounter {
+    count: usize,
+}
 
-    fn emit_nil(&mut self) -> EncodeResult<()> {
-        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        try!(write!(self.writer, "null"));
+impl ByteCounter {
+    /// Creates a writer which hasn't counted anything yet.
+    pub fn new() -> ByteCounter {
+        ByteCounter { count: 0 }
+    }
+
+    /// Returns how many bytes have been written so far.
+    pub fn byte_count(&self) -> usize {
+        self.count
+    }
+}
+
+impl fmt::Write for ByteCounter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.count += s.len();
         Ok(())
     }
+}
 
-    fn emit_usize(&mut self, v: usize) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
-    fn emit_u64(&mut self, v: u64) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
-    fn emit_u32(&mut self, v: u32) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
-    fn emit_u16(&mut self, v: u16) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
-    fn emit_u8(&mut self, v: u8) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+/// Writes a JSON array to an `io::Write` one element at a time, flushing
+/// each as it goes, so that the elements never need to be in memory at once.
+///
+/// An element is encoded in full before any of it is written, so one failing
+/// to encode, reported as an `io::ErrorKind::InvalidInput` error wrapping the
+/// `EncoderError`, leaves the array as it was. The array is only closed by
+/// `finish`.
+///
+/// ```
+/// use rustc_serialize::json::ArrayEncoder;
+///
+/// let mut array = ArrayEncoder::new(Vec::new()).unwrap();
+/// for i in 0..3 {
+///     array.push(&(i, i * i)).unwrap();
+/// }
+/// assert_eq!(array.finish().unwrap(), b"[[0,0],[1,1],[2,4]]");
+/// ```
+pub struct ArrayEncoder<W> {
+    writer: W,
+    len: usize,
+    // Reused to encode every element.
+    buf: string::String,
+}
 
-    fn emit_isize(&mut self, v: isize) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
-    fn emit_i64(&mut self, v: i64) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
-    fn emit_i32(&mut self, v: i32) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
-    fn emit_i16(&mut self, v: i16) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
-    fn emit_i8(&mut self, v: i8) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+impl<W: io::Write> ArrayEncoder<W> {
+    /// Opens the array on `writer`.
+    pub fn new(mut writer: W) -> io::Result<ArrayEncoder<W>> {
+        try!(writer.write_all(b"["));
+        Ok(ArrayEncoder { writer: writer, len: 0, buf: string::String::new() })
+    }
 
-    fn emit_bool(&mut self, v: bool) -> EncodeResult<()> {
-        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        if v {
-            try!(write!(self.writer, "true"));
-        } else {
-            try!(write!(self.writer, "false"));
+    /// Encodes `value` as the next element of the array, and flushes it.
+    pub fn push<T: Encodable>(&mut self, value: &T) -> io::Result<()> {
+        self.buf.clear();
+        if self.len != 0 {
+            self.buf.push(',');
+        }
+        if let Err(e) = value.encode(&mut Encoder::new(&mut self.buf)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, e));
         }
+        try!(self.writer.write_all(self.buf.as_bytes()));
+        try!(self.writer.flush());
+        self.len += 1;
         Ok(())
     }
 
-    fn emit_f64(&mut self, v: f64) -> EncodeResult<()> {
-        emit_enquoted_if_mapkey!(self, fmt_number_or_null(v))
-    }
-    fn emit_f32(&mut self, v: f32) -> EncodeResult<()> {
-        self.emit_f64(v as f64)
+    /// Returns how many elements have been written.
+    pub fn len(&self) -> usize {
+        self.len
     }
 
-    fn emit_char(&mut self, v: char) -> EncodeResult<()> {
-        escape_char(self.writer, v)
-    }
-    fn emit_str(&mut self, v: &str) -> EncodeResult<()> {
-        escape_str(self.writer, v)
+    /// Returns true if no element has been written yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
 
-    fn emit_enum<F>(&mut self, _name: &str, f: F) -> EncodeResult<()> where
+    /// Closes the array, returning the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        try!(self.writer.write_all(b"]"));
+        try!(self.writer.flush());
+        Ok(self.writer)
+    }
+}
+
+/// Writes a JSON document to an `io::Write` piece by piece, for data which
+/// isn't held in an `Encodable` value, such as the output of a code
+/// generator.
+///
+/// Arrays and objects are opened and closed explicitly, object members are
+/// written as a `key` followed by a value, and values are either encoded
+/// from an `Encodable` or copied from text which is already JSON. Output is
+/// compact, and goes straight to the writer.
+///
+/// Calls which would make the document invalid, such as a value in an object
+/// with no key before it, a `key` outside of an object, or closing the wrong
+/// kind of container, are reported as `io::ErrorKind::InvalidInput` errors
+/// and write nothing, as are values failing to encode and raw values which
+/// aren't valid JSON.
+///
+/// ```
+/// use rustc_serialize::json::JsonWriter;
+///
+/// let mut writer = JsonWriter::new(Vec::new());
+/// writer.begin_object().unwrap();
+/// writer.key("id").unwrap();
+/// writer.value(&7).unwrap();
+/// writer.key("tags").unwrap();
+/// writer.begin_array().unwrap();
+/// writer.value(&"a").unwrap();
+/// writer.raw_value("{\"b\": null}").unwrap();
+/// writer.end_array().unwrap();
+/// writer.end_object().unwrap();
+/// assert_eq!(writer.finish().unwrap(), b"{\"id\":7,\"tags\":[\"a\",{\"b\": null}]}");
+/// ```
+pub struct JsonWriter<W> {
+    writer: W,
+    // The open containers, innermost last: whether each is an object, and
+    // whether it has anything in it yet.
+    stack: Vec<(bool, bool)>,
+    // Set between a key and its value.
+    after_key: bool,
+    // Set once the top level value has been started.
+    started: bool,
+    // Reused to encode values and keys.
+    buf: string::String,
+}
+
+impl<W: io::Write> JsonWriter<W> {
+    /// Creates a writer for a document written to `writer`.
+    pub fn new(writer: W) -> JsonWriter<W> {
+        JsonWriter {
+            writer: writer,
+            stack: Vec::new(),
+            after_key: false,
+            started: false,
+            buf: string::String::new(),
+        }
+    }
+
+    /// Opens an object.
+    pub fn begin_object(&mut self) -> io::Result<()> {
+        try!(self.check_value());
+        try!(self.write_value_start());
+        try!(self.writer.write_all(b"{"));
+        self.stack.push((true, false));
+        Ok(())
+    }
+
+    /// Closes the innermost object.
+    pub fn end_object(&mut self) -> io::Result<()> {
+        match self.stack.last() {
+            Some(&(true, _)) if !self.after_key => {}
+            _ => return Err(misuse("no object to close")),
+        }
+        try!(self.writer.write_all(b"}"));
+        self.stack.pop();
+        Ok(())
+    }
+
+    /// Opens an array.
+    pub fn begin_array(&mut self) -> io::Result<()> {
+        try!(self.check_value());
+        try!(self.write_value_start());
+        try!(self.writer.write_all(b"["));
+        self.stack.push((false, false));
+        Ok(())
+    }
+
+    /// Closes the innermost array.
+    pub fn end_array(&mut self) -> io::Result<()> {
+        match self.stack.last() {
+            Some(&(false, _)) => {}
+            _ => return Err(misuse("no array to close")),
+        }
+        try!(self.writer.write_all(b"]"));
+        self.stack.pop();
+        Ok(())
+    }
+
+    /// Writes the key of the next member of the innermost object, which has
+    /// to be followed by its value.
+    pub fn key(&mut self, k: &str) -> io::Result<()> {
+        let first = match self.stack.last() {
+            Some(&(true, has_members)) if !self.after_key => !has_members,
+            _ => return Err(misuse("a key has to be a member of an object")),
+        };
+        self.buf.clear();
+        if !first {
+            self.buf.push(',');
+        }
+        // writing to a `String` can't fail
+        let _ = write_escaped_str(&mut self.buf, k, false);
+        self.buf.push(':');
+        try!(self.writer.write_all(self.buf.as_bytes()));
+        self.stack.last_mut().unwrap().1 = true;
+        self.after_key = true;
+        Ok(())
+    }
+
+    /// Encodes `v` as the next value.
+    pub fn value<T: Encodable>(&mut self, v: &T) -> io::Result<()> {
+        try!(self.check_value());
+        self.buf.clear();
+        if let Err(e) = v.encode(&mut Encoder::new(&mut self.buf)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, e));
+        }
+        try!(self.write_value_start());
+        self.writer.write_all(self.buf.as_bytes())
+    }
+
+    /// Writes `json`, which has to be a single valid JSON value, as the next
+    /// value, as it is.
+    pub fn raw_value(&mut self, json: &str) -> io::Result<()> {
+        try!(self.check_value());
+        for event in Parser::new(json.chars()) {
+            if let Error(e) = event {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, e));
+            }
+        }
+        try!(self.write_value_start());
+        self.writer.write_all(json.as_bytes())
+    }
+
+    /// Checks that the document is complete, and returns the writer.
+    pub fn finish(self) -> io::Result<W> {
+        if !self.started || !self.stack.is_empty() {
+            return Err(misuse("the document isn't complete"));
+        }
+        Ok(self.writer)
+    }
+
+    // Checks that a value may come next.
+    fn check_value(&self) -> io::Result<()> {
+        match self.stack.last() {
+            None if self.started => Err(misuse("there can only be one value at the top level")),
+            Some(&(true, _)) if !self.after_key => Err(misuse("a value in an object needs a key")),
+            _ => Ok(()),
+        }
+    }
+
+    // Writes the comma before a value if needed, and records that it is
+    // there.
+    fn write_value_start(&mut self) -> io::Result<()> {
+        match self.stack.last_mut() {
+            None => self.started = true,
+            Some(&mut (true, _)) => self.after_key = false,
+            Some(&mut (false, ref mut has_members)) => {
+                if *has_members {
+                    try!(self.writer.write_all(b","));
+                }
+                *has_members = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn misuse(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+enum EncodingFormat {
+    Compact,
+    Pretty {
+        curr_indent: u32,
+        indent: u32
+    }
+}
+
+/// How `Encoder` writes, and `Decoder` reads, the variants of an enum, shown
+/// for `Shape::Circle { r: 1 }` and `Shape::Square(2)`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum EnumTagging {
+    /// An object holding the name and the list of fields,
+    /// `{"variant":"Circle","fields":[1]}` and
+    /// `{"variant":"Square","fields":[2]}`. This is the default.
+    VariantFields,
+    /// An object with the name as its one key, holding the content,
+    /// `{"Circle":{"r":1}}` and `{"Square":2}`. A tuple variant with several
+    /// fields holds them in an array.
+    External,
+    /// An object holding the name under the given key next to the fields,
+    /// `{"type":"Circle","r":1}` for `Internal("type")`. Tuple variants must
+    /// have exactly one field, holding a struct whose fields are written
+    /// next to the name, so `Shape::Square(2)` can't be encoded.
+    Internal(string::String),
+}
+
+// How the encoder writes the arguments of the variant being encoded.
+#[derive(Clone, Copy)]
+enum VariantArgs {
+    // in the `"fields"` list of `EnumTagging::VariantFields`
+    List,
+    // as an array, for externally tagged tuple variants
+    Array,
+    // as the value itself, for externally tagged variants with one argument
+    Single,
+    // as struct fields, for tagged struct variants
+    Named,
+    // next to the tag, for internally tagged tuple variants
+    Flattened,
+}
+
+/// A structure for implementing serialization to JSON.
+pub struct Encoder<'a> {
+    writer: &'a mut (fmt::Write+'a),
+    format : EncodingFormat,
+    is_emitting_map_key: bool,
+    omit_null: bool,
+    quote_large_integers: bool,
+    minimal_escaping: bool,
+    // With `omit_null`, the name of a struct field whose value hasn't been
+    // emitted yet.
+    pending_field: Option<string::String>,
+    // Whether the innermost struct has written a field.
+    struct_has_fields: bool,
+    // Whether the next struct is to have its fields written into the
+    // enclosing one.
+    flatten_next: bool,
+    enum_tagging: EnumTagging,
+    variant_args: VariantArgs,
+}
+
+impl<'a> Encoder<'a> {
+    /// Creates a new encoder whose output will be written in human-readable
+    /// JSON to the specified writer
+    pub fn new_pretty(writer: &'a mut fmt::Write) -> Encoder<'a> {
+        Encoder {
+            writer: writer,
+            format: EncodingFormat::Pretty {
+                curr_indent: 0,
+                indent: 2,
+            },
+            is_emitting_map_key: false,
+            omit_null: false,
+            quote_large_integers: false,
+            minimal_escaping: false,
+            pending_field: None,
+            struct_has_fields: false,
+            flatten_next: false,
+            enum_tagging: EnumTagging::VariantFields,
+            variant_args: VariantArgs::List,
+        }
+    }
+
+    /// Creates a new encoder whose output will be written in compact
+    /// JSON to the specified writer
+    pub fn new(writer: &'a mut fmt::Write) -> Encoder<'a> {
+        Encoder {
+            writer: writer,
+            format: EncodingFormat::Compact,
+            is_emitting_map_key: false,
+            omit_null: false,
+            quote_large_integers: false,
+            minimal_escaping: false,
+            pending_field: None,
+            struct_has_fields: false,
+            flatten_next: false,
+            enum_tagging: EnumTagging::VariantFields,
+            variant_args: VariantArgs::List,
+        }
+    }
+
+    /// Set the number of spaces to indent for each level.
+    /// This is safe to set during encoding.
+    pub fn set_indent(&mut self, new_indent: u32) -> Result<(), ()> {
+        if let EncodingFormat::Pretty{ref mut curr_indent, ref mut indent} = self.format {
+            // self.indent very well could be 0 so we need to use checked division.
+            let level = curr_indent.checked_div(*indent).unwrap_or(0);
+            *indent = new_indent;
+            *curr_indent = level * *indent;
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    /// Set whether struct fields whose value encodes as `null`, such as a
+    /// `None` option, are left out of the output instead of being written as
+    /// `"field": null`. Defaults to `false`.
+    pub fn set_omit_null(&mut self, omit_null: bool) {
+        self.omit_null = omit_null;
+    }
+
+    /// Set whether integers beyond the range an `f64` holds exactly,
+    /// ±(2^53 - 1), are written as strings such as `"9007199254740993"`
+    /// rather than as numbers, for readers which turn every number into an
+    /// `f64`, as JavaScript does. Decoding reads them back either way.
+    /// Defaults to `false`.
+    pub fn set_quote_large_integers(&mut self, quote: bool) {
+        self.quote_large_integers = quote;
+    }
+
+    /// Set whether strings, and object keys, are written with only the
+    /// escapes JSON requires: `\"`, `\\` and those of the control characters
+    /// U+0000 to U+001F. DEL (U+007F) is then written as it is rather than as
+    /// `\u007f`. Either way `/` and non-ASCII characters are written as they
+    /// are, in UTF-8. Defaults to `false`.
+    pub fn set_minimal_escaping(&mut self, minimal: bool) {
+        self.minimal_escaping = minimal;
+    }
+
+    /// Set how the variants of enums are written. Defaults to
+    /// `EnumTagging::VariantFields`. A `Decoder` reads them back once given
+    /// the same setting.
+    pub fn set_enum_tagging(&mut self, tagging: EnumTagging) {
+        self.enum_tagging = tagging;
+    }
+
+    fn emit_field_name(&mut self, name: &str, first: bool) -> EncodeResult<()> {
+        self.struct_has_fields = true;
+        if !first {
+            try!(write!(self.writer, ","));
+        }
+        if let EncodingFormat::Pretty{curr_indent, ..} = self.format {
+            try!(write!(self.writer, "\n"));
+            try!(spaces(self.writer, curr_indent));
+        }
+        try!(write_escaped_str(self.writer, name, self.minimal_escaping));
+        if let EncodingFormat::Pretty{..} = self.format {
+            try!(write!(self.writer, ": "));
+        } else {
+            try!(write!(self.writer, ":"));
+        }
+        Ok(())
+    }
+
+    // Writes out the name of a deferred struct field once its value turns
+    // out not to be `null`. Called before writing out any value other than a
+    // struct, which also makes it the place to reject flattened non-structs.
+    fn emit_pending_field(&mut self) -> EncodeResult<()> {
+        if self.flatten_next { return Err(EncoderError::BadFlattenedField); }
+        if let Some(name) = self.pending_field.take() {
+            let first = !self.struct_has_fields;
+            try!(self.emit_field_name(&name, first));
+        }
+        Ok(())
+    }
+
+    // Writes out an enum variant the way `enum_tagging` says, `named` telling
+    // whether its arguments are struct fields.
+    fn emit_variant<F>(&mut self, name: &str, cnt: usize, named: bool, f: F)
+                       -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
-        f(self)
+        let tag = match self.enum_tagging {
+            EnumTagging::VariantFields => return self.emit_variant_fields(name, cnt, f),
+            EnumTagging::External => None,
+            EnumTagging::Internal(ref tag) => Some(tag.clone()),
+        };
+        // only structs can be flattened, even those holding a variant
+        if self.flatten_next { return Err(EncoderError::BadFlattenedField); }
+        let outer_args = self.variant_args;
+        let result = match tag {
+            // Circle { r: 1 } => {"Circle": {"r": 1}}
+            // Square(2) => {"Square": 2}
+            None if cnt == 0 && !named => {
+                try!(self.emit_pending_field());
+                write_escaped_str(self.writer, name, self.minimal_escaping)
+            }
+            None => {
+                if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+                ::Encoder::emit_struct(self, name, 1, |s| {
+                    try!(s.emit_field_name(name, true));
+                    if named {
+                        s.variant_args = VariantArgs::Named;
+                        ::Encoder::emit_struct(s, name, cnt, f)
+                    } else if cnt == 1 {
+                        s.variant_args = VariantArgs::Single;
+                        f(s)
+                    } else {
+                        s.variant_args = VariantArgs::Array;
+                        ::Encoder::emit_seq(s, cnt, f)
+                    }
+                })
+            }
+            // Circle { r: 1 } => {"type": "Circle", "r": 1}
+            Some(tag) => {
+                ::Encoder::emit_struct(self, name, cnt + 1, |s| {
+                    try!(s.emit_field_name(&tag, true));
+                    try!(write_escaped_str(s.writer, name, s.minimal_escaping));
+                    s.variant_args = if named {
+                        VariantArgs::Named
+                    } else {
+                        VariantArgs::Flattened
+                    };
+                    f(s)
+                })
+            }
+        };
+        self.variant_args = outer_args;
+        result
     }
 
-    fn emit_enum_variant<F>(&mut self,
-                            name: &str,
-                            _id: usize,
-                            cnt: usize,
-                            f: F)
-                            -> EncodeResult<()> where
+    fn emit_variant_fields<F>(&mut self, name: &str, cnt: usize, f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
         // enums are encoded as strings or objects
         // Bunny => "Bunny"
         // Kangaroo(34,"William") => {"variant": "Kangaroo", "fields": [34,"William"]}
+        try!(self.emit_pending_field());
         if cnt == 0 {
-            escape_str(self.writer, name)
+            write_escaped_str(self.writer, name, self.minimal_escaping)
         } else {
             if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
             if let EncodingFormat::Pretty{ref mut curr_indent, indent} = self.format {
@@ -690,17 +1709,20 @@ impl<'a> ::Encoder for Encoder<'a> {
                 *curr_indent += indent;
                 try!(spaces(self.writer, *curr_indent));
                 try!(write!(self.writer, "\"variant\": "));
-                try!(escape_str(self.writer, name));
+                try!(write_escaped_str(self.writer, name, self.minimal_escaping));
                 try!(write!(self.writer, ",\n"));
                 try!(spaces(self.writer, *curr_indent));
                 try!(write!(self.writer, "\"fields\": [\n"));
                 *curr_indent += indent;
             } else {
                 try!(write!(self.writer, "{{\"variant\":"));
-                try!(escape_str(self.writer, name));
+                try!(write_escaped_str(self.writer, name, self.minimal_escaping));
                 try!(write!(self.writer, ",\"fields\":["));
             }
-            try!(f(self));
+            let outer_args = mem::replace(&mut self.variant_args, VariantArgs::List);
+            let result = f(self);
+            self.variant_args = outer_args;
+            try!(result);
             if let EncodingFormat::Pretty{ref mut curr_indent, indent} = self.format {
                 *curr_indent -= indent;
                 try!(write!(self.writer, "\n"));
@@ -715,11 +1737,129 @@ impl<'a> ::Encoder for Encoder<'a> {
             Ok(())
         }
     }
+}
+
+impl<'a> ::Encoder for Encoder<'a> {
+    type Error = EncoderError;
+
+    fn emit_nil(&mut self) -> EncodeResult<()> {
+        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        if self.flatten_next { return Err(EncoderError::BadFlattenedField); }
+        if self.pending_field.take().is_some() { return Ok(()); }
+        try!(write!(self.writer, "null"));
+        Ok(())
+    }
+
+    fn emit_usize(&mut self, v: usize) -> EncodeResult<()> {
+        emit_integer!(self, v, v as u64 <= MAX_SAFE_INTEGER)
+    }
+    // Values beyond the range of `u64` are written as strings of their
+    // digits, as there is no `Json` number they could be read back into.
+    #[cfg(feature = "i128")]
+    fn emit_u128(&mut self, v: u128) -> EncodeResult<()> {
+        if v <= u64::MAX as u128 {
+            self.emit_u64(v as u64)
+        } else {
+            self.emit_str(&v.to_string())
+        }
+    }
+    fn emit_u64(&mut self, v: u64) -> EncodeResult<()> {
+        emit_integer!(self, v, v <= MAX_SAFE_INTEGER)
+    }
+    fn emit_u32(&mut self, v: u32) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+    fn emit_u16(&mut self, v: u16) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+    fn emit_u8(&mut self, v: u8) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+
+    fn emit_isize(&mut self, v: isize) -> EncodeResult<()> {
+        emit_integer!(self, v, safe_integer(v as i64))
+    }
+    #[cfg(feature = "i128")]
+    fn emit_i128(&mut self, v: i128) -> EncodeResult<()> {
+        if v >= i64::MIN as i128 && v <= i64::MAX as i128 {
+            self.emit_i64(v as i64)
+        } else if v > 0 {
+            self.emit_u128(v as u128)
+        } else {
+            self.emit_str(&v.to_string())
+        }
+    }
+    fn emit_i64(&mut self, v: i64) -> EncodeResult<()> {
+        emit_integer!(self, v, safe_integer(v))
+    }
+    fn emit_i32(&mut self, v: i32) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+    fn emit_i16(&mut self, v: i16) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+    fn emit_i8(&mut self, v: i8) -> EncodeResult<()> { emit_enquoted_if_mapkey!(self, v) }
+
+    fn emit_bool(&mut self, v: bool) -> EncodeResult<()> {
+        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        try!(self.emit_pending_field());
+        if v {
+            try!(write!(self.writer, "true"));
+        } else {
+            try!(write!(self.writer, "false"));
+        }
+        Ok(())
+    }
+
+    fn emit_f64(&mut self, v: f64) -> EncodeResult<()> {
+        if !v.is_finite() && self.pending_field.take().is_some() { return Ok(()); }
+        emit_enquoted_if_mapkey!(self, fmt_number_or_null(v))
+    }
+    fn emit_f32(&mut self, v: f32) -> EncodeResult<()> {
+        self.emit_f64(v as f64)
+    }
+
+    fn emit_char(&mut self, v: char) -> EncodeResult<()> {
+        try!(self.emit_pending_field());
+        escape_char(self.writer, v, self.minimal_escaping)
+    }
+    fn emit_str(&mut self, v: &str) -> EncodeResult<()> {
+        try!(self.emit_pending_field());
+        write_escaped_str(self.writer, v, self.minimal_escaping)
+    }
+
+    fn emit_number_str(&mut self, v: &str) -> EncodeResult<()> {
+        if !is_number(v) {
+            return self.emit_str(v);
+        }
+        emit_enquoted_if_mapkey!(self, v)
+    }
+
+    fn emit_enum<F>(&mut self, _name: &str, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
+    {
+        f(self)
+    }
+
+    fn emit_enum_variant<F>(&mut self,
+                            name: &str,
+                            _id: usize,
+                            cnt: usize,
+                            f: F)
+                            -> EncodeResult<()> where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
+    {
+        self.emit_variant(name, cnt, false, f)
+    }
 
     fn emit_enum_variant_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
         if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        match self.variant_args {
+            VariantArgs::List | VariantArgs::Named => {}
+            VariantArgs::Array => return self.emit_seq_elt(idx, f),
+            VariantArgs::Single => return f(self),
+            VariantArgs::Flattened if idx == 0 => {
+                return match self.emit_struct_flattened(idx, f) {
+                    Err(EncoderError::BadFlattenedField) => {
+                        Err(EncoderError::BadTaggedVariant)
+                    }
+                    result => result,
+                };
+            }
+            VariantArgs::Flattened => return Err(EncoderError::BadTaggedVariant),
+        }
         if idx != 0 {
             try!(write!(self.writer, ","));
             if let EncodingFormat::Pretty{..} = self.format {
@@ -734,22 +1874,25 @@ impl<'a> ::Encoder for Encoder<'a> {
 
     fn emit_enum_struct_variant<F>(&mut self,
                                    name: &str,
-                                   id: usize,
+                                   _id: usize,
                                    cnt: usize,
                                    f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
         if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        self.emit_enum_variant(name, id, cnt, f)
+        self.emit_variant(name, cnt, true, f)
     }
 
     fn emit_enum_struct_variant_field<F>(&mut self,
-                                         _: &str,
+                                         name: &str,
                                          idx: usize,
                                          f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
         if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        if let VariantArgs::Named = self.variant_args {
+            return self.emit_struct_field(name, idx, f);
+        }
         self.emit_enum_variant_arg(idx, f)
     }
 
@@ -758,6 +1901,12 @@ impl<'a> ::Encoder for Encoder<'a> {
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
         if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        if self.flatten_next {
+            // the fields go straight into the enclosing struct
+            self.flatten_next = false;
+            return f(self);
+        }
+        try!(self.emit_pending_field());
         if len == 0 {
             try!(write!(self.writer, "{{}}"));
         } else {
@@ -765,37 +1914,51 @@ impl<'a> ::Encoder for Encoder<'a> {
             if let EncodingFormat::Pretty{ref mut curr_indent, indent} = self.format {
                 *curr_indent += indent;
             }
+            let outer_has_fields = self.struct_has_fields;
+            self.struct_has_fields = false;
             try!(f(self));
+            // every field may have been omitted, in which case the braces
+            // stay together
+            let wrote_fields = self.struct_has_fields;
+            self.struct_has_fields = outer_has_fields;
             if let EncodingFormat::Pretty{ref mut curr_indent, indent} = self.format {
                 *curr_indent -= indent;
-                try!(write!(self.writer, "\n"));
-                try!(spaces(self.writer, *curr_indent));
+                if wrote_fields {
+                    try!(write!(self.writer, "\n"));
+                    try!(spaces(self.writer, *curr_indent));
+                }
             }
             try!(write!(self.writer, "}}"));
         }
         Ok(())
     }
 
-    fn emit_struct_field<F>(&mut self, name: &str, idx: usize, f: F) -> EncodeResult<()> where
+    fn emit_struct_field<F>(&mut self, name: &str, _idx: usize, f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
         if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
-        if idx != 0 {
-            try!(write!(self.writer, ","));
-        }
-        if let EncodingFormat::Pretty{curr_indent, ..} = self.format {
-            try!(write!(self.writer, "\n"));
-            try!(spaces(self.writer, curr_indent));
-        }
-        try!(escape_str(self.writer, name));
-        if let EncodingFormat::Pretty{..} = self.format {
-            try!(write!(self.writer, ": "));
-        } else {
-            try!(write!(self.writer, ":"));
+        if self.omit_null {
+            // the name is only written once the value turns out not to be null
+            self.pending_field = Some(name.to_string());
+            try!(f(self));
+            self.pending_field = None;
+            return Ok(());
         }
+        let first = !self.struct_has_fields;
+        try!(self.emit_field_name(name, first));
         f(self)
     }
 
+    fn emit_struct_flattened<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
+    {
+        if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        self.flatten_next = true;
+        let result = f(self);
+        self.flatten_next = false;
+        result
+    }
+
     fn emit_tuple<F>(&mut self, len: usize, f: F) -> EncodeResult<()> where
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
@@ -843,6 +2006,7 @@ impl<'a> ::Encoder for Encoder<'a> {
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
         if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        try!(self.emit_pending_field());
         if len == 0 {
             try!(write!(self.writer, "[]"));
         } else {
@@ -879,6 +2043,7 @@ impl<'a> ::Encoder for Encoder<'a> {
         F: FnOnce(&mut Encoder<'a>) -> EncodeResult<()>,
     {
         if self.is_emitting_map_key { return Err(EncoderError::BadHashmapKey); }
+        try!(self.emit_pending_field());
         if len == 0 {
             try!(write!(self.writer, "{{}}"));
         } else {
@@ -927,6 +2092,9 @@ impl<'a> ::Encoder for Encoder<'a> {
     }
 }
 
+/// Encodes the value through any `Encoder`, objects going through
+/// `emit_map` with their keys in order, so that `Json` can be used for the
+/// dynamic parts of an encodable struct.
 impl Encodable for Json {
     fn encode<S: ::Encoder>(&self, e: &mut S) -> Result<(), S::Error> {
         match *self {
@@ -942,9 +2110,343 @@ impl Encodable for Json {
     }
 }
 
-/// Create an `AsJson` wrapper which can be used to print a value as JSON
-/// on-the-fly via `write!`
-pub fn as_json<T: Encodable>(t: &T) -> AsJson<T> {
+/// Decodes whatever value comes next, as with `Json::from_str`, but through
+/// the `Decodable` machinery, so that `json::decode::<Json>` works and `Json`
+/// can be used for the dynamic parts of a decodable struct.
+///
+/// This relies on `Decoder::next_kind`, which the decoders of this module
+/// implement, to know what to read.
+impl ::Decodable for Json {
+    fn decode<D: ::Decoder>(d: &mut D) -> Result<Json, D::Error> {
+        match try!(d.next_kind()) {
+            ValueKind::Nil => d.read_nil().map(|()| Json::Null),
+            ValueKind::Bool => d.read_bool().map(Json::Boolean),
+            ValueKind::I64 => d.read_i64().map(Json::I64),
+            ValueKind::U64 => d.read_u64().map(Json::U64),
+            ValueKind::F64 => d.read_f64().map(Json::F64),
+            ValueKind::String => d.read_str().map(Json::String),
+            ValueKind::Seq => d.read_seq(|d, len| {
+                let mut array = Vec::with_capacity(::cap_capacity::<Json>(len));
+                for i in 0..len {
+                    array.push(try!(d.read_seq_elt(i, ::Decodable::decode)));
+                }
+                Ok(Json::Array(array))
+            }),
+            ValueKind::Map => d.read_map(|d, len| {
+                let mut object = BTreeMap::new();
+                for i in 0..len {
+                    let key = try!(d.read_map_elt_key(i, |d| d.read_str()));
+                    let value = try!(d.read_map_elt_val(i, ::Decodable::decode));
+                    object.insert(key, value);
+                }
+                Ok(Json::Object(object))
+            }),
+        }
+    }
+}
+
+impl Json {
+    // The kind of the value, for `Decoder::next_kind`.
+    fn value_kind(&self) -> ValueKind {
+        match *self {
+            Json::I64(_) => ValueKind::I64,
+            Json::U64(_) => ValueKind::U64,
+            Json::F64(_) => ValueKind::F64,
+            Json::String(_) => ValueKind::String,
+            Json::Boolean(_) => ValueKind::Bool,
+            Json::Array(_) => ValueKind::Seq,
+            Json::Object(_) => ValueKind::Map,
+            Json::Null => ValueKind::Nil,
+        }
+    }
+}
+
+/// An encoder building a `Json` value in memory rather than writing it out,
+/// so that the value can be looked at or patched before it is written.
+///
+/// Values come out as they would read back from what `Encoder` writes:
+/// structs and maps become objects, enums use `EnumTagging::VariantFields`,
+/// and non-finite floats become `Null`. Object keys end up sorted, as in
+/// any `Json::Object`.
+///
+/// ```
+/// use rustc_serialize::json::{Json, JsonValueEncoder};
+/// use rustc_serialize::Encodable;
+///
+/// let mut encoder = JsonValueEncoder::new();
+/// vec![1, 2].encode(&mut encoder).unwrap();
+/// let mut json = encoder.finish();
+/// json.as_array_mut().unwrap().push(Json::String("three".to_string()));
+/// assert_eq!(json.to_string(), "[1,2,\"three\"]");
+/// ```
+#[derive(Default)]
+pub struct JsonValueEncoder {
+    // Leaf values are left in `value`, from where the enclosing compound
+    // value (the top of `stack`) collects them.
+    value: Option<Json>,
+    stack: Vec<Json>,
+    keys: Vec<string::String>,
+}
+
+impl JsonValueEncoder {
+    /// Creates an encoder with no value yet.
+    pub fn new() -> JsonValueEncoder {
+        JsonValueEncoder { value: None, stack: Vec::new(), keys: Vec::new() }
+    }
+
+    /// Returns the value encoded, `Null` if there was none.
+    pub fn finish(self) -> Json {
+        self.value.unwrap_or(Json::Null)
+    }
+
+    fn emit_value(&mut self, value: Json) -> EncodeResult<()> {
+        self.value = Some(value);
+        Ok(())
+    }
+
+    // Encodes a compound value by running `f` against a fresh container.
+    fn emit_container<F>(&mut self, container: Json, f: F) -> EncodeResult<Json> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        self.stack.push(container);
+        try!(f(self));
+        Ok(self.stack.pop().unwrap())
+    }
+
+    // Encodes a single element and appends it to the enclosing array.
+    fn emit_elt<F>(&mut self, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        try!(f(self));
+        let value = self.value.take().unwrap_or(Json::Null);
+        match self.stack.last_mut() {
+            Some(&mut Json::Array(ref mut array)) => array.push(value),
+            _ => return Err(EncoderError::MisplacedValue),
+        }
+        Ok(())
+    }
+
+    // Encodes a single value and inserts it into the enclosing object.
+    fn emit_field<F>(&mut self, name: string::String, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        try!(f(self));
+        let value = self.value.take().unwrap_or(Json::Null);
+        match self.stack.last_mut() {
+            Some(&mut Json::Object(ref mut object)) => { object.insert(name, value); }
+            _ => return Err(EncoderError::MisplacedValue),
+        }
+        Ok(())
+    }
+}
+
+impl ::Encoder for JsonValueEncoder {
+    type Error = EncoderError;
+
+    fn emit_nil(&mut self) -> EncodeResult<()> { self.emit_value(Json::Null) }
+
+    fn emit_usize(&mut self, v: usize) -> EncodeResult<()> { self.emit_value(Json::U64(v as u64)) }
+    #[cfg(feature = "i128")]
+    fn emit_u128(&mut self, v: u128) -> EncodeResult<()> { self.emit_value(v.to_json()) }
+    fn emit_u64(&mut self, v: u64) -> EncodeResult<()> { self.emit_value(Json::U64(v)) }
+    fn emit_u32(&mut self, v: u32) -> EncodeResult<()> { self.emit_value(Json::U64(v as u64)) }
+    fn emit_u16(&mut self, v: u16) -> EncodeResult<()> { self.emit_value(Json::U64(v as u64)) }
+    fn emit_u8(&mut self, v: u8) -> EncodeResult<()> { self.emit_value(Json::U64(v as u64)) }
+
+    fn emit_isize(&mut self, v: isize) -> EncodeResult<()> { self.emit_value(Json::I64(v as i64)) }
+    #[cfg(feature = "i128")]
+    fn emit_i128(&mut self, v: i128) -> EncodeResult<()> { self.emit_value(v.to_json()) }
+    fn emit_i64(&mut self, v: i64) -> EncodeResult<()> { self.emit_value(Json::I64(v)) }
+    fn emit_i32(&mut self, v: i32) -> EncodeResult<()> { self.emit_value(Json::I64(v as i64)) }
+    fn emit_i16(&mut self, v: i16) -> EncodeResult<()> { self.emit_value(Json::I64(v as i64)) }
+    fn emit_i8(&mut self, v: i8) -> EncodeResult<()> { self.emit_value(Json::I64(v as i64)) }
+
+    fn emit_bool(&mut self, v: bool) -> EncodeResult<()> { self.emit_value(Json::Boolean(v)) }
+
+    fn emit_f64(&mut self, v: f64) -> EncodeResult<()> { self.emit_value(v.to_json()) }
+    fn emit_f32(&mut self, v: f32) -> EncodeResult<()> { self.emit_f64(v as f64) }
+
+    fn emit_char(&mut self, v: char) -> EncodeResult<()> {
+        self.emit_value(Json::String(v.to_string()))
+    }
+    fn emit_str(&mut self, v: &str) -> EncodeResult<()> {
+        self.emit_value(Json::String(v.to_string()))
+    }
+
+    fn emit_number_str(&mut self, v: &str) -> EncodeResult<()> {
+        // As close as a `Json` number gets, or a string for integers too
+        // large for any of them.
+        match (is_number(v), Json::from_str(v)) {
+            (true, Ok(number)) => self.emit_value(number),
+            _ => self.emit_value(Json::String(v.to_string())),
+        }
+    }
+
+    fn emit_enum<F>(&mut self, _name: &str, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        f(self)
+    }
+
+    fn emit_enum_variant<F>(&mut self,
+                            name: &str,
+                            _id: usize,
+                            cnt: usize,
+                            f: F)
+                            -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        // Same representation as the JSON encoder, see `Encoder`.
+        if cnt == 0 {
+            self.emit_value(Json::String(name.to_string()))
+        } else {
+            let fields = try!(self.emit_container(Json::Array(Vec::with_capacity(cnt)), f));
+            let mut obj = BTreeMap::new();
+            obj.insert("variant".to_string(), Json::String(name.to_string()));
+            obj.insert("fields".to_string(), fields);
+            self.emit_value(Json::Object(obj))
+        }
+    }
+
+    fn emit_enum_variant_arg<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        self.emit_elt(f)
+    }
+
+    fn emit_enum_struct_variant<F>(&mut self,
+                                   name: &str,
+                                   id: usize,
+                                   cnt: usize,
+                                   f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        self.emit_enum_variant(name, id, cnt, f)
+    }
+
+    fn emit_enum_struct_variant_field<F>(&mut self,
+                                         _: &str,
+                                         idx: usize,
+                                         f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        self.emit_enum_variant_arg(idx, f)
+    }
+
+    fn emit_struct<F>(&mut self, _: &str, _len: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        let obj = try!(self.emit_container(Json::Object(BTreeMap::new()), f));
+        self.emit_value(obj)
+    }
+
+    fn emit_struct_field<F>(&mut self, name: &str, _idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        self.emit_field(name.to_string(), f)
+    }
+
+    fn emit_struct_flattened<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        try!(f(self));
+        let fields = match self.value.take() {
+            Some(Json::Object(fields)) => fields,
+            _ => return Err(EncoderError::BadFlattenedField),
+        };
+        match self.stack.last_mut() {
+            Some(&mut Json::Object(ref mut object)) => object.extend(fields.into_iter()),
+            _ => return Err(EncoderError::MisplacedValue),
+        }
+        Ok(())
+    }
+
+    fn emit_tuple<F>(&mut self, len: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        self.emit_seq(len, f)
+    }
+    fn emit_tuple_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        self.emit_seq_elt(idx, f)
+    }
+
+    fn emit_tuple_struct<F>(&mut self, _: &str, len: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        self.emit_seq(len, f)
+    }
+    fn emit_tuple_struct_arg<F>(&mut self, idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        self.emit_seq_elt(idx, f)
+    }
+
+    fn emit_option<F>(&mut self, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        f(self)
+    }
+    fn emit_option_none(&mut self) -> EncodeResult<()> {
+        self.emit_nil()
+    }
+    fn emit_option_some<F>(&mut self, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        f(self)
+    }
+
+    fn emit_seq<F>(&mut self, len: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        let array = try!(self.emit_container(Json::Array(Vec::with_capacity(len)), f));
+        self.emit_value(array)
+    }
+
+    fn emit_seq_elt<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        self.emit_elt(f)
+    }
+
+    fn emit_map<F>(&mut self, _len: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        let obj = try!(self.emit_container(Json::Object(BTreeMap::new()), f));
+        self.emit_value(obj)
+    }
+
+    fn emit_map_elt_key<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        try!(f(self));
+        // Keys are quoted if need be, just like the JSON encoder does.
+        let key = match self.value.take() {
+            Some(Json::String(s)) => s,
+            Some(Json::I64(v)) => v.to_string(),
+            Some(Json::U64(v)) => v.to_string(),
+            Some(Json::F64(v)) => fmt_number_or_null(v),
+            _ => return Err(EncoderError::BadHashmapKey),
+        };
+        self.keys.push(key);
+        Ok(())
+    }
+
+    fn emit_map_elt_val<F>(&mut self, _idx: usize, f: F) -> EncodeResult<()> where
+        F: FnOnce(&mut JsonValueEncoder) -> EncodeResult<()>,
+    {
+        let key = match self.keys.pop() {
+            Some(key) => key,
+            None => return Err(EncoderError::MisplacedValue),
+        };
+        self.emit_field(key, f)
+    }
+}
+
+/// Create an `AsJson` wrapper which can be used to print a value as JSON
+/// on-the-fly via `write!`
+pub fn as_json<T: Encodable>(t: &T) -> AsJson<T> {
     AsJson { inner: t }
 }
 
@@ -976,12 +2478,107 @@ impl Json {
         builder.build()
     }
 
+    /// Decodes the json value at the start of a string, returning it along
+    /// with the number of bytes it took up, including any whitespace after
+    /// it. Whatever follows is left alone, so that values written one after
+    /// the other can be read in turn.
+    ///
+    /// ```
+    /// use rustc_serialize::json::Json;
+    ///
+    /// let mut rest = "{\"a\": 1} [2]\n3\n";
+    /// let mut values = Vec::new();
+    /// while !rest.is_empty() {
+    ///     let (json, len) = Json::from_str_prefix(rest).unwrap();
+    ///     values.push(json);
+    ///     rest = &rest[len..];
+    /// }
+    /// assert_eq!(values.len(), 3);
+    /// ```
+    pub fn from_str_prefix(s: &str) -> Result<(Self, usize), BuilderError> {
+        let read = Cell::new(0);
+        let chars = s.chars().inspect(|c| read.set(read.get() + c.len_utf8()));
+        let mut builder = Builder::new(chars);
+        builder.bump();
+        let json = try!(builder.build_value());
+        builder.parser.parse_whitespace();
+        // The parser has always read the character after the value.
+        let lookahead = builder.parser.ch.map_or(0, |c| c.len_utf8());
+        Ok((json, read.get() - lookahead))
+    }
+
     /// Borrow this json object as a pretty object to generate a pretty
     /// representation for it via `Display`.
     pub fn pretty(&self) -> PrettyJson {
         PrettyJson { inner: self }
     }
 
+    /// Encodes the value into compact JSON bytes, the same as `to_string`
+    /// gives but without going through a `String`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut writer = BytesWriter::new(&mut bytes);
+            // Nothing in a `Json` value can fail to encode.
+            self.encode(&mut Encoder::new(&mut writer)).unwrap();
+        }
+        bytes
+    }
+
+    /// Encodes the value into pretty-printed JSON bytes, the same as
+    /// `pretty().to_string()` gives but without going through a `String`.
+    pub fn to_pretty_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut writer = BytesWriter::new(&mut bytes);
+            self.encode(&mut Encoder::new_pretty(&mut writer)).unwrap();
+        }
+        bytes
+    }
+
+    /// Returns a copy of this value with the keys of every object, however
+    /// deeply nested, in sorted order.
+    ///
+    /// Objects are `BTreeMap`s which always keep their keys sorted, so this
+    /// is the same as `clone`.
+    pub fn sorted(&self) -> Json {
+        self.clone()
+    }
+
+    /// Rewrites the numbers in this value, however deeply nested, so that
+    /// equal numbers compare and encode the same:
+    ///
+    /// * floats with an integer value which a float holds exactly, up to
+    ///   2^53, become `U64`, or `I64` if negative, so `-0.0`, `0.0` and `0`
+    ///   all become `U64(0)`;
+    /// * `I64`s which aren't negative become `U64`;
+    /// * infinite and NaN floats become `Null`, which is how they encode.
+    pub fn normalize(&mut self) {
+        const MAX_EXACT: f64 = 9007199254740992.0;
+
+        let normalized = match *self {
+            Json::I64(n) if n >= 0 => Json::U64(n as u64),
+            Json::F64(f) if !f.is_finite() => Json::Null,
+            Json::F64(f) if f.trunc() == f && f.abs() <= MAX_EXACT => {
+                if f < 0.0 { Json::I64(f as i64) } else { Json::U64(f as u64) }
+            }
+            Json::Array(ref mut array) => {
+                for value in array.iter_mut() {
+                    value.normalize();
+                }
+                return
+            }
+            Json::Object(ref mut object) => {
+                for (_, value) in object.iter_mut() {
+                    value.normalize();
+                }
+                return
+            }
+            _ => return,
+        };
+        *self = normalized;
+    }
+
      /// If the Json value is an Object, returns the value associated with the provided key.
     /// Otherwise, returns None.
     pub fn find<'a>(&'a self, key: &str) -> Option<&'a Json>{
@@ -991,6 +2588,28 @@ impl Json {
         }
     }
 
+    /// Like `find`, but tells why the lookup failed: `self` not being an
+    /// object, or the key missing from it.
+    pub fn get<'a>(&'a self, key: &str) -> Result<&'a Json, LookupError> {
+        match *self {
+            Json::Object(ref map) => {
+                map.get(key).ok_or_else(|| LookupError::MissingKey(key.to_string()))
+            }
+            _ => Err(LookupError::ExpectedObject),
+        }
+    }
+
+    /// Returns the element at `index` if `self` is an array, and tells why
+    /// otherwise.
+    pub fn get_index(&self, index: usize) -> Result<&Json, LookupError> {
+        match *self {
+            Json::Array(ref array) => {
+                array.get(index).ok_or(LookupError::IndexOutOfBounds(index, array.len()))
+            }
+            _ => Err(LookupError::ExpectedArray),
+        }
+    }
+
     /// Attempts to get a nested Json Object for each key in `keys`.
     /// If any key is found not to exist, find_path will return None.
     /// Otherwise, it will return the Json value associated with the final key.
@@ -1028,6 +2647,74 @@ impl Json {
         }
     }
 
+    /// Returns an iterator over every leaf of this Json value, depth-first
+    /// and in document order, along with its RFC 6901 JSON Pointer path
+    /// (e.g. `"/a/0/b"`). Empty arrays and objects are yielded as leaves, and
+    /// a scalar root is yielded with the empty path `""`.
+    pub fn entries<'a>(&'a self) -> Entries<'a> {
+        Entries { stack: vec![(string::String::new(), self)] }
+    }
+
+    /// Compares two Json values as `==` does, except that the elements of
+    /// arrays, at any depth, may come in any order. Each element still has
+    /// to be matched by exactly one in the other array, so `[1, 1, 2]` and
+    /// `[1, 2, 2]` differ.
+    ///
+    /// This suits asserting on documents such as API responses whose arrays
+    /// aren't in a meaningful order. Arrays are compared in quadratic time.
+    ///
+    /// ```
+    /// use rustc_serialize::json::Json;
+    ///
+    /// let a = Json::from_str("{\"ids\": [1, 2, [3, 4]]}").unwrap();
+    /// let b = Json::from_str("{\"ids\": [[4, 3], 1, 2]}").unwrap();
+    /// assert!(a != b);
+    /// assert!(a.deep_eq_unordered(&b));
+    /// ```
+    pub fn deep_eq_unordered(&self, other: &Json) -> bool {
+        match (self, other) {
+            (&Json::Array(ref a), &Json::Array(ref b)) => {
+                if a.len() != b.len() {
+                    return false;
+                }
+                let mut matched = vec![false; b.len()];
+                a.iter().all(|x| {
+                    let found = b.iter().enumerate().position(|(i, y)| {
+                        !matched[i] && x.deep_eq_unordered(y)
+                    });
+                    match found {
+                        Some(i) => { matched[i] = true; true }
+                        None => false,
+                    }
+                })
+            }
+            (&Json::Object(ref a), &Json::Object(ref b)) => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|((ka, va), (kb, vb))| {
+                    ka == kb && va.deep_eq_unordered(vb)
+                })
+            }
+            _ => self == other,
+        }
+    }
+
+    /// Returns the number of elements of an Array, members of an Object or
+    /// bytes of a String, the same as `len` on the value it holds. Returns
+    /// None otherwise.
+    pub fn len(&self) -> Option<usize> {
+        match *self {
+            Json::Array(ref array) => Some(array.len()),
+            Json::Object(ref map) => Some(map.len()),
+            Json::String(ref s) => Some(s.len()),
+            _ => None
+        }
+    }
+
+    /// Returns true if the Json value is an empty Array, Object or String.
+    /// Returns false otherwise, including for values without a `len`.
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+
     /// Returns true if the Json value is an Object. Returns false otherwise.
     pub fn is_object<'a>(&'a self) -> bool {
         self.as_object().is_some()
@@ -1092,6 +2779,30 @@ impl Json {
         }
     }
 
+    /// If the Json value is an Array of Strings, returns the strs they hold.
+    /// Returns None otherwise, including when any element isn't a String.
+    pub fn as_str_array<'a>(&'a self) -> Option<Vec<&'a str>> {
+        self.as_array().and_then(|array| array.iter().map(|v| v.as_string()).collect())
+    }
+
+    /// If the Json value is an Array of numbers, returns them as `as_i64`
+    /// does. Returns None otherwise, including when any element isn't one.
+    pub fn as_i64_array(&self) -> Option<Vec<i64>> {
+        self.as_array().and_then(|array| array.iter().map(|v| v.as_i64()).collect())
+    }
+
+    /// If the Json value is an Array of numbers, returns them as `as_u64`
+    /// does. Returns None otherwise, including when any element isn't one.
+    pub fn as_u64_array(&self) -> Option<Vec<u64>> {
+        self.as_array().and_then(|array| array.iter().map(|v| v.as_u64()).collect())
+    }
+
+    /// If the Json value is an Array of numbers, returns them as `as_f64`
+    /// does. Returns None otherwise, including when any element isn't one.
+    pub fn as_f64_array(&self) -> Option<Vec<f64>> {
+        self.as_array().and_then(|array| array.iter().map(|v| v.as_f64()).collect())
+    }
+
     /// Returns true if the Json value is a String. Returns false otherwise.
     pub fn is_string<'a>(&'a self) -> bool {
         self.as_string().is_some()
@@ -1106,6 +2817,18 @@ impl Json {
         }
     }
 
+    /// Creates a Json String holding the standard base64 encoding of `bytes`,
+    /// the same representation `base64::Base64Bytes` encodes to.
+    pub fn from_bytes_base64(bytes: &[u8]) -> Json {
+        Json::String(bytes.to_base64(base64::STANDARD))
+    }
+
+    /// If the Json value is a String holding valid base64, returns the bytes
+    /// it decodes to. Returns None otherwise.
+    pub fn as_base64_bytes(&self) -> Option<Vec<u8>> {
+        self.as_string().and_then(|s| s.from_base64().ok())
+    }
+
     /// Returns true if the Json value is a Number. Returns false otherwise.
     pub fn is_number(&self) -> bool {
         match *self {
@@ -1199,65 +2922,465 @@ impl Json {
     }
 }
 
+// What indexing a Json value evaluates to when there is nothing at the index.
+// A static rather than a const, as borrowing a const for as long as `self`
+// relies on rvalue promotion, which needs Rust 1.21.
+static NULL: Json = Json::Null;
+
+/// Looks up a key of an object.
+///
+/// Missing keys, and indexing anything other than an object, evaluate to
+/// `Json::Null` rather than panicking, so lookups can be chained:
+/// `json["a"]["b"][0]` is `Null` if any step of the way is missing.
 impl<'a> Index<&'a str>  for Json {
     type Output = Json;
 
     fn index(&self, idx: &str) -> &Json {
-        self.find(idx).unwrap()
+        match self {
+            &Json::Object(ref map) => map.get(idx).unwrap_or(&NULL),
+            _ => &NULL
+        }
     }
 }
 
+/// Looks up an element of an array.
+///
+/// Indices out of bounds, and indexing anything other than an array,
+/// evaluate to `Json::Null` rather than panicking.
 impl Index<usize> for Json {
     type Output = Json;
 
     fn index<'a>(&'a self, idx: usize) -> &'a Json {
         match self {
-            &Json::Array(ref v) => &v[idx],
-            _ => panic!("can only index Json with usize if it is an array")
+            &Json::Array(ref v) => v.get(idx).unwrap_or(&NULL),
+            _ => &NULL
         }
     }
 }
 
-/// The output of the streaming parser.
-#[derive(PartialEq, Debug)]
-pub enum JsonEvent {
-    ObjectStart,
-    ObjectEnd,
-    ArrayStart,
-    ArrayEnd,
-    BooleanValue(bool),
-    I64Value(i64),
-    U64Value(u64),
-    F64Value(f64),
-    StringValue(string::String),
-    NullValue,
-    Error(ParserError),
+/// A total order on JSON values, for sorting them or keeping them in a
+/// `BTreeSet`.
+///
+/// Values of different kinds are ordered by kind, as `null` < booleans <
+/// numbers < strings < arrays < objects. Within a kind, `false` comes
+/// before `true`, strings are ordered by their bytes, and arrays and objects
+/// lexicographically, objects comparing their members in key order as
+/// `(key, value)` pairs.
+///
+/// Numbers are ordered by value, whichever variants hold them: `U64(1)`
+/// comes before `F64(1.5)`, which comes before `I64(2)`. NaN comes after
+/// every other number, and is equal to itself as the order must be total.
+/// Numbers of the same value in different variants, which aren't `==`, are
+/// then ordered as `I64`, `U64` and `F64`.
+impl Ord for Json {
+    fn cmp(&self, other: &Json) -> Ordering {
+        let (kind, variant) = self.order_rank();
+        let (other_kind, other_variant) = other.order_rank();
+        if kind != other_kind {
+            return kind.cmp(&other_kind);
+        }
+        let ord = match (self, other) {
+            (&Json::Boolean(a), &Json::Boolean(b)) => a.cmp(&b),
+            (&Json::String(ref a), &Json::String(ref b)) => a.cmp(b),
+            (&Json::Array(ref a), &Json::Array(ref b)) => a.cmp(b),
+            (&Json::Object(ref a), &Json::Object(ref b)) => a.cmp(b),
+            _ => match (self.as_order_number(), other.as_order_number()) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                _ => Ordering::Equal,
+            },
+        };
+        if ord == Ordering::Equal { variant.cmp(&other_variant) } else { ord }
+    }
 }
 
-#[derive(PartialEq, Debug)]
-enum ParserState {
-    // Parse a value in an array, true means first element.
-    ParseArray(bool),
-    // Parse ',' or ']' after an element in an array.
-    ParseArrayComma,
-    // Parse a key:value in an object, true means first element.
-    ParseObject(bool),
-    // Parse ',' or ']' after an element in an object.
-    ParseObjectComma,
-    // Initial state.
-    ParseStart,
-    // Expecting the stream to end.
-    ParseBeforeFinish,
-    // Parsing can't continue.
-    ParseFinished,
+impl PartialOrd for Json {
+    fn partial_cmp(&self, other: &Json) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
-/// A Stack represents the current position of the parser in the logical
-/// structure of the JSON stream.
-/// For example foo.bar[3].x
-pub struct Stack {
-    stack: Vec<InternalStackElement>,
-    str_buffer: Vec<u8>,
+/// Equality as `Ord` has it: values are equal when they are of the same
+/// variant and hold equal values, so `U64(1)` isn't equal to `F64(1.0)`.
+///
+/// Unlike `f64`, `F64` NaNs are equal to each other, so that `==` is
+/// reflexive.
+impl PartialEq for Json {
+    fn eq(&self, other: &Json) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Json {}
+
+/// Hashes values consistently with `==`, so that `Json` can be the key of a
+/// `HashMap`.
+///
+/// Numbers are hashed by value whichever variant holds them: `F64(1.0)`
+/// hashes as `U64(1)` does, and `F64(-0.0)` as `F64(0.0)`. All NaNs hash
+/// alike, as they are all `==`.
+impl Hash for Json {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.order_rank().0.hash(state);
+        match *self {
+            Json::Null => {}
+            Json::Boolean(b) => b.hash(state),
+            Json::String(ref s) => s.hash(state),
+            Json::Array(ref a) => a.hash(state),
+            Json::Object(ref o) => o.hash(state),
+            _ => match self.as_order_number() {
+                Some(OrderNumber::Int(neg, hi, lo)) => (0u8, neg, hi, lo).hash(state),
+                Some(OrderNumber::Float(f)) => match split_float(f) {
+                    Some((neg, hi, lo, frac)) if frac == 0.0 => {
+                        // -0.0 splits as not negative
+                        (0u8, neg, hi, lo).hash(state)
+                    }
+                    _ if f.is_nan() => 1u8.hash(state),
+                    _ => {
+                        let bits: u64 = unsafe { mem::transmute(f) };
+                        (2u8, bits).hash(state)
+                    }
+                },
+                None => {}
+            },
+        }
+    }
+}
+
+impl Json {
+    // The name of the variant, for `JsonTypeMismatch`.
+    #[cfg(feature = "try-from")]
+    fn variant_name(&self) -> &'static str {
+        match *self {
+            Json::I64(_) => "I64",
+            Json::U64(_) => "U64",
+            Json::F64(_) => "F64",
+            Json::String(_) => "String",
+            Json::Boolean(_) => "Boolean",
+            Json::Array(_) => "Array",
+            Json::Object(_) => "Object",
+            Json::Null => "Null",
+        }
+    }
+
+    // The rank of the kind of a value in the order of `Ord`, and that of
+    // its variant among the variants holding numbers.
+    fn order_rank(&self) -> (u8, u8) {
+        match *self {
+            Json::Null => (0, 0),
+            Json::Boolean(_) => (1, 0),
+            Json::I64(_) => (2, 0),
+            Json::U64(_) => (2, 1),
+            Json::F64(_) => (2, 4),
+            Json::String(_) => (3, 0),
+            Json::Array(_) => (4, 0),
+            Json::Object(_) => (5, 0),
+        }
+    }
+
+    fn as_order_number(&self) -> Option<OrderNumber> {
+        match *self {
+            Json::I64(n) if n < 0 => Some(OrderNumber::Int(true, 0, !(n as u64) + 1)),
+            Json::I64(n) => Some(OrderNumber::Int(false, 0, n as u64)),
+            Json::U64(n) => Some(OrderNumber::Int(false, 0, n)),
+            Json::F64(f) => Some(OrderNumber::Float(f)),
+            _ => None,
+        }
+    }
+}
+
+// A number of any variant, for ordering numbers by value. Integers are
+// held as whether they are negative, and the high and low 64 bits of their
+// magnitude.
+#[derive(Clone, Copy)]
+enum OrderNumber {
+    Int(bool, u64, u64),
+    Float(f64),
+}
+
+impl OrderNumber {
+    fn cmp(&self, other: &OrderNumber) -> Ordering {
+        match (*self, *other) {
+            (OrderNumber::Float(a), OrderNumber::Float(b)) => match a.partial_cmp(&b) {
+                Some(ord) => ord,
+                // NaN comes last
+                None => a.is_nan().cmp(&b.is_nan()),
+            },
+            (OrderNumber::Int(neg, hi, lo), OrderNumber::Float(f)) => {
+                cmp_int_float(neg, hi, lo, f)
+            }
+            (OrderNumber::Float(f), OrderNumber::Int(neg, hi, lo)) => {
+                cmp_int_float(neg, hi, lo, f).reverse()
+            }
+            (OrderNumber::Int(a_neg, a_hi, a_lo), OrderNumber::Int(b_neg, b_hi, b_lo)) => {
+                cmp_split((a_neg, a_hi, a_lo, 0.0), (b_neg, b_hi, b_lo, 0.0))
+            }
+        }
+    }
+}
+
+// Compares an integer with a float exactly, by splitting the float up the
+// same way as the integer, plus its fractional part.
+fn cmp_int_float(neg: bool, hi: u64, lo: u64, f: f64) -> Ordering {
+    match split_float(f) {
+        Some(split) => cmp_split((neg, hi, lo, 0.0), split),
+        None if f > 0.0 || f.is_nan() => Ordering::Less,
+        None => Ordering::Greater,
+    }
+}
+
+// Splits a float up as with `cmp_split`, or returns `None` if it is NaN or
+// of a magnitude of 2^128 or more, beyond any integer.
+fn split_float(f: f64) -> Option<(bool, u64, u64, f64)> {
+    let two_64 = (1u64 << 63) as f64 * 2.0;
+    if f.is_nan() || f.abs() >= two_64 * two_64 {
+        return None;
+    }
+    let int = f.abs().trunc();
+    let hi = (int / two_64).trunc();
+    // All exact: the low bits of a float of magnitude below 2^128 fit in
+    // another float.
+    let lo = int - hi * two_64;
+    Some((f < 0.0, hi as u64, lo as u64, f.abs() - int))
+}
+
+// Compares numbers split up as whether they are negative, the high and low
+// 64 bits of the magnitude of their integral part and their fractional part.
+fn cmp_split(a: (bool, u64, u64, f64), b: (bool, u64, u64, f64)) -> Ordering {
+    if a.0 != b.0 {
+        return if a.0 { Ordering::Less } else { Ordering::Greater };
+    }
+    let ord = match (a.1, a.2).cmp(&(b.1, b.2)) {
+        Ordering::Equal => a.3.partial_cmp(&b.3).unwrap_or(Ordering::Equal),
+        ord => ord,
+    };
+    if a.0 { ord.reverse() } else { ord }
+}
+
+/// An iterator over the leaves of a Json value and their JSON Pointer paths.
+///
+/// This struct is created by the `entries` method on `Json`.
+pub struct Entries<'a> {
+    // Nodes still to be visited, top of the stack first.
+    stack: Vec<(string::String, &'a Json)>,
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = (string::String, &'a Json);
+
+    fn next(&mut self) -> Option<(string::String, &'a Json)> {
+        loop {
+            let (path, value) = match self.stack.pop() {
+                Some(entry) => entry,
+                None => return None,
+            };
+            match *value {
+                Json::Array(ref v) if !v.is_empty() => {
+                    for (i, child) in v.iter().enumerate().rev() {
+                        self.stack.push((format!("{}/{}", path, i), child));
+                    }
+                }
+                Json::Object(ref o) if !o.is_empty() => {
+                    for (key, child) in o.iter().rev() {
+                        let mut child_path = path.clone();
+                        child_path.push('/');
+                        escape_pointer_token(&mut child_path, key);
+                        self.stack.push((child_path, child));
+                    }
+                }
+                _ => return Some((path, value)),
+            }
+        }
+    }
+}
+
+// Appends `key` to `path` with `~` and `/` escaped as per RFC 6901.
+fn escape_pointer_token(path: &mut string::String, key: &str) {
+    for c in key.chars() {
+        match c {
+            '~' => path.push_str("~0"),
+            '/' => path.push_str("~1"),
+            c => path.push(c),
+        }
+    }
+}
+
+/// The structure of some JSON documents, as worked out by `infer_schema`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum JsonSchema {
+    /// Nothing is known about the value, as for the elements of arrays which
+    /// were always empty.
+    Any,
+    Null,
+    Bool,
+    /// A number, whether integer or floating point.
+    Number,
+    String,
+    /// An array, with the structure of all of its elements.
+    Array(Box<JsonSchema>),
+    /// An object, with the structure of each member seen.
+    Object(HashMap<string::String, FieldSchema>),
+    /// A value seen with different types, one structure for each. None of
+    /// them is a `Union` or `Any`, and no two are of the same type.
+    Union(Vec<JsonSchema>),
+}
+
+/// The structure of a member of the objects described by a
+/// `JsonSchema::Object`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FieldSchema {
+    pub schema: JsonSchema,
+    /// Whether the member was in all of the objects.
+    pub required: bool,
+}
+
+/// Works out the structure of a sample of JSON documents, such as the
+/// records of a log, describing them all at once.
+///
+/// An object member missing from some of the objects is not `required`,
+/// and values of different types, be they in different documents or
+/// elements of the same array, make a `Union`. An empty sample is `Any`.
+///
+/// ```
+/// use rustc_serialize::json::{self, Json, JsonSchema};
+///
+/// let samples = [Json::from_str("{\"id\": 1, \"tags\": [\"a\"]}").unwrap(),
+///                Json::from_str("{\"id\": 2, \"tags\": [], \"note\": null}").unwrap()];
+/// let schema = match json::infer_schema(&samples) {
+///     JsonSchema::Object(fields) => fields,
+///     other => panic!("{:?}", other),
+/// };
+/// assert_eq!(schema["id"].schema, JsonSchema::Number);
+/// assert!(schema["id"].required);
+/// assert_eq!(schema["tags"].schema, JsonSchema::Array(Box::new(JsonSchema::String)));
+/// assert!(!schema["note"].required);
+/// ```
+pub fn infer_schema(samples: &[Json]) -> JsonSchema {
+    samples.iter().fold(JsonSchema::Any, |schema, sample| merge_schemas(schema, schema_of(sample)))
+}
+
+fn schema_of(json: &Json) -> JsonSchema {
+    match *json {
+        Json::Null => JsonSchema::Null,
+        Json::Boolean(_) => JsonSchema::Bool,
+        Json::String(_) => JsonSchema::String,
+        Json::Array(ref v) => JsonSchema::Array(Box::new(infer_schema(v))),
+        Json::Object(ref o) => {
+            JsonSchema::Object(o.iter().map(|(k, v)| {
+                (k.clone(), FieldSchema { schema: schema_of(v), required: true })
+            }).collect())
+        }
+        _ => JsonSchema::Number,
+    }
+}
+
+// Combines two structures into one describing the values of both.
+fn merge_schemas(a: JsonSchema, b: JsonSchema) -> JsonSchema {
+    let mut variants = into_variants(a);
+    for schema in into_variants(b) {
+        match variants.iter().position(|v| same_type(v, &schema)) {
+            Some(i) => {
+                let merged = merge_same_type(variants.remove(i), schema);
+                variants.insert(i, merged);
+            }
+            None => variants.push(schema),
+        }
+    }
+    match variants.len() {
+        0 => JsonSchema::Any,
+        1 => variants.pop().unwrap(),
+        _ => JsonSchema::Union(variants),
+    }
+}
+
+fn into_variants(schema: JsonSchema) -> Vec<JsonSchema> {
+    match schema {
+        JsonSchema::Any => Vec::new(),
+        JsonSchema::Union(variants) => variants,
+        schema => vec![schema],
+    }
+}
+
+fn same_type(a: &JsonSchema, b: &JsonSchema) -> bool {
+    match (a, b) {
+        (&JsonSchema::Null, &JsonSchema::Null) |
+        (&JsonSchema::Bool, &JsonSchema::Bool) |
+        (&JsonSchema::Number, &JsonSchema::Number) |
+        (&JsonSchema::String, &JsonSchema::String) |
+        (&JsonSchema::Array(_), &JsonSchema::Array(_)) |
+        (&JsonSchema::Object(_), &JsonSchema::Object(_)) => true,
+        _ => false,
+    }
+}
+
+fn merge_same_type(a: JsonSchema, b: JsonSchema) -> JsonSchema {
+    match (a, b) {
+        (JsonSchema::Array(a), JsonSchema::Array(b)) => {
+            JsonSchema::Array(Box::new(merge_schemas(*a, *b)))
+        }
+        (JsonSchema::Object(mut a), JsonSchema::Object(b)) => {
+            for (key, field) in a.iter_mut() {
+                if !b.contains_key(key) {
+                    field.required = false;
+                }
+            }
+            for (key, field) in b {
+                let merged = match a.remove(&key) {
+                    Some(old) => FieldSchema {
+                        schema: merge_schemas(old.schema, field.schema),
+                        required: old.required && field.required,
+                    },
+                    None => FieldSchema { schema: field.schema, required: false },
+                };
+                a.insert(key, merged);
+            }
+            JsonSchema::Object(a)
+        }
+        (a, _) => a,
+    }
+}
+
+/// The output of the streaming parser.
+#[derive(PartialEq, Debug)]
+pub enum JsonEvent {
+    ObjectStart,
+    ObjectEnd,
+    ArrayStart,
+    ArrayEnd,
+    BooleanValue(bool),
+    I64Value(i64),
+    U64Value(u64),
+    F64Value(f64),
+    StringValue(string::String),
+    NullValue,
+    Error(ParserError),
+}
+
+#[derive(PartialEq, Debug)]
+enum ParserState {
+    // Parse a value in an array, true means first element.
+    ParseArray(bool),
+    // Parse ',' or ']' after an element in an array.
+    ParseArrayComma,
+    // Parse a key:value in an object, true means first element.
+    ParseObject(bool),
+    // Parse ',' or ']' after an element in an object.
+    ParseObjectComma,
+    // Initial state.
+    ParseStart,
+    // Expecting the stream to end.
+    ParseBeforeFinish,
+    // Skipping input after a syntax error, in recovery mode.
+    ParseRecover,
+    // Parsing can't continue.
+    ParseFinished,
+}
+
+/// A Stack represents the current position of the parser in the logical
+/// structure of the JSON stream.
+/// For example foo.bar[3].x
+pub struct Stack {
+    stack: Vec<InternalStackElement>,
+    str_buffer: Vec<u8>,
 }
 
 /// StackElements compose a Stack.
@@ -1379,6 +3502,13 @@ impl Stack {
         }
     }
 
+    // Used by Parser to drop the elements above the first `len` ones.
+    fn truncate(&mut self, len: usize) {
+        while self.len() > len {
+            self.pop();
+        }
+    }
+
     // Used by Parser to increment the index of the top-most element.
     fn bump_index(&mut self) {
         let len = self.stack.len();
@@ -1390,8 +3520,38 @@ impl Stack {
     }
 }
 
+// The most significant digits a number may have unless
+// `Parser::set_max_number_len` says otherwise.
+const DEFAULT_MAX_NUMBER_LEN: usize = 64;
+
 /// A streaming JSON parser implemented as an iterator of JsonEvent, consuming
 /// an iterator of char.
+///
+/// Each call to `next` parses just enough of the input to produce the next
+/// event, so a document can be walked incrementally and abandoned part way.
+/// Object keys aren't events of their own: the key of the member a value
+/// belongs to is at the top of `stack` while the value's events, including
+/// the end of an array or object, are produced.
+///
+/// ```
+/// use rustc_serialize::json::{JsonEvent, Parser, StackElement};
+///
+/// let mut parser = Parser::new("{\"a\": [true], \"b\": null}".chars());
+/// let mut keys = Vec::new();
+/// while let Some(event) = parser.next() {
+///     if let JsonEvent::Error(e) = event {
+///         panic!("{}", e);
+///     }
+///     if parser.stack().len() == 1 {
+///         if let Some(StackElement::Key(key)) = parser.stack().top() {
+///             keys.push((key.to_string(), event));
+///         }
+///     }
+/// }
+/// assert_eq!(keys, [("a".to_string(), JsonEvent::ArrayStart),
+///                   ("a".to_string(), JsonEvent::ArrayEnd),
+///                   ("b".to_string(), JsonEvent::NullValue)]);
+/// ```
 pub struct Parser<T> {
     rdr: T,
     ch: Option<char>,
@@ -1402,12 +3562,58 @@ pub struct Parser<T> {
     stack: Stack,
     // A state machine is kept to make it possible to interrupt and resume parsing.
     state: ParserState,
+    // Whether to carry on after syntax errors, and in that case the closing
+    // bracket of every array or object which is open.
+    recover: bool,
+    containers: Vec<char>,
+    // Set while inside a string, so that recovery can skip the rest of it.
+    in_str: bool,
+    // The significant digits of the number being parsed, and the most it is
+    // allowed. The zeros since the last other digit only count once another
+    // digit follows, and are None before the first one of the mantissa or
+    // exponent.
+    number_digits: usize,
+    number_zeros: Option<usize>,
+    max_number_len: usize,
+    // The text of the last number parsed, which floats are read from.
+    number_text: string::String,
+    // Whether to replace surrogates which aren't part of a pair with U+FFFD
+    // rather than fail.
+    replace_lone_surrogates: bool,
+    // Whether to accept the number forms allowed by `set_relaxed`.
+    relaxed: bool,
+    max_depth: Option<usize>,
+    allow_trailing_comma: bool,
+    allow_comments: bool,
+    case_insensitive_literals: bool,
 }
 
 impl<T: Iterator<Item = char>> Iterator for Parser<T> {
     type Item = JsonEvent;
 
     fn next(&mut self) -> Option<JsonEvent> {
+        let evt = self.next_event();
+        if self.recover {
+            match evt {
+                Some(ArrayStart) => self.containers.push(']'),
+                Some(ObjectStart) => self.containers.push('}'),
+                Some(ArrayEnd) | Some(ObjectEnd) => { self.containers.pop(); }
+                Some(Error(_)) => self.state = ParseRecover,
+                _ => {}
+            }
+        }
+        evt
+    }
+}
+
+impl<T: Iterator<Item = char>> Parser<T> {
+    fn next_event(&mut self) -> Option<JsonEvent> {
+        if self.state == ParseRecover {
+            if let Some(evt) = self.recover_from_error() {
+                return Some(evt);
+            }
+        }
+
         if self.state == ParseFinished {
             return None;
         }
@@ -1425,9 +3631,7 @@ impl<T: Iterator<Item = char>> Iterator for Parser<T> {
 
         return Some(self.parse());
     }
-}
 
-impl<T: Iterator<Item = char>> Parser<T> {
     /// Creates the JSON parser.
     pub fn new(rdr: T) -> Parser<T> {
         let mut p = Parser {
@@ -1437,11 +3641,108 @@ impl<T: Iterator<Item = char>> Parser<T> {
             col: 0,
             stack: Stack::new(),
             state: ParseStart,
+            recover: false,
+            containers: Vec::new(),
+            in_str: false,
+            number_digits: 0,
+            number_zeros: None,
+            max_number_len: DEFAULT_MAX_NUMBER_LEN,
+            number_text: string::String::new(),
+            replace_lone_surrogates: false,
+            relaxed: false,
+            max_depth: None,
+            allow_trailing_comma: false,
+            allow_comments: false,
+            case_insensitive_literals: false,
         };
         p.bump();
         return p;
     }
 
+    /// Creates a JSON parser which carries on after syntax errors.
+    ///
+    /// After yielding an `Error` event, the parser skips ahead to the next
+    /// `,`, `]` or `}` of the innermost open array or object and resumes
+    /// from there, as if the element or member in error had not been there.
+    ///
+    /// Recovery is a heuristic. A missing or mismatched bracket is taken to
+    /// close the innermost open array or object, and running out of input is
+    /// reported once however many of them are still open, so errors after
+    /// the first one may be consequences of it. An error outside of any array
+    /// or object ends parsing.
+    pub fn new_recovering(rdr: T) -> Parser<T> {
+        let mut p = Parser::new(rdr);
+        p.recover = true;
+        p
+    }
+
+    /// Sets the most significant digits a number may have, counting those of
+    /// the mantissa and of the exponent. Zeros before the first other digit
+    /// or after the last one aren't significant. Longer numbers are reported
+    /// as `NumberTooLong` as soon as the limit is crossed, so that a huge
+    /// number doesn't take long to reject.
+    ///
+    /// The default is 64, which leaves room for any number the encoder
+    /// writes: an `f64` written out in full, such as `1e-300`, has at most
+    /// 17 significant digits.
+    pub fn set_max_number_len(&mut self, max: usize) {
+        self.max_number_len = max;
+    }
+
+    /// Sets how to handle a `\u` escape of a UTF-16 surrogate which isn't
+    /// part of a pair. By default it is reported as a `LoneSurrogate` error,
+    /// and with `replace` set it is replaced with U+FFFD REPLACEMENT
+    /// CHARACTER. Either way, parsed strings are always valid UTF-8.
+    pub fn set_replace_lone_surrogates(&mut self, replace: bool) {
+        self.replace_lone_surrogates = replace;
+    }
+
+    /// Sets whether to accept numbers written in some ways which JSON
+    /// forbids, but which some producers write anyway. These are, and are
+    /// limited to:
+    ///
+    /// * a leading `+`, as in `+1.5`;
+    /// * a decimal point with no digits before it, as in `.5` or `-.5`;
+    /// * a decimal point with no digits after it, as in `5.` or `5.e3`.
+    ///
+    /// There still have to be digits on at least one side of the decimal
+    /// point, so `.` and `+.` are errors, and leading zeros are still
+    /// rejected. By default the parser is strict, and rejects all of these.
+    pub fn set_relaxed(&mut self, relaxed: bool) {
+        self.relaxed = relaxed;
+    }
+
+    /// Sets how deeply arrays and objects may be nested, a value at the top
+    /// level being at depth 1. An array or object going deeper is reported
+    /// as `NestingTooDeep`. `None`, the default, sets no limit.
+    pub fn set_max_depth(&mut self, max: Option<usize>) {
+        self.max_depth = max;
+    }
+
+    /// Sets whether to accept a comma after the last element of an array or
+    /// the last member of an object, as in `[1,]` and `{"a":1,}`. By default
+    /// it is an error.
+    pub fn set_allow_trailing_comma(&mut self, allow: bool) {
+        self.allow_trailing_comma = allow;
+    }
+
+    /// Sets whether to skip comments wherever whitespace may be, as in
+    /// JSON5: line comments from `//` to the end of the line, and block
+    /// comments from `/*` to the next `*/`. An unterminated block comment
+    /// runs to the end of the input. By default a `/` is a syntax error.
+    pub fn set_allow_comments(&mut self, allow: bool) {
+        self.allow_comments = allow;
+    }
+
+    /// Sets whether to accept the literals `true`, `false` and `null` in
+    /// any mix of upper and lower case, as in `TRUE` or `Null`, as some
+    /// producers write them. Only these three literals are affected: strings
+    /// are read as they are, and the `e` of an exponent could always be
+    /// either case. By default only lower case is accepted, as JSON requires.
+    pub fn set_case_insensitive_literals(&mut self, allow: bool) {
+        self.case_insensitive_literals = allow;
+    }
+
     /// Provides access to the current position in the logical structure of the
     /// JSON stream.
     pub fn stack<'l>(&'l self) -> &'l Stack {
@@ -1474,69 +3775,159 @@ impl<T: Iterator<Item = char>> Parser<T> {
     }
 
     fn parse_whitespace(&mut self) {
-        while self.ch_is(' ') ||
-              self.ch_is('\n') ||
-              self.ch_is('\t') ||
-              self.ch_is('\r') { self.bump(); }
+        loop {
+            while self.ch_is(' ') ||
+                  self.ch_is('\n') ||
+                  self.ch_is('\t') ||
+                  self.ch_is('\r') { self.bump(); }
+            if !(self.allow_comments && self.ch_is('/') && self.skip_comment()) {
+                return;
+            }
+        }
+    }
+
+    // Skips the comment starting at the current `/`, returning whether there
+    // was one. Otherwise the parser is left on the `/`, which is an error
+    // wherever whitespace may be, though what followed it is lost.
+    fn skip_comment(&mut self) -> bool {
+        let (line, col) = (self.line, self.col);
+        self.bump();
+        if self.ch_is('/') {
+            while !self.eof() && !self.ch_is('\n') {
+                self.bump();
+            }
+        } else if self.ch_is('*') {
+            self.bump();
+            loop {
+                match self.ch {
+                    None => break,
+                    Some('*') => {
+                        self.bump();
+                        if self.ch_is('/') {
+                            self.bump();
+                            break;
+                        }
+                    }
+                    Some(_) => self.bump(),
+                }
+            }
+        } else {
+            self.ch = Some('/');
+            self.line = line;
+            self.col = col;
+            return false;
+        }
+        true
     }
 
     fn parse_number(&mut self) -> JsonEvent {
         let mut neg = false;
+        self.number_digits = 0;
+        self.number_zeros = None;
+        self.number_text.clear();
 
         if self.ch_is('-') {
-            self.bump();
+            self.bump_number();
             neg = true;
+        } else if self.relaxed && self.ch_is('+') {
+            // Left out of the text, which is kept as JSON would write it.
+            self.bump();
         }
 
-        let res = match self.parse_u64() {
-            Ok(res) => res,
-            Err(e) => { return Error(e); }
+        // In relaxed mode the digits before the decimal point may be left out.
+        let leading_dot = self.relaxed && self.ch_is('.');
+        let res = if leading_dot {
+            self.number_text.push('0');
+            Ok(0)
+        } else {
+            match self.parse_integer() {
+                Ok(res) => res,
+                Err(e) => { return Error(e); }
+            }
         };
 
         if self.ch_is('.') || self.ch_is('e') || self.ch_is('E') {
-            let mut res = res as f64;
-
             if self.ch_is('.') {
-                res = match self.parse_decimal(res) {
-                    Ok(res) => res,
-                    Err(e) => { return Error(e); }
-                };
+                if let Err(e) = self.parse_decimal(!leading_dot) {
+                    return Error(e);
+                }
             }
 
             if self.ch_is('e') || self.ch_is('E') {
-                res = match self.parse_exponent(res) {
-                    Ok(res) => res,
-                    Err(e) => { return Error(e); }
-                };
+                if let Err(e) = self.parse_exponent() {
+                    return Error(e);
+                }
             }
 
-            if neg {
-                res *= -1.0;
+            // Adding up the digits would round at every step, while the
+            // standard library rounds the text correctly once. Numbers too
+            // large for an `f64` are out of range like those too large for
+            // the integer types, rather than infinite, which would encode
+            // as `null`.
+            match self.number_text.parse::<f64>() {
+                Ok(res) if res.is_finite() => F64Value(res),
+                _ => Error(SyntaxError(InvalidNumber, self.line, self.col)),
+            }
+        } else {
+            match res {
+                Ok(res) => self.integer_event(neg, res),
+                Err(e) => Error(e),
             }
+        }
+    }
 
-            F64Value(res)
+    fn integer_event(&self, neg: bool, res: u64) -> JsonEvent {
+        if neg {
+            // Make sure we don't underflow.
+            if res > (i64::MAX as u64) + 1 {
+                Error(SyntaxError(InvalidNumber, self.line, self.col))
+            } else if res == 0 {
+                // the same as `0`, which encodes without the sign
+                U64Value(0)
+            } else {
+                I64Value((!res + 1) as i64)
+            }
         } else {
-            if neg {
-                // Make sure we don't underflow.
-                if res > (i64::MAX as u64) + 1 {
-                    Error(SyntaxError(InvalidNumber, self.line, self.col))
-                } else if res == 0 {
-                    I64Value(res as i64)
-                } else {
-                    I64Value((!res + 1) as i64)
+            U64Value(res)
+        }
+    }
+
+    // Moves past a sign, decimal point or `e` of the number being parsed.
+    fn bump_number(&mut self) {
+        if let Some(c) = self.ch {
+            self.number_text.push(c);
+        }
+        self.bump();
+    }
+
+    // Moves past a digit of the number being parsed, failing once the
+    // number has more significant digits than `max_number_len`.
+    fn bump_digit(&mut self) -> Result<(), ParserError> {
+        let zero = self.ch_is('0');
+        self.bump_number();
+        match (zero, self.number_zeros) {
+            (true, None) => {}
+            (true, Some(zeros)) => self.number_zeros = Some(zeros + 1),
+            (false, zeros) => {
+                self.number_digits += zeros.unwrap_or(0) + 1;
+                self.number_zeros = Some(0);
+                if self.number_digits > self.max_number_len {
+                    return self.error(NumberTooLong);
                 }
-            } else {
-                U64Value(res)
             }
         }
+        Ok(())
     }
 
-    fn parse_u64(&mut self) -> Result<u64, ParserError> {
-        let mut accum: u64 = 0;
+    // Reads the digits before the decimal point. Their value is an error if
+    // it is out of range, which only matters if no fraction or exponent
+    // follows to make the number a float.
+    fn parse_integer(&mut self) -> Result<Result<u64, ParserError>, ParserError> {
+        let mut accum: Result<u64, ParserError> = Ok(0);
 
         match self.ch_or_null() {
             '0' => {
-                self.bump();
+                try!(self.bump_digit());
 
                 // A leading '0' must be the only digit before the decimal point.
                 match self.ch_or_null() {
@@ -1548,18 +3939,15 @@ impl<T: Iterator<Item = char>> Parser<T> {
                 while !self.eof() {
                     match self.ch_or_null() {
                         c @ '0' ... '9' => {
-                            macro_rules! try_or_invalid {
-                                ($e: expr) => {
-                                    match $e {
-                                        Some(v) => v,
-                                        None => return self.error(InvalidNumber)
-                                    }
-                                }
+                            if let Ok(n) = accum {
+                                let digit = (c as u64) - ('0' as u64);
+                                accum = match n.checked_mul(10).and_then(|n| n.checked_add(digit)) {
+                                    Some(n) => Ok(n),
+                                    None => Err(SyntaxError(InvalidNumber, self.line, self.col)),
+                                };
                             }
-                            accum = try_or_invalid!(accum.checked_mul(10));
-                            accum = try_or_invalid!(accum.checked_add((c as u64) - ('0' as u64)));
 
-                            self.bump();
+                            try!(self.bump_digit());
                         }
                         _ => break,
                     }
@@ -1571,44 +3959,36 @@ impl<T: Iterator<Item = char>> Parser<T> {
         Ok(accum)
     }
 
-    fn parse_decimal(&mut self, mut res: f64) -> Result<f64, ParserError> {
-        self.bump();
+    // `had_digits` is whether there were digits before the decimal point.
+    fn parse_decimal(&mut self, had_digits: bool) -> Result<(), ParserError> {
+        self.bump_number();
 
-        // Make sure a digit follows the decimal place.
+        // Make sure a digit follows the decimal place, unless relaxed.
         match self.ch_or_null() {
             '0' ... '9' => (),
+            _ if self.relaxed && had_digits => {
+                self.number_text.pop();
+                return Ok(());
+            }
              _ => return self.error(InvalidNumber)
         }
 
-        let mut dec = 1.0;
-        let mut frac = 0.0;
         while !self.eof() {
             match self.ch_or_null() {
-                c @ '0' ... '9' => {
-                    dec /= 10.0;
-                    frac += (((c as isize) - ('0' as isize)) as f64) * dec;
-                    self.bump();
-                }
+                '0' ... '9' => try!(self.bump_digit()),
                 _ => break,
             }
         }
 
-        res += frac;
-
-        Ok(res)
+        Ok(())
     }
 
-    fn parse_exponent(&mut self, mut res: f64) -> Result<f64, ParserError> {
-        self.bump();
-
-        let mut exp = 0;
-        let mut neg_exp = false;
+    fn parse_exponent(&mut self) -> Result<(), ParserError> {
+        self.bump_number();
+        self.number_zeros = None;
 
-        if self.ch_is('+') {
-            self.bump();
-        } else if self.ch_is('-') {
-            self.bump();
-            neg_exp = true;
+        if self.ch_is('+') || self.ch_is('-') {
+            self.bump_number();
         }
 
         // Make sure a digit follows the exponent place.
@@ -1618,24 +3998,12 @@ impl<T: Iterator<Item = char>> Parser<T> {
         }
         while !self.eof() {
             match self.ch_or_null() {
-                c @ '0' ... '9' => {
-                    exp *= 10;
-                    exp += (c as usize) - ('0' as usize);
-
-                    self.bump();
-                }
+                '0' ... '9' => try!(self.bump_digit()),
                 _ => break
             }
         }
 
-        let exp = 10_f64.powi(exp as i32);
-        if neg_exp {
-            res /= exp;
-        } else {
-            res *= exp;
-        }
-
-        Ok(res)
+        Ok(())
     }
 
     fn decode_hex_escape(&mut self) -> Result<u16, ParserError> {
@@ -1658,7 +4026,11 @@ impl<T: Iterator<Item = char>> Parser<T> {
 
     fn parse_str(&mut self) -> Result<string::String, ParserError> {
         let mut escape = false;
+        // A leading surrogate from a hex escape, waiting for the trailing one
+        // which must come next.
+        let mut lead = None;
         let mut res = string::String::new();
+        self.in_str = true;
 
         loop {
             self.bump();
@@ -1667,6 +4039,9 @@ impl<T: Iterator<Item = char>> Parser<T> {
             }
 
             if escape {
+                if self.ch_or_null() != 'u' && lead.take().is_some() {
+                    try!(self.lone_surrogate(&mut res));
+                }
                 match self.ch_or_null() {
                     '"' => res.push('"'),
                     '\\' => res.push('\\'),
@@ -1676,32 +4051,27 @@ impl<T: Iterator<Item = char>> Parser<T> {
                     'n' => res.push('\n'),
                     'r' => res.push('\r'),
                     't' => res.push('\t'),
-                    'u' => match try!(self.decode_hex_escape()) {
-                        0xDC00 ... 0xDFFF => {
-                            return self.error(LoneLeadingSurrogateInHexEscape)
-                        }
-
-                        // Non-BMP characters are encoded as a sequence of
-                        // two hex escapes, representing UTF-16 surrogates.
-                        n1 @ 0xD800 ... 0xDBFF => {
-                            match (self.next_char(), self.next_char()) {
-                                (Some('\\'), Some('u')) => (),
-                                _ => return self.error(UnexpectedEndOfHexEscape),
-                            }
-
-                            let n2 = try!(self.decode_hex_escape());
-                            if n2 < 0xDC00 || n2 > 0xDFFF {
-                                return self.error(LoneLeadingSurrogateInHexEscape)
-                            }
+                    // Non-BMP characters are encoded as a sequence of two hex
+                    // escapes, representing UTF-16 surrogates.
+                    'u' => match (lead.take(), try!(self.decode_hex_escape())) {
+                        (Some(n1), n2 @ 0xDC00 ... 0xDFFF) => {
                             let c = (((n1 - 0xD800) as u32) << 10 |
                                      (n2 - 0xDC00) as u32) + 0x1_0000;
                             res.push(char::from_u32(c).unwrap());
                         }
-
-                        n => match char::from_u32(n as u32) {
-                            Some(c) => res.push(c),
-                            None => return self.error(InvalidUnicodeCodePoint),
-                        },
+                        (pending, n) => {
+                            if pending.is_some() {
+                                try!(self.lone_surrogate(&mut res));
+                            }
+                            match n {
+                                0xD800 ... 0xDBFF => lead = Some(n),
+                                0xDC00 ... 0xDFFF => try!(self.lone_surrogate(&mut res)),
+                                n => match char::from_u32(n as u32) {
+                                    Some(c) => res.push(c),
+                                    None => return self.error(InvalidUnicodeCodePoint),
+                                },
+                            }
+                        }
                     },
                     _ => return self.error(InvalidEscape),
                 }
@@ -1709,9 +4079,18 @@ impl<T: Iterator<Item = char>> Parser<T> {
             } else if self.ch_is('\\') {
                 escape = true;
             } else {
+                if lead.take().is_some() {
+                    if self.ch_is('"') && !self.replace_lone_surrogates {
+                        // The string itself has ended already.
+                        self.bump();
+                        self.in_str = false;
+                    }
+                    try!(self.lone_surrogate(&mut res));
+                }
                 match self.ch {
                     Some('"') => {
                         self.bump();
+                        self.in_str = false;
                         return Ok(res);
                     },
                     Some(c) if c <= '\u{1F}' =>
@@ -1723,6 +4102,17 @@ impl<T: Iterator<Item = char>> Parser<T> {
         }
     }
 
+    // Handles a surrogate which isn't part of a pair, either by replacing it
+    // or failing.
+    fn lone_surrogate(&self, res: &mut string::String) -> Result<(), ParserError> {
+        if self.replace_lone_surrogates {
+            res.push('\u{FFFD}');
+            Ok(())
+        } else {
+            self.error(LoneSurrogate)
+        }
+    }
+
     // Invoked at each iteration, consumes the stream until it has enough
     // information to return a JsonEvent.
     // Manages an internal state so that parsing can be interrupted and resumed.
@@ -1784,9 +4174,13 @@ impl<T: Iterator<Item = char>> Parser<T> {
 
     fn parse_array(&mut self, first: bool) -> JsonEvent {
         if self.ch_is(']') {
-            if !first {
+            if !first && !self.allow_trailing_comma {
                 self.error_event(InvalidSyntax)
             } else {
+                if !first {
+                    // drop the index of the element which didn't come
+                    self.stack.pop();
+                }
                 self.state = if self.stack.is_empty() {
                     ParseBeforeFinish
                 } else if self.stack.last_is_index() {
@@ -1838,7 +4232,9 @@ impl<T: Iterator<Item = char>> Parser<T> {
 
     fn parse_object(&mut self, first: bool) -> JsonEvent {
         if self.ch_is('}') {
-            if !first {
+            // With trailing commas allowed, the key of the last member was
+            // already dropped with the comma.
+            if !first && !self.allow_trailing_comma {
                 if self.stack.is_empty() {
                     return self.error_event(TrailingComma);
                 } else {
@@ -1913,11 +4309,21 @@ impl<T: Iterator<Item = char>> Parser<T> {
             'n' => { self.parse_ident("ull", NullValue) }
             't' => { self.parse_ident("rue", BooleanValue(true)) }
             'f' => { self.parse_ident("alse", BooleanValue(false)) }
+            'N' if self.case_insensitive_literals => self.parse_ident("ull", NullValue),
+            'T' if self.case_insensitive_literals => self.parse_ident("rue", BooleanValue(true)),
+            'F' if self.case_insensitive_literals => {
+                self.parse_ident("alse", BooleanValue(false))
+            }
             '0' ... '9' | '-' => self.parse_number(),
+            '+' | '.' if self.relaxed => self.parse_number(),
             '"' => match self.parse_str() {
                 Ok(s) => StringValue(s),
                 Err(e) => Error(e),
             },
+            // The stack holds an element for every array or object open.
+            '[' | '{' if self.max_depth.map_or(false, |max| self.stack.len() >= max) => {
+                self.error_event(NestingTooDeep)
+            }
             '[' => {
                 self.bump();
                 ArrayStart
@@ -1930,8 +4336,13 @@ impl<T: Iterator<Item = char>> Parser<T> {
         }
     }
 
+    // Parses the rest of a literal, spelled in lower case in `ident`.
     fn parse_ident(&mut self, ident: &str, value: JsonEvent) -> JsonEvent {
-        if ident.chars().all(|c| Some(c) == self.next_char()) {
+        let ignore_case = self.case_insensitive_literals;
+        if ident.chars().all(|c| match self.next_char() {
+            Some(n) => n == c || ignore_case && n >= 'A' && n <= 'Z' && n as u32 + 32 == c as u32,
+            None => false,
+        }) {
             self.bump();
             value
         } else {
@@ -1943,18 +4354,110 @@ impl<T: Iterator<Item = char>> Parser<T> {
         self.state = ParseFinished;
         Error(SyntaxError(reason, self.line, self.col))
     }
-}
 
-/// A Builder consumes a json::Parser to create a generic Json structure.
-pub struct Builder<T> {
-    parser: Parser<T>,
-    token: Option<JsonEvent>,
-}
+    // Skips the input following a syntax error up to the next ',', ']' or
+    // '}' of the innermost open array or object, and sets the parser up to
+    // carry on from there. Returns the event the recovery produced, if any.
+    fn recover_from_error(&mut self) -> Option<JsonEvent> {
+        if self.eof() {
+            // the error was about the end of the input already
+            self.state = ParseFinished;
+            return None;
+        }
+        if self.in_str {
+            self.in_str = false;
+            self.skip_str();
+        }
+        let mut depth = 0;
+        loop {
+            match self.ch {
+                None => break,
+                Some('"') => {
+                    self.bump();
+                    self.skip_str();
+                    continue;
+                }
+                Some('[') | Some('{') => depth += 1,
+                Some(']') | Some('}') if depth > 0 => depth -= 1,
+                Some(',') | Some(']') | Some('}')
+                    if depth == 0 && !self.containers.is_empty() => break,
+                _ => {}
+            }
+            self.bump();
+        }
+
+        let closing = match self.containers.last() {
+            Some(&c) if !self.eof() => c,
+            Some(&c) => {
+                // the input ended within an array or object
+                self.state = ParseFinished;
+                let reason = if c == ']' { EOFWhileParsingArray } else { EOFWhileParsingObject };
+                return Some(Error(SyntaxError(reason, self.line, self.col)));
+            }
+            _ => {
+                self.state = ParseFinished;
+                return None;
+            }
+        };
+        let depth = self.containers.len();
+        if self.ch_is(',') {
+            if closing == ']' {
+                self.stack.truncate(depth);
+                if self.stack.len() < depth {
+                    self.stack.push_index(0);
+                }
+                self.stack.bump_index();
+                self.state = ParseArray(false);
+            } else {
+                self.stack.truncate(depth - 1);
+                self.state = ParseObject(false);
+            }
+            self.bump();
+            None
+        } else {
+            // whichever bracket this is, it closes the innermost container
+            self.stack.truncate(depth - 1);
+            self.state = if self.stack.is_empty() {
+                ParseBeforeFinish
+            } else if self.stack.last_is_index() {
+                ParseArrayComma
+            } else {
+                ParseObjectComma
+            };
+            self.bump();
+            Some(if closing == ']' { ArrayEnd } else { ObjectEnd })
+        }
+    }
+
+    // Skips to just past the closing quote of the string being parsed.
+    fn skip_str(&mut self) {
+        let mut escape = false;
+        loop {
+            match self.ch {
+                None => return,
+                Some('"') if !escape => {
+                    self.bump();
+                    return;
+                }
+                Some('\\') if !escape => escape = true,
+                _ => escape = false,
+            }
+            self.bump();
+        }
+    }
+}
+
+/// A Builder consumes a json::Parser to create a generic Json structure.
+pub struct Builder<T> {
+    parser: Parser<T>,
+    token: Option<JsonEvent>,
+    strict_keys: bool,
+}
 
 impl<T: Iterator<Item = char>> Builder<T> {
     /// Create a JSON Builder.
     pub fn new(src: T) -> Builder<T> {
-        Builder { parser: Parser::new(src), token: None, }
+        Builder { parser: Parser::new(src), token: None, strict_keys: false }
     }
 
     // Decode a Json value from a Parser.
@@ -1970,10 +4473,121 @@ impl<T: Iterator<Item = char>> Builder<T> {
         result
     }
 
+    /// Sets the longest a number may be, as with `Parser::set_max_number_len`.
+    pub fn set_max_number_len(&mut self, max: usize) {
+        self.parser.set_max_number_len(max);
+    }
+
+    /// Sets how to handle lone surrogates, as with
+    /// `Parser::set_replace_lone_surrogates`.
+    pub fn set_replace_lone_surrogates(&mut self, replace: bool) {
+        self.parser.set_replace_lone_surrogates(replace);
+    }
+
+    /// Sets whether to accept relaxed number forms, as with
+    /// `Parser::set_relaxed`.
+    pub fn set_relaxed(&mut self, relaxed: bool) {
+        self.parser.set_relaxed(relaxed);
+    }
+
+    /// Sets how deeply arrays and objects may be nested, as with
+    /// `Parser::set_max_depth`.
+    pub fn set_max_depth(&mut self, max: Option<usize>) {
+        self.parser.set_max_depth(max);
+    }
+
+    /// Sets whether to accept trailing commas, as with
+    /// `Parser::set_allow_trailing_comma`.
+    pub fn set_allow_trailing_comma(&mut self, allow: bool) {
+        self.parser.set_allow_trailing_comma(allow);
+    }
+
+    /// Sets whether to skip comments, as with `Parser::set_allow_comments`.
+    pub fn set_allow_comments(&mut self, allow: bool) {
+        self.parser.set_allow_comments(allow);
+    }
+
+    /// Sets whether to accept literals in any case, as with
+    /// `Parser::set_case_insensitive_literals`.
+    pub fn set_case_insensitive_literals(&mut self, allow: bool) {
+        self.parser.set_case_insensitive_literals(allow);
+    }
+
+    /// Sets whether a key repeated within an object is reported as
+    /// `DuplicateKey`. By default the last of the values is kept, or all of
+    /// them with `build_pairs`.
+    pub fn set_strict_keys(&mut self, strict: bool) {
+        self.strict_keys = strict;
+    }
+
+    /// Decode a Json value from a Parser, carrying on after syntax errors
+    /// to report all of them rather than only the first.
+    ///
+    /// The value is only returned if there were no errors at all. See
+    /// `Parser::new_recovering` for how the parser resynchronizes, and the
+    /// limits of it.
+    pub fn build_recovering(&mut self) -> Result<Json, Vec<BuilderError>> {
+        self.parser.recover = true;
+        let mut errors = Vec::new();
+        self.bump_recovering(&mut errors);
+        let json = self.build_value_recovering(&mut errors);
+        self.bump_recovering(&mut errors);
+        if errors.is_empty() { Ok(json) } else { Err(errors) }
+    }
+
     fn bump(&mut self) {
         self.token = self.parser.next();
     }
 
+    fn bump_recovering(&mut self, errors: &mut Vec<BuilderError>) {
+        loop {
+            self.bump();
+            match self.token.take() {
+                Some(Error(e)) => errors.push(e),
+                token => {
+                    self.token = token;
+                    return;
+                }
+            }
+        }
+    }
+
+    fn build_value_recovering(&mut self, errors: &mut Vec<BuilderError>) -> Json {
+        match self.token.take() {
+            Some(ArrayStart) => {
+                let mut values = Vec::new();
+                loop {
+                    self.bump_recovering(errors);
+                    match self.token {
+                        Some(ArrayEnd) | None => return Json::Array(values),
+                        _ => values.push(self.build_value_recovering(errors)),
+                    }
+                }
+            }
+            Some(ObjectStart) => {
+                let mut values = BTreeMap::new();
+                loop {
+                    self.bump_recovering(errors);
+                    match self.token {
+                        Some(ObjectEnd) | None => return Json::Object(values),
+                        _ => {}
+                    }
+                    let key = match self.parser.stack().top() {
+                        Some(StackElement::Key(k)) => { k.to_string() }
+                        _ => { panic!("invalid state"); }
+                    };
+                    let value = self.build_value_recovering(errors);
+                    values.insert(key, value);
+                }
+            }
+            // the parser has reported the end of the input already
+            token => {
+                self.token = token;
+                self.build_value().unwrap_or(Json::Null)
+            }
+        }
+    }
+
     fn build_value(&mut self) -> Result<Json, BuilderError> {
         return match self.token.take() {
             Some(NullValue) => Ok(Json::Null),
@@ -2011,14 +4625,46 @@ impl<T: Iterator<Item = char>> Builder<T> {
         }
     }
 
+    /// Decode a JSON object from a Parser, keeping its members in the order
+    /// they appear in the document instead of collecting them into an
+    /// `Object`. Nested objects are still built as `Json::Object`.
+    pub fn build_pairs(&mut self) -> DecodeResult<Vec<(string::String, Json)>> {
+        self.bump();
+        let result = match self.token {
+            Some(ObjectStart) => self.build_members().map_err(ParseError),
+            _ => match self.build_value() {
+                Ok(json) => Err(ExpectedError("Object".to_string(),
+                                              json.to_string())),
+                Err(e) => Err(ParseError(e)),
+            },
+        };
+        self.bump();
+        match self.token.take() {
+            None => {}
+            Some(Error(e)) => { return Err(ParseError(e)); }
+            _ => {
+                return Err(ParseError(SyntaxError(InvalidSyntax,
+                                                  self.parser.line,
+                                                  self.parser.col)));
+            }
+        }
+        result
+    }
+
     fn build_object(&mut self) -> Result<Json, BuilderError> {
+        let members = try!(self.build_members());
+        Ok(Json::Object(members.into_iter().collect()))
+    }
+
+    fn build_members(&mut self) -> Result<Vec<(string::String, Json)>, BuilderError> {
         self.bump();
 
-        let mut values = BTreeMap::new();
+        let mut values = Vec::new();
+        let mut keys = BTreeSet::new();
 
         loop {
             match self.token.take() {
-                Some(ObjectEnd) => { return Ok(Json::Object(values)); }
+                Some(ObjectEnd) => { return Ok(values); }
                 Some(Error(e)) => { return Err(e); }
                 None => { break; }
                 token => { self.token = token; }
@@ -2027,8 +4673,13 @@ impl<T: Iterator<Item = char>> Builder<T> {
                 Some(StackElement::Key(k)) => { k.to_string() }
                 _ => { panic!("invalid state"); }
             };
+            if self.strict_keys && !keys.insert(key.clone()) {
+                // stop the parser, as its own errors do
+                self.parser.state = ParseFinished;
+                return self.parser.error(DuplicateKey);
+            }
             match self.build_value() {
-                Ok(value) => { values.insert(key, value); }
+                Ok(value) => { values.push((key, value)); }
                 Err(e) => { return Err(e); }
             }
             self.bump();
@@ -2037,15 +4688,198 @@ impl<T: Iterator<Item = char>> Builder<T> {
     }
 }
 
+/// An iterator over the elements of the JSON array making up a string,
+/// parsing each one only as it is asked for, so that the elements of a huge
+/// array don't all have to be in memory at once.
+///
+/// Nothing is parsed until the first call to `next`. The string has to hold
+/// the array and nothing else but whitespace, as for `Json::from_str`. A
+/// syntax error, including the string not holding an array, is reported in
+/// place of the next element, and ends the iteration.
+///
+/// ```
+/// use rustc_serialize::json::{Json, LazyJsonArray};
+///
+/// let mut total = 0;
+/// for element in LazyJsonArray::new("[1, 2, {\"n\": 3}]") {
+///     match element.unwrap() {
+///         Json::Object(obj) => total += obj["n"].as_u64().unwrap(),
+///         n => total += n.as_u64().unwrap(),
+///     }
+/// }
+/// assert_eq!(total, 6);
+/// ```
+pub struct LazyJsonArray<'a> {
+    builder: Builder<str::Chars<'a>>,
+    started: bool,
+    done: bool,
+}
+
+impl<'a> LazyJsonArray<'a> {
+    /// Creates an iterator over the elements of the array in `s`.
+    pub fn new(s: &'a str) -> LazyJsonArray<'a> {
+        LazyJsonArray { builder: Builder::new(s.chars()), started: false, done: false }
+    }
+
+    fn next_element(&mut self) -> Option<Result<Json, BuilderError>> {
+        if !self.started {
+            self.started = true;
+            self.builder.parser.parse_whitespace();
+            if !self.builder.parser.ch_is('[') {
+                let reason = if self.builder.parser.eof() { EOFWhileParsingValue } else { InvalidSyntax };
+                return Some(self.builder.parser.error(reason));
+            }
+            self.builder.bump();
+        }
+        self.builder.bump();
+        match self.builder.token {
+            Some(ArrayEnd) => {
+                // make sure nothing but whitespace follows
+                self.builder.bump();
+                match self.builder.token.take() {
+                    Some(Error(e)) => Some(Err(e)),
+                    _ => None,
+                }
+            }
+            _ => Some(self.builder.build_value()),
+        }
+    }
+}
+
+impl<'a> Iterator for LazyJsonArray<'a> {
+    type Item = Result<Json, BuilderError>;
+
+    fn next(&mut self) -> Option<Result<Json, BuilderError>> {
+        if self.done {
+            return None;
+        }
+        let next = self.next_element();
+        match next {
+            Some(Ok(_)) => {}
+            _ => self.done = true,
+        }
+        next
+    }
+}
+
+// The decoders which keep their values on the stack of a `Decoder`.
+trait OnStack {
+    fn decoder(&mut self) -> &mut Decoder;
+}
+
+impl OnStack for Decoder {
+    fn decoder(&mut self) -> &mut Decoder { self }
+}
+
+impl<F> OnStack for RenamingDecoder<F> {
+    fn decoder(&mut self) -> &mut Decoder { &mut self.inner }
+}
+
+// Reads an enum variant for `read_enum_variant`. Which variant to read is
+// known up front, but not how many arguments it has, so the array holding
+// an externally tagged variant is decoded as its one argument first, and as
+// its arguments when that fails or a second one is asked for.
+fn read_variant<D, T, F>(d: &mut D, names: &[&str], mut f: F) -> DecodeResult<T> where
+    D: OnStack,
+    F: FnMut(&mut D, usize) -> DecodeResult<T>,
+{
+    let (idx, value) = try!(d.decoder().tagged_variant(names));
+    let outer = d.decoder().variant_args.take();
+    let value = match value {
+        Some(value) => value,
+        None => {
+            let result = f(d, idx);
+            d.decoder().variant_args = outer;
+            return result;
+        }
+    };
+    let elements = match value {
+        Json::Array(ref elements) => Some(elements.clone()),
+        _ => None,
+    };
+    // what a failed attempt may have left changed
+    let depth = d.decoder().stack.len();
+    let keys = match elements {
+        Some(_) => d.decoder().flattened_keys.clone(),
+        None => None,
+    };
+    d.decoder().stack.push(value);
+    d.decoder().variant_args = Some((1, 0));
+    let result = f(d, idx);
+    let asked = d.decoder().variant_args.map_or(0, |(_, asked)| asked);
+    // struct variants leave their object behind
+    d.decoder().stack.truncate(depth);
+    let elements = match elements {
+        Some(_) if result.is_ok() && asked <= 1 => None,
+        elements => elements,
+    };
+    let result = match elements {
+        None => result,
+        Some(elements) => {
+            let len = elements.len();
+            d.decoder().flattened_keys = keys;
+            d.decoder().stack.extend(elements.into_iter().rev());
+            d.decoder().variant_args = Some((len, asked));
+            let retried = f(d, idx);
+            let asked = d.decoder().variant_args.map_or(0, |(_, asked)| asked);
+            let left = d.decoder().stack.len() - depth;
+            d.decoder().stack.truncate(depth);
+            match retried {
+                // a variant with one argument takes the array itself
+                _ if asked <= 1 => result,
+                Ok(_) if left != 0 => Err(ExpectedError(format!("{} arguments", len - left),
+                                                        len.to_string())),
+                retried => retried,
+            }
+        }
+    };
+    d.decoder().variant_args = outer;
+    result
+}
+
 /// A structure to decode JSON to values in rust.
+///
+/// The decoder works on a `Json` value which has already been parsed, so the
+/// same value can be inspected, or modified, before decoding it; see
+/// `from_json`.
+///
+/// An `Option` decodes as `None` from a JSON `null` and as `Some` from any
+/// other value. A struct field which is missing from its object decodes as
+/// if it were `null`, so an absent `Option` field is `None` too, while any
+/// other absent field is a `MissingFieldError`.
 pub struct Decoder {
     stack: Vec<Json>,
+    // Whether the next struct is to be decoded out of the object of the
+    // struct it is flattened into.
+    flatten_next: bool,
+    // Once a struct has flattened fields, the keys taken from its object.
+    flattened_keys: Option<Vec<string::String>>,
+    enum_tagging: EnumTagging,
+    // While an externally tagged variant is decoded, how many arguments its
+    // value holds and how many were asked for.
+    variant_args: Option<(usize, usize)>,
 }
 
 impl Decoder {
     /// Creates a new decoder instance for decoding the specified JSON value.
     pub fn new(json: Json) -> Decoder {
-        Decoder { stack: vec![json] }
+        Decoder {
+            stack: vec![json],
+            flatten_next: false,
+            flattened_keys: None,
+            enum_tagging: EnumTagging::VariantFields,
+            variant_args: None,
+        }
+    }
+
+    /// Set how the variants of enums are read, which should match how they
+    /// were written. Defaults to `EnumTagging::VariantFields`.
+    ///
+    /// With `EnumTagging::External`, an array is taken as the arguments of a
+    /// tuple variant with several of them, and as the argument itself
+    /// otherwise.
+    pub fn set_enum_tagging(&mut self, tagging: EnumTagging) {
+        self.enum_tagging = tagging;
     }
 }
 
@@ -2115,6 +4949,201 @@ macro_rules! read_primitive {
     }
 }
 
+// Stack manipulation shared by the `Decoder` implementations in this module.
+// Each helper prepares the stack for a compound value, after which the caller
+// runs the decoding closure against itself.
+impl Decoder {
+    // Pops an enum value, pushes its fields and returns the variant index.
+    fn enum_variant_index(&mut self, names: &[&str]) -> DecodeResult<usize> {
+        let name = match try!(self.pop()) {
+            Json::String(s) => s,
+            Json::Object(mut o) => {
+                let n = match o.remove(&"variant".to_string()) {
+                    Some(Json::String(s)) => s,
+                    Some(val) => {
+                        return Err(ExpectedError("String".to_string(), format!("{}", val)))
+                    }
+                    None => {
+                        return Err(MissingFieldError("variant".to_string()))
+                    }
+                };
+                match o.remove(&"fields".to_string()) {
+                    Some(Json::Array(l)) => {
+                        for field in l.into_iter().rev() {
+                            self.stack.push(field);
+                        }
+                    },
+                    Some(val) => {
+                        return Err(ExpectedError("Array".to_string(), format!("{}", val)))
+                    }
+                    None => {
+                        return Err(MissingFieldError("fields".to_string()))
+                    }
+                }
+                n
+            }
+            json => {
+                return Err(ExpectedError("String or Object".to_string(), format!("{}", json)))
+            }
+        };
+        match names.iter().position(|n| *n == name) {
+            Some(idx) => Ok(idx),
+            None => Err(UnknownVariantError(name))
+        }
+    }
+
+    // Pops an enum value tagged the way `enum_tagging` says and returns the
+    // variant index, along with the value holding the variant's arguments
+    // unless they were pushed already.
+    fn tagged_variant(&mut self, names: &[&str]) -> DecodeResult<(usize, Option<Json>)> {
+        if let EnumTagging::VariantFields = self.enum_tagging {
+            return self.enum_variant_index(names).map(|idx| (idx, None));
+        }
+        let json = try!(self.pop());
+        let (name, value) = match self.enum_tagging {
+            EnumTagging::Internal(ref tag) => {
+                let mut obj = match json {
+                    Json::Object(obj) => obj,
+                    json => return Err(ExpectedError("Object".to_string(), format!("{}", json))),
+                };
+                match obj.remove(tag) {
+                    Some(Json::String(s)) => (s, Some(Json::Object(obj))),
+                    Some(val) => {
+                        return Err(ExpectedError("String".to_string(), format!("{}", val)))
+                    }
+                    None => return Err(MissingFieldError(tag.clone())),
+                }
+            }
+            _ => match json {
+                Json::String(s) => (s, None),
+                Json::Object(obj) => {
+                    if obj.len() != 1 {
+                        let json = Json::Object(obj);
+                        return Err(ExpectedError("String or Object".to_string(),
+                                                 format!("{}", json)))
+                    }
+                    let (name, value) = obj.into_iter().next().unwrap();
+                    (name, Some(value))
+                }
+                json => {
+                    return Err(ExpectedError("String or Object".to_string(), format!("{}", json)))
+                }
+            },
+        };
+        match names.iter().position(|n| *n == name) {
+            Some(idx) => Ok((idx, value)),
+            None => Err(UnknownVariantError(name))
+        }
+    }
+
+    // Checks that the value of an externally tagged variant holds an
+    // argument at `idx`.
+    fn variant_arg(&mut self, idx: usize) -> DecodeResult<()> {
+        if let Some((len, ref mut asked)) = self.variant_args {
+            *asked = cmp::max(*asked, idx + 1);
+            if idx >= len {
+                return Err(ExpectedError(format!("at least {} arguments", idx + 1),
+                                         len.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    // Whether the fields of struct variants are read by name.
+    fn named_variant_fields(&self) -> bool {
+        match self.enum_tagging {
+            EnumTagging::VariantFields => false,
+            _ => true,
+        }
+    }
+
+    // Pops an object and pushes the value of its `name` field (or a Null if
+    // there is no such field). Returns the rest of the object, which must be
+    // pushed back once the field has been decoded, and whether the field was
+    // present.
+    fn take_struct_field(&mut self, name: &str) -> DecodeResult<(Object, bool)> {
+        let mut obj = try!(expect!(self.pop(), Object));
+        let present = match obj.remove(name) {
+            None => {
+                if let Some(ref keys) = self.flattened_keys {
+                    if keys.iter().any(|k| k == name) {
+                        return Err(ConflictingFieldError(name.to_string()));
+                    }
+                }
+                self.stack.push(Json::Null);
+                false
+            }
+            Some(json) => {
+                if let Some(ref mut keys) = self.flattened_keys {
+                    keys.push(name.to_string());
+                }
+                self.stack.push(json);
+                true
+            }
+        };
+        Ok((obj, present))
+    }
+
+    // Sets up the decoding of a struct, returning whether it is flattened
+    // into the enclosing struct along with the keys to restore once done.
+    fn begin_struct(&mut self) -> (bool, Option<Vec<string::String>>) {
+        if self.flatten_next {
+            self.flatten_next = false;
+            (true, None)
+        } else {
+            (false, self.flattened_keys.take())
+        }
+    }
+
+    fn end_struct(&mut self, flattened: bool, outer_keys: Option<Vec<string::String>>)
+                  -> DecodeResult<()> {
+        if !flattened {
+            self.flattened_keys = outer_keys;
+            try!(self.pop());
+        }
+        Ok(())
+    }
+
+    // Sets up the decoding of a flattened field, which takes its fields out
+    // of the object of the struct being decoded.
+    fn begin_flattened(&mut self) {
+        if self.flattened_keys.is_none() {
+            self.flattened_keys = Some(Vec::new());
+        }
+        self.flatten_next = true;
+    }
+
+    // Pops an array, pushes its elements and returns its length.
+    fn push_array(&mut self) -> DecodeResult<usize> {
+        let array = try!(expect!(self.pop(), Array));
+        let len = array.len();
+        for v in array.into_iter().rev() {
+            self.stack.push(v);
+        }
+        Ok(len)
+    }
+
+    // Pops an object, pushes its keys and values and returns its length.
+    fn push_object(&mut self) -> DecodeResult<usize> {
+        let obj = try!(expect!(self.pop(), Object));
+        let len = obj.len();
+        for (key, value) in obj.into_iter() {
+            self.stack.push(value);
+            self.stack.push(Json::String(key));
+        }
+        Ok(len)
+    }
+
+    // Returns whether the top of the stack holds a value, popping it if it
+    // is a Null.
+    fn take_option(&mut self) -> DecodeResult<bool> {
+        match try!(self.pop()) {
+            Json::Null => Ok(false),
+            value => { self.stack.push(value); Ok(true) }
+        }
+    }
+}
+
 impl ::Decoder for Decoder {
     type Error = DecoderError;
 
@@ -2133,6 +5162,32 @@ impl ::Decoder for Decoder {
     read_primitive! { read_i32, i32 }
     read_primitive! { read_i64, i64 }
 
+    #[cfg(feature = "i128")]
+    fn read_u128(&mut self) -> DecodeResult<u128> {
+        match try!(self.pop()) {
+            Json::I64(i) if i >= 0 => Ok(i as u128),
+            Json::U64(u) => Ok(u as u128),
+            Json::String(s) => match s.parse() {
+                Ok(u) => Ok(u),
+                Err(_) => Err(ExpectedError("Number".to_string(), s)),
+            },
+            value => Err(ExpectedError("Number".to_string(), value.to_string())),
+        }
+    }
+
+    #[cfg(feature = "i128")]
+    fn read_i128(&mut self) -> DecodeResult<i128> {
+        match try!(self.pop()) {
+            Json::I64(i) => Ok(i as i128),
+            Json::U64(u) => Ok(u as i128),
+            Json::String(s) => match s.parse() {
+                Ok(i) => Ok(i),
+                Err(_) => Err(ExpectedError("Number".to_string(), s)),
+            },
+            value => Err(ExpectedError("Number".to_string(), value.to_string())),
+        }
+    }
+
     fn read_f32(&mut self) -> DecodeResult<f32> {
         self.read_f64().map(|x| x as f32)
     }
@@ -2176,6 +5231,20 @@ impl ::Decoder for Decoder {
         expect!(self.pop(), String)
     }
 
+    fn read_number_str(&mut self) -> DecodeResult<string::String> {
+        match try!(self.pop()) {
+            Json::I64(i) => Ok(i.to_string()),
+            Json::U64(u) => Ok(u.to_string()),
+            // The text of a decimal is lost once it is read into an `f64`,
+            // and writing the `f64` back out would hand out a rounded number
+            // as if it were the one in the document.
+            Json::F64(f) => Err(ExpectedError("Number as written".to_string(), f.to_string())),
+            // numbers written as strings, as with the other numbers
+            Json::String(ref s) if is_number(s) => Ok(s.clone()),
+            value => Err(ExpectedError("Number".to_string(), value.to_string())),
+        }
+    }
+
     fn read_enum<T, F>(&mut self, _name: &str, f: F) -> DecodeResult<T> where
         F: FnOnce(&mut Decoder) -> DecodeResult<T>,
     {
@@ -2183,50 +5252,16 @@ impl ::Decoder for Decoder {
     }
 
     fn read_enum_variant<T, F>(&mut self, names: &[&str],
-                               mut f: F) -> DecodeResult<T>
+                               f: F) -> DecodeResult<T>
         where F: FnMut(&mut Decoder, usize) -> DecodeResult<T>,
     {
-        let name = match try!(self.pop()) {
-            Json::String(s) => s,
-            Json::Object(mut o) => {
-                let n = match o.remove(&"variant".to_string()) {
-                    Some(Json::String(s)) => s,
-                    Some(val) => {
-                        return Err(ExpectedError("String".to_string(), format!("{}", val)))
-                    }
-                    None => {
-                        return Err(MissingFieldError("variant".to_string()))
-                    }
-                };
-                match o.remove(&"fields".to_string()) {
-                    Some(Json::Array(l)) => {
-                        for field in l.into_iter().rev() {
-                            self.stack.push(field);
-                        }
-                    },
-                    Some(val) => {
-                        return Err(ExpectedError("Array".to_string(), format!("{}", val)))
-                    }
-                    None => {
-                        return Err(MissingFieldError("fields".to_string()))
-                    }
-                }
-                n
-            }
-            json => {
-                return Err(ExpectedError("String or Object".to_string(), format!("{}", json)))
-            }
-        };
-        let idx = match names.iter().position(|n| *n == name) {
-            Some(idx) => idx,
-            None => return Err(UnknownVariantError(name))
-        };
-        f(self, idx)
+        read_variant(self, names, f)
     }
 
-    fn read_enum_variant_arg<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
+    fn read_enum_variant_arg<T, F>(&mut self, idx: usize, f: F) -> DecodeResult<T> where
         F: FnOnce(&mut Decoder) -> DecodeResult<T>,
     {
+        try!(self.variant_arg(idx));
         f(self)
     }
 
@@ -2238,20 +5273,24 @@ impl ::Decoder for Decoder {
 
 
     fn read_enum_struct_variant_field<T, F>(&mut self,
-                                         _name: &str,
+                                         name: &str,
                                          idx: usize,
                                          f: F)
                                          -> DecodeResult<T> where
         F: FnOnce(&mut Decoder) -> DecodeResult<T>,
     {
+        if self.named_variant_fields() {
+            return self.read_struct_field(name, idx, f);
+        }
         self.read_enum_variant_arg(idx, f)
     }
 
     fn read_struct<T, F>(&mut self, _name: &str, _len: usize, f: F) -> DecodeResult<T> where
         F: FnOnce(&mut Decoder) -> DecodeResult<T>,
     {
+        let (flattened, outer_keys) = self.begin_struct();
         let value = try!(f(self));
-        try!(self.pop());
+        try!(self.end_struct(flattened, outer_keys));
         Ok(value)
     }
 
@@ -2262,27 +5301,30 @@ impl ::Decoder for Decoder {
                                -> DecodeResult<T> where
         F: FnOnce(&mut Decoder) -> DecodeResult<T>,
     {
-        let mut obj = try!(expect!(self.pop(), Object));
-
-        let value = match obj.remove(&name.to_string()) {
-            None => {
-                // Add a Null and try to parse it as an Option<_>
-                // to get None as a default value.
-                self.stack.push(Json::Null);
-                match f(self) {
-                    Ok(x) => x,
-                    Err(_) => return Err(MissingFieldError(name.to_string())),
-                }
-            },
-            Some(json) => {
-                self.stack.push(json);
-                try!(f(self))
+        let (obj, present) = try!(self.take_struct_field(name));
+        let value = if present {
+            try!(f(self))
+        } else {
+            // A Null was pushed in place of the missing field, try to parse
+            // it as an Option<_> to get None as a default value.
+            match f(self) {
+                Ok(x) => x,
+                Err(_) => return Err(MissingFieldError(name.to_string())),
             }
         };
         self.stack.push(Json::Object(obj));
         Ok(value)
     }
 
+    fn read_struct_flattened<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        self.begin_flattened();
+        let value = f(self);
+        self.flatten_next = false;
+        value
+    }
+
     fn read_tuple<T, F>(&mut self, tuple_len: usize, f: F) -> DecodeResult<T> where
         F: FnOnce(&mut Decoder) -> DecodeResult<T>,
     {
@@ -2320,58 +5362,1131 @@ impl ::Decoder for Decoder {
         self.read_tuple_arg(idx, f)
     }
 
-    fn read_option<T, F>(&mut self, mut f: F) -> DecodeResult<T> where
-        F: FnMut(&mut Decoder, bool) -> DecodeResult<T>,
-    {
-        match try!(self.pop()) {
-            Json::Null => f(self, false),
-            value => { self.stack.push(value); f(self, true) }
-        }
+    fn read_option<T, F>(&mut self, mut f: F) -> DecodeResult<T> where
+        F: FnMut(&mut Decoder, bool) -> DecodeResult<T>,
+    {
+        // Only `null` is `None`; an absent struct field also ends up here as
+        // the `Null` pushed by `read_struct_field`.
+        let present = try!(self.take_option());
+        f(self, present)
+    }
+
+    fn read_seq<T, F>(&mut self, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder, usize) -> DecodeResult<T>,
+    {
+        let len = try!(self.push_array());
+        f(self, len)
+    }
+
+    fn read_seq_elt<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_map<T, F>(&mut self, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut Decoder, usize) -> DecodeResult<T>,
+    {
+        let len = try!(self.push_object());
+        f(self, len)
+    }
+
+    fn read_map_elt_key<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
+       F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_map_elt_val<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
+       F: FnOnce(&mut Decoder) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn next_kind(&mut self) -> DecodeResult<ValueKind> {
+        match self.stack.last() {
+            Some(json) => Ok(json.value_kind()),
+            None => Err(EOF),
+        }
+    }
+
+    fn read_alternatives<T, F>(&mut self, len: usize, f: F) -> DecodeResult<T> where
+        F: FnMut(&mut Decoder, usize) -> DecodeResult<T>,
+    {
+        let value = try!(self.pop());
+        decode_alternatives(self, value, len, f)
+    }
+
+    fn error(&mut self, err: &str) -> DecoderError {
+        ApplicationError(err.to_string())
+    }
+}
+
+// The decoders of this module, which keep the values they can't decode in
+// document order on a stack.
+trait ValueStack {
+    fn value_stack(&mut self) -> &mut Vec<Json>;
+}
+
+impl ValueStack for Decoder {
+    fn value_stack(&mut self) -> &mut Vec<Json> {
+        &mut self.stack
+    }
+}
+
+impl<F> ValueStack for RenamingDecoder<F> {
+    fn value_stack(&mut self) -> &mut Vec<Json> {
+        &mut self.inner.stack
+    }
+}
+
+impl<'a> ValueStack for StreamDecoder<'a> {
+    fn value_stack(&mut self) -> &mut Vec<Json> {
+        &mut self.tree.stack
+    }
+}
+
+// Decodes `value` in the first of `len` ways that works, for
+// `read_alternatives`, putting it back on the stack of `d` for each attempt.
+fn decode_alternatives<D, T, F>(d: &mut D, value: Json, len: usize, mut f: F)
+                                -> DecodeResult<T>
+    where D: ValueStack, F: FnMut(&mut D, usize) -> DecodeResult<T>
+{
+    let depth = d.value_stack().len();
+    let mut value = Some(value);
+    let mut result = Err(ApplicationError("no way to decode the value".to_string()));
+    for i in 0..len {
+        // the last attempt can have the value itself
+        let attempt = if i + 1 == len { value.take() } else { value.clone() };
+        d.value_stack().push(attempt.unwrap());
+        result = f(d, i);
+        // what a failed attempt left unread
+        d.value_stack().truncate(depth);
+        if result.is_ok() {
+            break;
+        }
+    }
+    result
+}
+
+/// A decoder adapter which transforms the name of every struct field before
+/// looking it up in the JSON object being decoded.
+///
+/// This allows decoding JSON whose keys don't match the names of the fields
+/// of the target struct, for example `camelCase` keys into `snake_case`
+/// fields.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// use rustc_serialize::Decodable;
+/// use rustc_serialize::json::{self, Json, RenamingDecoder};
+///
+/// #[derive(RustcDecodable)]
+/// struct User {
+///     first_name: String,
+///     user_id: u32,
+/// }
+///
+/// fn main() {
+///     let json = Json::from_str("{\"firstName\": \"Ann\", \"userID\": 7}").unwrap();
+///     let mut decoder = RenamingDecoder::new(json::Decoder::new(json), |name: &str| {
+///         match name {
+///             "user_id" => "userID".to_string(),
+///             _ => {
+///                 let mut parts = name.split('_');
+///                 let mut key = parts.next().unwrap_or("").to_string();
+///                 for part in parts {
+///                     let mut chars = part.chars();
+///                     if let Some(c) = chars.next() {
+///                         key.extend(c.to_uppercase());
+///                         key.push_str(chars.as_str());
+///                     }
+///                 }
+///                 key
+///             }
+///         }
+///     });
+///     let user: User = Decodable::decode(&mut decoder).unwrap();
+///     assert_eq!(user.first_name, "Ann");
+///     assert_eq!(user.user_id, 7);
+/// }
+/// ```
+pub struct RenamingDecoder<F> {
+    inner: Decoder,
+    rename: F,
+}
+
+impl<F: Fn(&str) -> string::String> RenamingDecoder<F> {
+    /// Creates a new adapter around `decoder`, which looks up each struct
+    /// field under the key returned by `rename` for the field's name.
+    pub fn new(decoder: Decoder, rename: F) -> RenamingDecoder<F> {
+        RenamingDecoder { inner: decoder, rename: rename }
+    }
+
+    /// Unwraps this adapter, returning the underlying decoder.
+    pub fn into_inner(self) -> Decoder {
+        self.inner
+    }
+}
+
+impl<F: Fn(&str) -> string::String> ::Decoder for RenamingDecoder<F> {
+    type Error = DecoderError;
+
+    fn read_nil(&mut self) -> DecodeResult<()> { self.inner.read_nil() }
+    fn read_usize(&mut self) -> DecodeResult<usize> { self.inner.read_usize() }
+    #[cfg(feature = "i128")]
+    fn read_u128(&mut self) -> DecodeResult<u128> { self.inner.read_u128() }
+    fn read_u64(&mut self) -> DecodeResult<u64> { self.inner.read_u64() }
+    fn read_u32(&mut self) -> DecodeResult<u32> { self.inner.read_u32() }
+    fn read_u16(&mut self) -> DecodeResult<u16> { self.inner.read_u16() }
+    fn read_u8(&mut self) -> DecodeResult<u8> { self.inner.read_u8() }
+    fn read_isize(&mut self) -> DecodeResult<isize> { self.inner.read_isize() }
+    #[cfg(feature = "i128")]
+    fn read_i128(&mut self) -> DecodeResult<i128> { self.inner.read_i128() }
+    fn read_i64(&mut self) -> DecodeResult<i64> { self.inner.read_i64() }
+    fn read_i32(&mut self) -> DecodeResult<i32> { self.inner.read_i32() }
+    fn read_i16(&mut self) -> DecodeResult<i16> { self.inner.read_i16() }
+    fn read_i8(&mut self) -> DecodeResult<i8> { self.inner.read_i8() }
+    fn read_bool(&mut self) -> DecodeResult<bool> { self.inner.read_bool() }
+    fn read_f64(&mut self) -> DecodeResult<f64> { self.inner.read_f64() }
+    fn read_f32(&mut self) -> DecodeResult<f32> { self.inner.read_f32() }
+    fn read_char(&mut self) -> DecodeResult<char> { self.inner.read_char() }
+    fn read_str(&mut self) -> DecodeResult<string::String> { self.inner.read_str() }
+    fn read_number_str(&mut self) -> DecodeResult<string::String> {
+        self.inner.read_number_str()
+    }
+
+    fn read_enum<T, G>(&mut self, _name: &str, f: G) -> DecodeResult<T> where
+        G: FnOnce(&mut RenamingDecoder<F>) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_enum_variant<T, G>(&mut self, names: &[&str], f: G) -> DecodeResult<T> where
+        G: FnMut(&mut RenamingDecoder<F>, usize) -> DecodeResult<T>,
+    {
+        read_variant(self, names, f)
+    }
+
+    fn read_enum_variant_arg<T, G>(&mut self, idx: usize, f: G) -> DecodeResult<T> where
+        G: FnOnce(&mut RenamingDecoder<F>) -> DecodeResult<T>,
+    {
+        try!(self.inner.variant_arg(idx));
+        f(self)
+    }
+
+    fn read_enum_struct_variant<T, G>(&mut self, names: &[&str], f: G) -> DecodeResult<T> where
+        G: FnMut(&mut RenamingDecoder<F>, usize) -> DecodeResult<T>,
+    {
+        self.read_enum_variant(names, f)
+    }
+
+    fn read_enum_struct_variant_field<T, G>(&mut self,
+                                            name: &str,
+                                            idx: usize,
+                                            f: G)
+                                            -> DecodeResult<T> where
+        G: FnOnce(&mut RenamingDecoder<F>) -> DecodeResult<T>,
+    {
+        if self.inner.named_variant_fields() {
+            return self.read_struct_field(name, idx, f);
+        }
+        self.read_enum_variant_arg(idx, f)
+    }
+
+    fn read_struct<T, G>(&mut self, _name: &str, _len: usize, f: G) -> DecodeResult<T> where
+        G: FnOnce(&mut RenamingDecoder<F>) -> DecodeResult<T>,
+    {
+        let (flattened, outer_keys) = self.inner.begin_struct();
+        let value = try!(f(self));
+        try!(self.inner.end_struct(flattened, outer_keys));
+        Ok(value)
+    }
+
+    fn read_struct_field<T, G>(&mut self,
+                               name: &str,
+                               _idx: usize,
+                               f: G)
+                               -> DecodeResult<T> where
+        G: FnOnce(&mut RenamingDecoder<F>) -> DecodeResult<T>,
+    {
+        let key = (self.rename)(name);
+        let (obj, present) = try!(self.inner.take_struct_field(&key));
+        let value = if present {
+            try!(f(self))
+        } else {
+            match f(self) {
+                Ok(x) => x,
+                Err(_) => return Err(MissingFieldError(key)),
+            }
+        };
+        self.inner.stack.push(Json::Object(obj));
+        Ok(value)
+    }
+
+    fn read_struct_flattened<T, G>(&mut self, _idx: usize, f: G) -> DecodeResult<T> where
+        G: FnOnce(&mut RenamingDecoder<F>) -> DecodeResult<T>,
+    {
+        self.inner.begin_flattened();
+        let value = f(self);
+        self.inner.flatten_next = false;
+        value
+    }
+
+    fn read_tuple<T, G>(&mut self, tuple_len: usize, f: G) -> DecodeResult<T> where
+        G: FnOnce(&mut RenamingDecoder<F>) -> DecodeResult<T>,
+    {
+        self.read_seq(move |d, len| {
+            if len == tuple_len {
+                f(d)
+            } else {
+                Err(ExpectedError(format!("Tuple{}", tuple_len), format!("Tuple{}", len)))
+            }
+        })
+    }
+
+    fn read_tuple_arg<T, G>(&mut self, idx: usize, f: G) -> DecodeResult<T> where
+        G: FnOnce(&mut RenamingDecoder<F>) -> DecodeResult<T>,
+    {
+        self.read_seq_elt(idx, f)
+    }
+
+    fn read_tuple_struct<T, G>(&mut self,
+                               _name: &str,
+                               len: usize,
+                               f: G)
+                               -> DecodeResult<T> where
+        G: FnOnce(&mut RenamingDecoder<F>) -> DecodeResult<T>,
+    {
+        self.read_tuple(len, f)
+    }
+
+    fn read_tuple_struct_arg<T, G>(&mut self,
+                                   idx: usize,
+                                   f: G)
+                                   -> DecodeResult<T> where
+        G: FnOnce(&mut RenamingDecoder<F>) -> DecodeResult<T>,
+    {
+        self.read_tuple_arg(idx, f)
+    }
+
+    fn read_option<T, G>(&mut self, mut f: G) -> DecodeResult<T> where
+        G: FnMut(&mut RenamingDecoder<F>, bool) -> DecodeResult<T>,
+    {
+        let present = try!(self.inner.take_option());
+        f(self, present)
+    }
+
+    fn read_seq<T, G>(&mut self, f: G) -> DecodeResult<T> where
+        G: FnOnce(&mut RenamingDecoder<F>, usize) -> DecodeResult<T>,
+    {
+        let len = try!(self.inner.push_array());
+        f(self, len)
+    }
+
+    fn read_seq_elt<T, G>(&mut self, _idx: usize, f: G) -> DecodeResult<T> where
+        G: FnOnce(&mut RenamingDecoder<F>) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_map<T, G>(&mut self, f: G) -> DecodeResult<T> where
+        G: FnOnce(&mut RenamingDecoder<F>, usize) -> DecodeResult<T>,
+    {
+        let len = try!(self.inner.push_object());
+        f(self, len)
+    }
+
+    fn read_map_elt_key<T, G>(&mut self, _idx: usize, f: G) -> DecodeResult<T> where
+       G: FnOnce(&mut RenamingDecoder<F>) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_map_elt_val<T, G>(&mut self, _idx: usize, f: G) -> DecodeResult<T> where
+       G: FnOnce(&mut RenamingDecoder<F>) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn next_kind(&mut self) -> DecodeResult<ValueKind> {
+        self.inner.next_kind()
+    }
+
+    fn read_alternatives<T, G>(&mut self, len: usize, f: G) -> DecodeResult<T> where
+        G: FnMut(&mut RenamingDecoder<F>, usize) -> DecodeResult<T>,
+    {
+        let value = try!(self.inner.pop());
+        decode_alternatives(self, value, len, f)
+    }
+
+    fn error(&mut self, err: &str) -> DecoderError {
+        ApplicationError(err.to_string())
+    }
+}
+
+// The characters of a string, keeping hold of the rest of it.
+struct StrChars<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for StrChars<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = match self.rest.chars().next() {
+            Some(c) => c,
+            None => return None,
+        };
+        self.rest = &self.rest[c.len_utf8()..];
+        Some(c)
+    }
+}
+
+// The object of a struct being decoded straight from the parser.
+struct StreamObject {
+    // Members read past on the way to a field the struct asked for.
+    skipped: Object,
+    // Whether the parser is past the end of the object.
+    ended: bool,
+    // Once the struct has flattened fields, the keys taken from the object.
+    taken: Option<Vec<string::String>>,
+}
+
+// The decoder behind `decode_str`, which reads values off a `Builder`.
+//
+// Values which are built rather than decoded as they are read go onto the
+// stack of `tree`, which decodes them the way `Decoder` does. Whenever that
+// stack isn't empty, it holds the value to decode next.
+struct StreamDecoder<'a> {
+    // The builder's token is the next event not read yet.
+    builder: Builder<StrChars<'a>>,
+    tree: Decoder,
+    objects: Vec<StreamObject>,
+    flatten_next: bool,
+}
+
+impl<'a> StreamDecoder<'a> {
+    fn new(s: &'a str) -> StreamDecoder<'a> {
+        let mut builder = Builder::new(StrChars { rest: s });
+        builder.bump();
+        StreamDecoder {
+            builder: builder,
+            tree: Decoder {
+                stack: Vec::new(),
+                flatten_next: false,
+                flattened_keys: None,
+                enum_tagging: EnumTagging::VariantFields,
+                variant_args: None,
+            },
+            objects: Vec::new(),
+            flatten_next: false,
+        }
+    }
+
+    fn streaming(&self) -> bool {
+        self.tree.stack.is_empty()
+    }
+
+    // Builds the next value from the parser.
+    fn build(&mut self) -> DecodeResult<Json> {
+        let value = try!(self.builder.build_value().map_err(ParseError));
+        self.builder.bump();
+        Ok(value)
+    }
+
+    // Makes sure the value to decode next is on the stack of `tree`.
+    fn fill(&mut self) -> DecodeResult<()> {
+        if self.streaming() {
+            let value = try!(self.build());
+            self.tree.stack.push(value);
+        }
+        Ok(())
+    }
+
+    // Moves past the next event if it is `event`, returning whether it was.
+    fn eat(&mut self, event: &JsonEvent) -> bool {
+        if self.builder.token.as_ref() == Some(event) {
+            self.builder.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    // Skips what is left of an array or object, up to and including `end`.
+    fn skip_to(&mut self, end: &JsonEvent) -> DecodeResult<()> {
+        while !self.eat(end) {
+            try!(self.build());
+        }
+        Ok(())
+    }
+
+    // Counts the elements of the array or object the parser has just
+    // started, by scanning ahead in the text without parsing it. Anything
+    // malformed is left for the parser to report.
+    fn count_elements(&self) -> usize {
+        let parser = &self.builder.parser;
+        let (mut depth, mut commas, mut empty) = (0, 0, true);
+        let (mut in_string, mut escaped) = (false, false);
+        for c in parser.ch.into_iter().chain(parser.rdr.rest.chars()) {
+            if in_string {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == '"' {
+                    in_string = false;
+                }
+                continue
+            }
+            match c {
+                ' ' | '\t' | '\n' | '\r' => continue,
+                ']' | '}' if depth == 0 => break,
+                ']' | '}' => depth -= 1,
+                '[' | '{' => depth += 1,
+                ',' if depth == 0 => commas += 1,
+                '"' => in_string = true,
+                _ => {}
+            }
+            empty = false;
+        }
+        if empty { 0 } else { commas + 1 }
+    }
+
+    // Returns the key of the object member the parser is at.
+    fn member_key(&mut self) -> DecodeResult<string::String> {
+        let key = match (&self.builder.token, self.builder.parser.stack().top()) {
+            (&Some(Error(_)), _) | (&Some(ObjectEnd), _) | (&None, _) => None,
+            (_, Some(StackElement::Key(key))) => Some(key.to_string()),
+            _ => None,
+        };
+        match key {
+            Some(key) => Ok(key),
+            // Not a member, have the parser say what is wrong.
+            None => {
+                let value = try!(self.build());
+                Err(ExpectedError("Object member".to_string(), value.to_string()))
+            }
+        }
+    }
+
+    // Looks for the field `name` of the struct being read, which is pushed
+    // onto `tree` if it was read past already, or else left for the parser.
+    // Returns whether the field is there.
+    fn find_field(&mut self, name: &str) -> DecodeResult<bool> {
+        let idx = self.objects.len() - 1;
+        if let Some(value) = self.objects[idx].skipped.remove(name) {
+            self.take_field(idx, name);
+            self.tree.stack.push(value);
+            return Ok(true);
+        }
+        while !self.objects[idx].ended {
+            if self.eat(&ObjectEnd) {
+                self.objects[idx].ended = true;
+                break
+            }
+            let key = try!(self.member_key());
+            if key == name {
+                self.take_field(idx, name);
+                return Ok(true);
+            }
+            let value = try!(self.build());
+            self.objects[idx].skipped.insert(key, value);
+        }
+        if let Some(ref taken) = self.objects[idx].taken {
+            if taken.iter().any(|k| k == name) {
+                return Err(ConflictingFieldError(name.to_string()));
+            }
+        }
+        Ok(false)
+    }
+
+    fn take_field(&mut self, idx: usize, name: &str) {
+        let obj = &mut self.objects[idx];
+        if let Some(ref mut taken) = obj.taken {
+            taken.push(name.to_string());
+        }
+    }
+
+    // Decodes a struct held by `tree`.
+    fn read_tree_struct<T, F>(&mut self, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut StreamDecoder<'a>) -> DecodeResult<T>,
+    {
+        let (flattened, outer_keys) = self.tree.begin_struct();
+        let value = try!(f(self));
+        try!(self.tree.end_struct(flattened, outer_keys));
+        Ok(value)
+    }
+
+    // Checks that nothing but whitespace follows the value decoded.
+    fn finish(&mut self) -> DecodeResult<()> {
+        match self.builder.token.take() {
+            None => Ok(()),
+            Some(Error(e)) => Err(ParseError(e)),
+            Some(_) => {
+                let parser = &self.builder.parser;
+                Err(ParseError(SyntaxError(InvalidSyntax, parser.line, parser.col)))
+            }
+        }
+    }
+}
+
+macro_rules! read_filled {
+    ($($name:ident -> $ty:ty),*) => {$(
+        fn $name(&mut self) -> DecodeResult<$ty> {
+            try!(self.fill());
+            ::Decoder::$name(&mut self.tree)
+        }
+    )*}
+}
+
+impl<'a> ::Decoder for StreamDecoder<'a> {
+    type Error = DecoderError;
+
+    read_filled! {
+        read_nil -> (),
+        read_usize -> usize,
+        read_u64 -> u64,
+        read_u32 -> u32,
+        read_u16 -> u16,
+        read_u8 -> u8,
+        read_isize -> isize,
+        read_i64 -> i64,
+        read_i32 -> i32,
+        read_i16 -> i16,
+        read_i8 -> i8,
+        read_bool -> bool,
+        read_f64 -> f64,
+        read_f32 -> f32,
+        read_char -> char,
+        read_str -> string::String
+    }
+
+    #[cfg(feature = "i128")]
+    fn read_u128(&mut self) -> DecodeResult<u128> {
+        try!(self.fill());
+        ::Decoder::read_u128(&mut self.tree)
+    }
+
+    fn read_number_str(&mut self) -> DecodeResult<string::String> {
+        if self.streaming() {
+            let text = match self.builder.token {
+                Some(I64Value(_)) | Some(U64Value(_)) | Some(F64Value(_)) => {
+                    Some(self.builder.parser.number_text.clone())
+                }
+                _ => None,
+            };
+            if let Some(text) = text {
+                self.builder.bump();
+                return Ok(text);
+            }
+        }
+        try!(self.fill());
+        ::Decoder::read_number_str(&mut self.tree)
+    }
+
+    #[cfg(feature = "i128")]
+    fn read_i128(&mut self) -> DecodeResult<i128> {
+        try!(self.fill());
+        ::Decoder::read_i128(&mut self.tree)
+    }
+
+    fn read_enum<T, F>(&mut self, _name: &str, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut StreamDecoder<'a>) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_enum_variant<T, F>(&mut self, names: &[&str], mut f: F) -> DecodeResult<T> where
+        F: FnMut(&mut StreamDecoder<'a>, usize) -> DecodeResult<T>,
+    {
+        // The variant name may come after its fields, so the whole value
+        // is built.
+        try!(self.fill());
+        let idx = try!(self.tree.enum_variant_index(names));
+        f(self, idx)
+    }
+
+    fn read_enum_variant_arg<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut StreamDecoder<'a>) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_enum_struct_variant<T, F>(&mut self, names: &[&str], f: F) -> DecodeResult<T> where
+        F: FnMut(&mut StreamDecoder<'a>, usize) -> DecodeResult<T>,
+    {
+        self.read_enum_variant(names, f)
+    }
+
+    fn read_enum_struct_variant_field<T, F>(&mut self,
+                                            _name: &str,
+                                            idx: usize,
+                                            f: F)
+                                            -> DecodeResult<T> where
+        F: FnOnce(&mut StreamDecoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_enum_variant_arg(idx, f)
+    }
+
+    fn read_struct<T, F>(&mut self, _name: &str, _len: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut StreamDecoder<'a>) -> DecodeResult<T>,
+    {
+        if !self.streaming() {
+            return self.read_tree_struct(f);
+        }
+        if self.flatten_next {
+            self.flatten_next = false;
+            return f(self);
+        }
+        if !self.eat(&ObjectStart) {
+            // Not an object, leave it to `tree` to complain.
+            try!(self.fill());
+            return self.read_tree_struct(f);
+        }
+        self.objects.push(StreamObject { skipped: BTreeMap::new(), ended: false, taken: None });
+        let value = f(self);
+        let object = self.objects.pop().unwrap();
+        let value = try!(value);
+        if !object.ended {
+            try!(self.skip_to(&ObjectEnd));
+        }
+        Ok(value)
+    }
+
+    fn read_struct_field<T, F>(&mut self,
+                               name: &str,
+                               _idx: usize,
+                               f: F)
+                               -> DecodeResult<T> where
+        F: FnOnce(&mut StreamDecoder<'a>) -> DecodeResult<T>,
+    {
+        if !self.streaming() || self.objects.is_empty() {
+            let (obj, present) = try!(self.tree.take_struct_field(name));
+            let value = if present {
+                try!(f(self))
+            } else {
+                match f(self) {
+                    Ok(x) => x,
+                    Err(_) => return Err(MissingFieldError(name.to_string())),
+                }
+            };
+            self.tree.stack.push(Json::Object(obj));
+            return Ok(value);
+        }
+        if try!(self.find_field(name)) {
+            f(self)
+        } else {
+            self.tree.stack.push(Json::Null);
+            f(self).map_err(|_| MissingFieldError(name.to_string()))
+        }
+    }
+
+    fn read_struct_flattened<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut StreamDecoder<'a>) -> DecodeResult<T>,
+    {
+        if !self.streaming() {
+            self.tree.begin_flattened();
+            let value = f(self);
+            self.tree.flatten_next = false;
+            return value;
+        }
+        if let Some(object) = self.objects.last_mut() {
+            if object.taken.is_none() {
+                object.taken = Some(Vec::new());
+            }
+        }
+        self.flatten_next = true;
+        let value = f(self);
+        self.flatten_next = false;
+        value
+    }
+
+    fn read_tuple<T, F>(&mut self, tuple_len: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut StreamDecoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_seq(move |d, len| {
+            if len == tuple_len {
+                f(d)
+            } else {
+                Err(ExpectedError(format!("Tuple{}", tuple_len), format!("Tuple{}", len)))
+            }
+        })
+    }
+
+    fn read_tuple_arg<T, F>(&mut self, idx: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut StreamDecoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_seq_elt(idx, f)
+    }
+
+    fn read_tuple_struct<T, F>(&mut self,
+                               _name: &str,
+                               len: usize,
+                               f: F)
+                               -> DecodeResult<T> where
+        F: FnOnce(&mut StreamDecoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_tuple(len, f)
+    }
+
+    fn read_tuple_struct_arg<T, F>(&mut self,
+                                   idx: usize,
+                                   f: F)
+                                   -> DecodeResult<T> where
+        F: FnOnce(&mut StreamDecoder<'a>) -> DecodeResult<T>,
+    {
+        self.read_tuple_arg(idx, f)
+    }
+
+    fn read_option<T, F>(&mut self, mut f: F) -> DecodeResult<T> where
+        F: FnMut(&mut StreamDecoder<'a>, bool) -> DecodeResult<T>,
+    {
+        let present = if self.flatten_next {
+            // A flattened value takes the object it is flattened into,
+            // which is never a null.
+            true
+        } else if self.streaming() {
+            !self.eat(&NullValue)
+        } else {
+            try!(self.tree.take_option())
+        };
+        f(self, present)
+    }
+
+    fn read_seq<T, F>(&mut self, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut StreamDecoder<'a>, usize) -> DecodeResult<T>,
+    {
+        if self.streaming() && self.builder.token == Some(ArrayStart) {
+            let len = self.count_elements();
+            self.builder.bump();
+            let value = try!(f(self, len));
+            try!(self.skip_to(&ArrayEnd));
+            return Ok(value);
+        }
+        try!(self.fill());
+        let len = try!(self.tree.push_array());
+        f(self, len)
+    }
+
+    fn read_seq_elt<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut StreamDecoder<'a>) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn read_map<T, F>(&mut self, f: F) -> DecodeResult<T> where
+        F: FnOnce(&mut StreamDecoder<'a>, usize) -> DecodeResult<T>,
+    {
+        if self.streaming() && self.builder.token == Some(ObjectStart) {
+            let len = self.count_elements();
+            self.builder.bump();
+            let value = try!(f(self, len));
+            try!(self.skip_to(&ObjectEnd));
+            return Ok(value);
+        }
+        try!(self.fill());
+        let len = try!(self.tree.push_object());
+        f(self, len)
+    }
+
+    fn read_map_elt_key<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
+       F: FnOnce(&mut StreamDecoder<'a>) -> DecodeResult<T>,
+    {
+        if self.streaming() {
+            let key = try!(self.member_key());
+            self.tree.stack.push(Json::String(key));
+        }
+        f(self)
+    }
+
+    fn read_map_elt_val<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
+       F: FnOnce(&mut StreamDecoder<'a>) -> DecodeResult<T>,
+    {
+        f(self)
+    }
+
+    fn next_kind(&mut self) -> DecodeResult<ValueKind> {
+        if self.streaming() {
+            let kind = match self.builder.token {
+                Some(NullValue) => Some(ValueKind::Nil),
+                Some(BooleanValue(_)) => Some(ValueKind::Bool),
+                Some(I64Value(_)) => Some(ValueKind::I64),
+                Some(U64Value(_)) => Some(ValueKind::U64),
+                Some(F64Value(_)) => Some(ValueKind::F64),
+                Some(StringValue(_)) => Some(ValueKind::String),
+                Some(ArrayStart) => Some(ValueKind::Seq),
+                Some(ObjectStart) => Some(ValueKind::Map),
+                // errors are built into the value, and reported from there
+                _ => None,
+            };
+            if let Some(kind) = kind {
+                return Ok(kind);
+            }
+        }
+        try!(self.fill());
+        ::Decoder::next_kind(&mut self.tree)
+    }
+
+    fn read_alternatives<T, F>(&mut self, len: usize, f: F) -> DecodeResult<T> where
+        F: FnMut(&mut StreamDecoder<'a>, usize) -> DecodeResult<T>,
+    {
+        try!(self.fill());
+        let value = try!(self.tree.pop());
+        decode_alternatives(self, value, len, f)
+    }
+
+    fn error(&mut self, err: &str) -> DecoderError {
+        ApplicationError(err.to_string())
+    }
+}
+
+/// A JSON number kept as it is written, for numbers which mustn't be
+/// rounded to an `f64`, such as `1.000000000000000001`.
+///
+/// It encodes as a number token, written as is. Only `decode_str` decodes
+/// it from the document text, and so keeps the number exactly as written.
+/// Everything else goes through `Json` values (`decode`, `from_str_with_config`,
+/// `Decoder`, and the members `decode_str` buffers into `Json` values to
+/// decode out of order), where a decimal has already been read into an
+/// `f64`: decoding a `JsonNumber` from one fails rather than handing out a
+/// rounded number. Integers are exact either way.
+///
+/// Equality compares the text: `1.0` and `1.00` are different.
+///
+/// The parser's limits still apply when decoding: integers beyond the range
+/// of `i64` and `u64` are rejected, as are numbers with more significant
+/// digits than `max_number_len` allows. Such numbers can still be built
+/// with `from_str` and encoded.
+///
+/// # Example
+///
+/// ```rust
+/// use rustc_serialize::json::{self, JsonNumber};
+///
+/// let n: JsonNumber = json::decode_str("1.000000000000000001").unwrap();
+/// assert_eq!(n.as_str(), "1.000000000000000001");
+/// assert_eq!(n.as_f64(), Some(1.0));
+/// assert_eq!(json::encode(&n).unwrap(), "1.000000000000000001");
+/// ```
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct JsonNumber(string::String);
+
+impl JsonNumber {
+    /// Returns the number as it is written.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the number as an `i64` if it is written as an integer in its
+    /// range. Returns None otherwise.
+    pub fn as_i64(&self) -> Option<i64> {
+        self.0.parse().ok()
+    }
+
+    /// Returns the number as a `u64` if it is written as an integer in its
+    /// range. Returns None otherwise.
+    pub fn as_u64(&self) -> Option<u64> {
+        self.0.parse().ok()
+    }
+
+    /// Returns the `f64` closest to the number, or None if it is beyond the
+    /// range of `f64`.
+    pub fn as_f64(&self) -> Option<f64> {
+        self.0.parse().ok().and_then(|f: f64| if f.is_finite() { Some(f) } else { None })
+    }
+}
+
+impl FromStr for JsonNumber {
+    type Err = ParserError;
+
+    /// Checks that `s` is a JSON number, and nothing else.
+    fn from_str(s: &str) -> Result<JsonNumber, ParserError> {
+        if is_number(s) {
+            Ok(JsonNumber(s.to_string()))
+        } else {
+            Err(SyntaxError(InvalidNumber, 1, 1))
+        }
+    }
+}
+
+impl From<i64> for JsonNumber {
+    fn from(n: i64) -> JsonNumber {
+        JsonNumber(n.to_string())
+    }
+}
+
+impl From<u64> for JsonNumber {
+    fn from(n: u64) -> JsonNumber {
+        JsonNumber(n.to_string())
+    }
+}
+
+impl fmt::Display for JsonNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Encodable for JsonNumber {
+    fn encode<E: ::Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+        e.emit_number_str(&self.0)
+    }
+}
+
+impl ::Decodable for JsonNumber {
+    fn decode<D: ::Decoder>(d: &mut D) -> Result<JsonNumber, D::Error> {
+        let s = try!(d.read_number_str());
+        if is_number(&s) {
+            Ok(JsonNumber(s))
+        } else {
+            Err(d.error(&format!("invalid number `{}`", s)))
+        }
+    }
+}
+
+impl ToJson for JsonNumber {
+    /// The number as a `Json` number, rounded to an `f64` if need be, or a
+    /// string for integers too large for any of the `Json` variants.
+    fn to_json(&self) -> Json {
+        match Json::from_str(&self.0) {
+            Ok(json) => json,
+            Err(_) => Json::String(self.0.clone()),
+        }
+    }
+}
+
+// Whether `s` is a number as JSON writes them, and nothing else.
+fn is_number(s: &str) -> bool {
+    let b = s.as_bytes();
+    // The end of the digits starting at `i`, and how many there are.
+    let digits = |mut i: usize| {
+        let start = i;
+        while i < b.len() && b'0' <= b[i] && b[i] <= b'9' {
+            i += 1;
+        }
+        (i, i - start)
+    };
+
+    let mut i = if b.first() == Some(&b'-') { 1 } else { 0 };
+    let (end, n) = digits(i);
+    // A leading 0 must be the only digit before the decimal point.
+    if n == 0 || (n > 1 && b[i] == b'0') {
+        return false;
+    }
+    i = end;
+    if i < b.len() && b[i] == b'.' {
+        let (end, n) = digits(i + 1);
+        if n == 0 {
+            return false;
+        }
+        i = end;
+    }
+    if i < b.len() && (b[i] == b'e' || b[i] == b'E') {
+        i += 1;
+        if i < b.len() && (b[i] == b'+' || b[i] == b'-') {
+            i += 1;
+        }
+        let (end, n) = digits(i);
+        if n == 0 {
+            return false;
+        }
+        i = end;
+    }
+    i == b.len()
+}
+
+/// An optional string whose `None` is left out of its object altogether,
+/// rather than written as `null`.
+///
+/// It encodes as `Option<String>` does, and the field can be left out by
+/// `#[serialize(skip_serializing_if = "NullableString::is_none")]` or by
+/// `Encoder::set_omit_null`. A missing field, as well as `null`, decodes to
+/// `None`.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[derive(RustcEncodable, RustcDecodable)]
+/// struct User {
+///     name: String,
+///     #[serialize(skip_serializing_if = "NullableString::is_none")]
+///     nickname: NullableString, // no "nickname" key when None
+/// }
+/// ```
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct NullableString(pub Option<string::String>);
+
+impl NullableString {
+    /// Returns true if there is no string.
+    pub fn is_none(&self) -> bool {
+        self.0.is_none()
     }
+}
 
-    fn read_seq<T, F>(&mut self, f: F) -> DecodeResult<T> where
-        F: FnOnce(&mut Decoder, usize) -> DecodeResult<T>,
-    {
-        let array = try!(expect!(self.pop(), Array));
-        let len = array.len();
-        for v in array.into_iter().rev() {
-            self.stack.push(v);
-        }
-        f(self, len)
+impl From<Option<string::String>> for NullableString {
+    fn from(s: Option<string::String>) -> NullableString {
+        NullableString(s)
     }
+}
 
-    fn read_seq_elt<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
-        F: FnOnce(&mut Decoder) -> DecodeResult<T>,
-    {
-        f(self)
+impl Encodable for NullableString {
+    fn encode<E: ::Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+        self.0.encode(e)
     }
+}
 
-    fn read_map<T, F>(&mut self, f: F) -> DecodeResult<T> where
-        F: FnOnce(&mut Decoder, usize) -> DecodeResult<T>,
-    {
-        let obj = try!(expect!(self.pop(), Object));
-        let len = obj.len();
-        for (key, value) in obj.into_iter() {
-            self.stack.push(value);
-            self.stack.push(Json::String(key));
-        }
-        f(self, len)
+impl ::Decodable for NullableString {
+    fn decode<D: ::Decoder>(d: &mut D) -> Result<NullableString, D::Error> {
+        ::Decodable::decode(d).map(NullableString)
     }
+}
 
-    fn read_map_elt_key<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
-       F: FnOnce(&mut Decoder) -> DecodeResult<T>,
-    {
-        f(self)
+/// An optional string whose `None` is written as the string `"null"`, for
+/// APIs which put nulls in strings.
+///
+/// The string `"null"` decodes to `None`, and so does a JSON `null` or a
+/// missing field. Note that `Some("null")` therefore comes back as `None`.
+///
+/// # Example
+///
+/// ```rust
+/// use rustc_serialize::json::{self, NullString};
+///
+/// assert_eq!(json::encode(&NullString(None)).unwrap(), "\"null\"");
+/// let s: NullString = json::decode("\"null\"").unwrap();
+/// assert_eq!(s, NullString(None));
+/// ```
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct NullString(pub Option<string::String>);
+
+impl From<Option<string::String>> for NullString {
+    fn from(s: Option<string::String>) -> NullString {
+        NullString(s)
     }
+}
 
-    fn read_map_elt_val<T, F>(&mut self, _idx: usize, f: F) -> DecodeResult<T> where
-       F: FnOnce(&mut Decoder) -> DecodeResult<T>,
-    {
-        f(self)
+impl Encodable for NullString {
+    fn encode<E: ::Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+        match self.0 {
+            Some(ref s) => e.emit_str(s),
+            None => e.emit_str("null"),
+        }
     }
+}
 
-    fn error(&mut self, err: &str) -> DecoderError {
-        ApplicationError(err.to_string())
+impl ::Decodable for NullString {
+    fn decode<D: ::Decoder>(d: &mut D) -> Result<NullString, D::Error> {
+        d.read_option(|d, present| {
+            if !present {
+                return Ok(NullString(None));
+            }
+            let s = try!(d.read_str());
+            Ok(NullString(if s == "null" { None } else { Some(s) }))
+        })
     }
 }
 
@@ -2401,6 +6516,32 @@ macro_rules! to_json_impl_u64 {
 
 to_json_impl_u64! { usize, u8, u16, u32, u64 }
 
+// Values which no `Json` number holds become strings of their digits, as
+// `Encoder` writes them.
+#[cfg(feature = "i128")]
+impl ToJson for i128 {
+    fn to_json(&self) -> Json {
+        if *self < i64::MIN as i128 {
+            Json::String(self.to_string())
+        } else if *self <= i64::MAX as i128 {
+            Json::I64(*self as i64)
+        } else {
+            (*self as u128).to_json()
+        }
+    }
+}
+
+#[cfg(feature = "i128")]
+impl ToJson for u128 {
+    fn to_json(&self) -> Json {
+        if *self <= u64::MAX as u128 {
+            Json::U64(*self as u64)
+        } else {
+            Json::String(self.to_string())
+        }
+    }
+}
+
 impl ToJson for Json {
     fn to_json(&self) -> Json { self.clone() }
 }
@@ -2467,6 +6608,10 @@ tuple_impl!{A, B, C, D, E, F, G, H, I}
 tuple_impl!{A, B, C, D, E, F, G, H, I, J}
 tuple_impl!{A, B, C, D, E, F, G, H, I, J, K}
 tuple_impl!{A, B, C, D, E, F, G, H, I, J, K, L}
+tuple_impl!{A, B, C, D, E, F, G, H, I, J, K, L, M}
+tuple_impl!{A, B, C, D, E, F, G, H, I, J, K, L, M, N}
+tuple_impl!{A, B, C, D, E, F, G, H, I, J, K, L, M, N, O}
+tuple_impl!{A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P}
 
 impl<A: ToJson> ToJson for [A] {
     fn to_json(&self) -> Json { Json::Array(self.iter().map(|elt| elt.to_json()).collect()) }
@@ -2496,6 +6641,14 @@ impl<A: ToJson> ToJson for HashMap<string::String, A> {
     }
 }
 
+impl ToJson for Base64Bytes {
+    fn to_json(&self) -> Json { Json::from_bytes_base64(&self.0) }
+}
+
+impl ToJson for HexBytes {
+    fn to_json(&self) -> Json { Json::String(self.0.to_hex()) }
+}
+
 impl<A:ToJson> ToJson for Option<A> {
     fn to_json(&self) -> Json {
         match *self {
@@ -2585,6 +6738,336 @@ impl FromStr for Json {
     }
 }
 
+impl From<bool> for Json {
+    fn from(b: bool) -> Json { Json::Boolean(b) }
+}
+
+impl From<i64> for Json {
+    /// Makes a `U64` of numbers which aren't negative, as the parser does.
+    fn from(n: i64) -> Json {
+        if n < 0 { Json::I64(n) } else { Json::U64(n as u64) }
+    }
+}
+
+impl From<f64> for Json {
+    fn from(n: f64) -> Json { Json::F64(n) }
+}
+
+impl From<string::String> for Json {
+    fn from(s: string::String) -> Json { Json::String(s) }
+}
+
+impl From<Array> for Json {
+    fn from(v: Array) -> Json { Json::Array(v) }
+}
+
+impl From<Object> for Json {
+    fn from(o: Object) -> Json { Json::Object(o) }
+}
+
+// Implements `TryFrom<Json>` for `$t`, with `$e` giving `Some` of the result
+// if the value, bound to `$json`, is of the right type.
+macro_rules! try_from_json {
+    ($t:ty, $name:expr, $json:ident => $e:expr) => {
+        #[cfg(feature = "try-from")]
+        impl ::std::convert::TryFrom<Json> for $t {
+            type Error = JsonTypeMismatch;
+            fn try_from(json: Json) -> Result<$t, JsonTypeMismatch> {
+                let got = json.variant_name();
+                let $json = json;
+                match $e {
+                    Some(value) => Ok(value),
+                    None => Err(JsonTypeMismatch { expected: $name, got: got }),
+                }
+            }
+        }
+    }
+}
+
+try_from_json!(bool, "bool", json => json.as_boolean());
+try_from_json!(i64, "i64", json => json.as_i64());
+try_from_json!(f64, "f64", json => json.as_f64());
+try_from_json!(string::String, "String", json => match json {
+    Json::String(s) => Some(s),
+    _ => None,
+});
+try_from_json!(Array, "Array", json => match json {
+    Json::Array(v) => Some(v),
+    _ => None,
+});
+try_from_json!(Object, "Object", json => match json {
+    Json::Object(o) => Some(o),
+    _ => None,
+});
+
+/// Newline delimited JSON, also known as JSON Lines: a sequence of values
+/// written one per line.
+///
+/// ```rust
+/// use rustc_serialize::json::ndjson;
+///
+/// let text = ndjson::encode_ndjson(&[vec![1, 2], vec![3]]).unwrap();
+/// assert_eq!(text, "[1,2]\n[3]\n");
+///
+/// let values: Vec<Vec<u32>> = ndjson::decode_ndjson(&text).unwrap();
+/// assert_eq!(values, [vec![1, 2], vec![3]]);
+///
+/// let reader = ndjson::NdjsonReader::<_, Vec<u32>>::new(text.as_bytes());
+/// assert_eq!(reader.map(|v| v.unwrap().len()).collect::<Vec<_>>(), [2, 1]);
+/// ```
+pub mod ndjson {
+    use std::error::Error as StdError;
+    use std::io::prelude::*;
+    use std::io::{self, BufReader};
+    use std::marker::PhantomData;
+    use std::{fmt, str, string};
+
+    use {Decodable, Encodable};
+    use super::{BuilderError, DecoderError, EncodeResult, Encoder, Json};
+    use super::ErrorCode::NotUtf8;
+    use super::ParserError::{IoError, SyntaxError};
+
+    /// The reasons decoding newline delimited JSON can fail.
+    #[derive(Debug)]
+    pub enum NdjsonError {
+        /// The line, counting from 1, doesn't hold a value of the expected
+        /// type.
+        DecodeError(usize, DecoderError),
+        /// Reading the input failed.
+        IoError(io::Error),
+    }
+
+    impl PartialEq for NdjsonError {
+        fn eq(&self, other: &NdjsonError) -> bool {
+            match (self, other) {
+                (&NdjsonError::DecodeError(line0, ref err0),
+                 &NdjsonError::DecodeError(line1, ref err1)) => line0 == line1 && err0 == err1,
+                _ => false,
+            }
+        }
+    }
+
+    impl StdError for NdjsonError {
+        fn description(&self) -> &str { "failed to decode newline delimited json" }
+        fn cause(&self) -> Option<&StdError> {
+            match *self {
+                NdjsonError::DecodeError(_, ref e) => Some(e),
+                NdjsonError::IoError(ref e) => Some(e),
+            }
+        }
+        #[cfg(feature = "error-source")]
+        fn source(&self) -> Option<&(StdError + 'static)> {
+            match *self {
+                NdjsonError::DecodeError(_, ref e) => Some(e),
+                NdjsonError::IoError(ref e) => Some(e),
+            }
+        }
+    }
+
+    impl fmt::Display for NdjsonError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                NdjsonError::DecodeError(line, ref e) => write!(f, "line {}: {}", line, e),
+                NdjsonError::IoError(ref e) => fmt::Display::fmt(e, f),
+            }
+        }
+    }
+
+    impl From<io::Error> for NdjsonError {
+        fn from(err: io::Error) -> NdjsonError {
+            NdjsonError::IoError(err)
+        }
+    }
+
+    /// Encodes each value as compact JSON on a line of its own, each line
+    /// ending with `\n`.
+    ///
+    /// Fails if a value can't be encoded as JSON, such as a map whose keys
+    /// aren't strings or numbers.
+    pub fn encode_ndjson<T: Encodable>(values: &[T]) -> EncodeResult<string::String> {
+        let mut s = string::String::new();
+        for value in values.iter() {
+            {
+                let mut encoder = Encoder::new(&mut s);
+                try!(value.encode(&mut encoder));
+            }
+            s.push('\n');
+        }
+        Ok(s)
+    }
+
+    /// Decodes a value from each line of `input`. Lines holding nothing but
+    /// whitespace are skipped.
+    pub fn decode_ndjson<T: Decodable>(input: &str) -> Result<Vec<T>, NdjsonError> {
+        let mut values = Vec::new();
+        for (i, line) in input.lines().enumerate() {
+            if let Some(value) = decode_line(line, i + 1) {
+                values.push(try!(value));
+            }
+        }
+        Ok(values)
+    }
+
+    fn decode_line<T: Decodable>(line: &str, number: usize) -> Option<Result<T, NdjsonError>> {
+        if line.trim().is_empty() {
+            return None;
+        }
+        Some(super::decode(line).map_err(|e| NdjsonError::DecodeError(number, e)))
+    }
+
+    /// An iterator decoding a value from each line read off a reader, one at
+    /// a time. Lines holding nothing but whitespace are skipped.
+    ///
+    /// A line which fails to decode is reported and the iterator moves on to
+    /// the next one, while a failure to read ends the iteration.
+    pub struct NdjsonReader<R, T> {
+        reader: BufReader<R>,
+        buf: string::String,
+        // the number of the last line read
+        line: usize,
+        done: bool,
+        marker: PhantomData<fn() -> T>,
+    }
+
+    impl<R: Read, T: Decodable> NdjsonReader<R, T> {
+        /// Creates an iterator over the values on the lines of `reader`.
+        pub fn new(reader: R) -> NdjsonReader<R, T> {
+            NdjsonReader {
+                reader: BufReader::new(reader),
+                buf: string::String::new(),
+                line: 0,
+                done: false,
+                marker: PhantomData,
+            }
+        }
+    }
+
+    impl<R: Read, T: Decodable> Iterator for NdjsonReader<R, T> {
+        type Item = Result<T, NdjsonError>;
+
+        fn next(&mut self) -> Option<Result<T, NdjsonError>> {
+            while !self.done {
+                self.buf.clear();
+                match self.reader.read_line(&mut self.buf) {
+                    Ok(0) => self.done = true,
+                    Ok(_) => {
+                        self.line += 1;
+                        if let Some(value) = decode_line(&self.buf, self.line) {
+                            return Some(value);
+                        }
+                    }
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(NdjsonError::IoError(e)));
+                    }
+                }
+            }
+            None
+        }
+    }
+
+    /// Parses a `Json` value from each line read off `reader`, one at a
+    /// time. Lines holding nothing but whitespace are skipped.
+    ///
+    /// A line which fails to parse is reported, with the line number of the
+    /// error counted from the start of the input, and the iterator moves on
+    /// to the next line. A failure to read is reported as an `IoError` and
+    /// ends the iteration.
+    ///
+    /// ```rust
+    /// use rustc_serialize::json::{self, Json};
+    ///
+    /// let input = "{\"level\": \"info\"}\n\n[1, 2\n3\n";
+    /// let mut lines = json::read_lines(input.as_bytes());
+    /// assert!(lines.next().unwrap().unwrap().is_object());
+    /// assert!(lines.next().unwrap().is_err());
+    /// assert_eq!(lines.next().unwrap(), Ok(Json::U64(3)));
+    /// assert!(lines.next().is_none());
+    /// ```
+    pub fn read_lines<R: Read>(reader: R) -> JsonLines<R> {
+        JsonLines {
+            reader: BufReader::new(reader),
+            buf: Vec::new(),
+            line: 0,
+            done: false,
+        }
+    }
+
+    /// An iterator parsing a `Json` value from each line read off a reader.
+    ///
+    /// This struct is created by the `read_lines` function.
+    pub struct JsonLines<R> {
+        reader: BufReader<R>,
+        buf: Vec<u8>,
+        // the number of the last line read
+        line: usize,
+        done: bool,
+    }
+
+    impl<R: Read> Iterator for JsonLines<R> {
+        type Item = Result<Json, BuilderError>;
+
+        fn next(&mut self) -> Option<Result<Json, BuilderError>> {
+            while !self.done {
+                self.buf.clear();
+                match self.reader.read_until(b'\n', &mut self.buf) {
+                    Ok(0) => self.done = true,
+                    Ok(_) => {
+                        self.line += 1;
+                        let line = match str::from_utf8(&self.buf) {
+                            Ok(line) => line.trim_right_matches(|c| c == '\n' || c == '\r'),
+                            Err(_) => return Some(Err(SyntaxError(NotUtf8, self.line, 0))),
+                        };
+                        if line.trim().is_empty() {
+                            continue;
+                        }
+                        return Some(Json::from_str(line).map_err(|e| match e {
+                            SyntaxError(code, _, col) => SyntaxError(code, self.line, col),
+                            e => e,
+                        }));
+                    }
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(IoError(e)));
+                    }
+                }
+            }
+            None
+        }
+    }
+
+    /// Writes each value as compact JSON on a line of its own to `writer`,
+    /// each line ending with `\n`, as `encode_ndjson` does.
+    ///
+    /// A value is encoded in full before any of it is written, so one failing
+    /// to encode, reported as an `io::ErrorKind::InvalidInput` error wrapping
+    /// the `EncoderError`, stops the writing after the lines before it.
+    ///
+    /// ```rust
+    /// use rustc_serialize::json;
+    ///
+    /// let mut out = Vec::new();
+    /// json::write_lines(&mut out, &[(1, "a"), (2, "b")]).unwrap();
+    /// assert_eq!(out, b"[1,\"a\"]\n[2,\"b\"]\n");
+    /// ```
+    pub fn write_lines<W, I>(writer: &mut W, values: I) -> io::Result<()>
+        where W: Write, I: IntoIterator, I::Item: Encodable
+    {
+        let mut buf = string::String::new();
+        for value in values {
+            buf.clear();
+            if let Err(e) = value.encode(&mut Encoder::new(&mut buf)) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, e));
+            }
+            buf.push('\n');
+            try!(writer.write_all(buf.as_bytes()));
+        }
+        Ok(())
+    }
+}
+
+pub use self::ndjson::{read_lines, write_lines, JsonLines};
+
 #[cfg(test)]
 mod tests {
     use self::Animal::*;
@@ -2595,9 +7078,11 @@ mod tests {
     use super::DecoderError::*;
     use super::JsonEvent::*;
     use super::StackElement::*;
-    use super::{Json, DecodeResult, DecoderError, JsonEvent, Parser,
-                StackElement, Stack, Decoder, Encoder, EncoderError};
+    use super::{Json, DecodeResult, DecoderError, JsonEvent, Parser, Builder,
+                BuilderError, StackElement, Stack, Decoder, Encoder, EncoderError,
+                LookupError, ByteCounter};
     use std::{i64, u64, f32, f64};
+    use std::cell::RefCell;
     use std::collections::BTreeMap;
     use std::string;
 
@@ -2606,6 +7091,40 @@ mod tests {
         opt: Option<usize>,
     }
 
+    #[test]
+    fn test_errors() {
+        use std::error::Error;
+        use std::io;
+
+        fn assert_send_sync<E: Error + Send + Sync + 'static>(e: E) -> Box<Error + Send + Sync> {
+            Box::new(e)
+        }
+
+        let err = super::decode::<u8>("[1,").unwrap_err();
+        assert_eq!(err.to_string(), "EOF While parsing value at line 1 column 4");
+        assert_eq!(err.cause().unwrap().to_string(), "EOF While parsing value at line 1 column 4");
+        assert_send_sync(err);
+        let err = super::decode::<u8>("true").unwrap_err();
+        assert_eq!(err.to_string(), "expected Number, found true");
+        assert!(err.cause().is_none());
+        assert_eq!(MissingFieldError("a".to_string()).to_string(), "missing field `a`");
+        assert_eq!(EOF.to_string(), "unexpected end of input");
+
+        let err = IoError(io::Error::new(io::ErrorKind::Other, "gone"));
+        assert_eq!(err.to_string(), "gone");
+        assert_eq!(err.cause().unwrap().to_string(), "gone");
+        assert_send_sync(err);
+
+        let mut map = BTreeMap::new();
+        map.insert(vec![1u8], 2u8);
+        let err = super::encode(&map).unwrap_err();
+        assert_eq!(err.to_string(), "map key is not a string or number");
+        assert_send_sync(err);
+        assert_send_sync(LookupError::ExpectedArray);
+        assert_send_sync(::base64::InvalidBase64Length);
+        assert_send_sync(::hex::InvalidHexLength(1));
+    }
+
     #[test]
     fn test_decode_option_none() {
         let s ="{}";
@@ -2696,17 +7215,468 @@ mod tests {
         assert_eq!(F64(-1.5).to_string(), "-1.5");
         assert_eq!(F64(-1.5).pretty().to_string(), "-1.5");
 
-        assert_eq!(F64(0.5).to_string(), "0.5");
-        assert_eq!(F64(0.5).pretty().to_string(), "0.5");
+        assert_eq!(F64(0.5).to_string(), "0.5");
+        assert_eq!(F64(0.5).pretty().to_string(), "0.5");
+
+        assert_eq!(F64(f64::NAN).to_string(), "null");
+        assert_eq!(F64(f64::NAN).pretty().to_string(), "null");
+
+        assert_eq!(F64(f64::INFINITY).to_string(), "null");
+        assert_eq!(F64(f64::INFINITY).pretty().to_string(), "null");
+
+        assert_eq!(F64(f64::NEG_INFINITY).to_string(), "null");
+        assert_eq!(F64(f64::NEG_INFINITY).pretty().to_string(), "null");
+    }
+
+    #[test]
+    fn test_encode_to_bytes() {
+        let value = (1u8, "\u{e9}\"", vec![None, Some(2.5f64)]);
+        let bytes = super::encode_to_bytes(&value).unwrap();
+        assert_eq!(bytes, super::encode(&value).unwrap().into_bytes());
+        assert_eq!(bytes, "[1,\"\u{e9}\\\"\",[null,2.5]]".as_bytes());
+    }
+
+    #[test]
+    fn test_encoded_len() {
+        let value = (1u8, "\u{e9}\"", vec![None, Some(2.5f64)]);
+        assert_eq!(super::encoded_len(&value).unwrap(), super::encode(&value).unwrap().len());
+        assert_eq!(super::encoded_len(&Null).unwrap(), 4);
+
+        let mut map = BTreeMap::new();
+        map.insert(vec![1u8], 2u8);
+        assert_eq!(super::encoded_len(&map), Err(EncoderError::BadHashmapKey));
+
+        let mut counter = ByteCounter::default();
+        {
+            let mut encoder = Encoder::new_pretty(&mut counter);
+            value.encode(&mut encoder).unwrap();
+        }
+        assert_eq!(counter.byte_count(), super::as_pretty_json(&value).to_string().len());
+    }
+
+    #[test]
+    fn test_value_encoder_misplaced_values() {
+        use super::JsonValueEncoder;
+
+        struct LoneElement;
+        impl Encodable for LoneElement {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_seq_elt(0, |s| s.emit_u8(1))
+            }
+        }
+        struct KeylessValue;
+        impl Encodable for KeylessValue {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_map(1, |s| s.emit_map_elt_val(0, |s| s.emit_u8(1)))
+            }
+        }
+
+        assert_eq!(super::to_value(&LoneElement), Err(EncoderError::MisplacedValue));
+        assert_eq!(super::to_value(&KeylessValue), Err(EncoderError::MisplacedValue));
+
+        let mut encoder = JsonValueEncoder::default();
+        assert_eq!(LoneElement.encode(&mut encoder), Err(EncoderError::MisplacedValue));
+        assert_eq!(encoder.finish(), Null);
+    }
+
+    #[test]
+    fn test_from_str_prefix() {
+        let s = "{\"a\": [1]}  \"\u{e9}\"\n-2.5[]null\r\n";
+        let mut values = Vec::new();
+        let mut rest = s;
+        while !rest.is_empty() {
+            let (json, len) = Json::from_str_prefix(rest).unwrap();
+            values.push((json, len));
+            rest = &rest[len..];
+        }
+        assert_eq!(values, vec![
+            (Json::from_str("{\"a\": [1]}").unwrap(), 12),
+            (String("\u{e9}".to_string()), 5),
+            (F64(-2.5), 4),
+            (Array(vec![]), 2),
+            (Null, 6),
+        ]);
+
+        // leading whitespace is skipped, and the value needn't be followed by
+        // anything valid
+        assert_eq!(Json::from_str_prefix(" 12x"), Ok((U64(12), 3)));
+        assert_eq!(Json::from_str_prefix("1}"), Ok((U64(1), 1)));
+        assert_eq!(Json::from_str_prefix(""), Err(SyntaxError(EOFWhileParsingValue, 1, 1)));
+        assert_eq!(Json::from_str_prefix("  "), Err(SyntaxError(EOFWhileParsingValue, 1, 3)));
+        assert_eq!(Json::from_str_prefix("[1, 2"), Err(SyntaxError(EOFWhileParsingArray, 1, 6)));
+    }
+
+    #[test]
+    fn test_array_encoder() {
+        use super::ArrayEncoder;
+        use std::collections::HashMap;
+        use std::io;
+
+        let array = ArrayEncoder::new(Vec::new()).unwrap();
+        assert_eq!(array.len(), 0);
+        assert!(array.is_empty());
+        assert_eq!(array.finish().unwrap(), b"[]");
+
+        let mut array = ArrayEncoder::new(Vec::new()).unwrap();
+        array.push(&"a").unwrap();
+        array.push(&Some(vec![1, 2])).unwrap();
+
+        // an element which fails to encode is left out entirely
+        let mut bad = ::std::collections::HashMap::new();
+        bad.insert(vec![1], 2);
+        let err = array.push(&bad).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        array.push(&Null).unwrap();
+        assert_eq!(array.len(), 3);
+        assert!(!array.is_empty());
+        let bytes = array.finish().unwrap();
+        assert_eq!(bytes, b"[\"a\",[1,2],null]");
+        assert_eq!(Json::from_str(str::from_utf8(&bytes).unwrap()).unwrap().as_array().unwrap().len(),
+                   3);
+
+        // each element is flushed as it is pushed
+        struct Flushes(Vec<usize>, usize);
+        impl io::Write for Flushes {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.1 += buf.len();
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                self.0.push(self.1);
+                Ok(())
+            }
+        }
+        let mut array = ArrayEncoder::new(Flushes(vec![], 0)).unwrap();
+        array.push(&1).unwrap();
+        array.push(&22).unwrap();
+        assert_eq!(array.finish().unwrap().0, vec![2, 5, 6]);
+    }
+
+    #[test]
+    fn test_json_writer() {
+        use super::JsonWriter;
+        use std::collections::HashMap;
+        use std::io;
+
+        fn invalid<T: ::std::fmt::Debug>(res: io::Result<T>) {
+            assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+
+        let mut writer = JsonWriter::new(Vec::new());
+        writer.begin_array().unwrap();
+        writer.begin_object().unwrap();
+        writer.end_object().unwrap();
+        writer.begin_object().unwrap();
+        writer.key("a\"b").unwrap();
+        writer.begin_array().unwrap();
+        writer.end_array().unwrap();
+        writer.key("c").unwrap();
+        writer.value(&Some(1.5)).unwrap();
+        writer.end_object().unwrap();
+        writer.raw_value(" [1, 2] ").unwrap();
+        writer.value(&Null).unwrap();
+        writer.end_array().unwrap();
+        let out = writer.finish().unwrap();
+        assert_eq!(str::from_utf8(&out).unwrap(), "[{},{\"a\\\"b\":[],\"c\":1.5}, [1, 2] ,null]");
+        assert!(Json::from_str(str::from_utf8(&out).unwrap()).is_ok());
+
+        // a single scalar makes a document
+        let mut writer = JsonWriter::new(Vec::new());
+        writer.value(&"x").unwrap();
+        invalid(writer.value(&"y"));
+        invalid(writer.begin_array());
+        assert_eq!(writer.finish().unwrap(), b"\"x\"");
+
+        // misuse is reported and writes nothing
+        let mut writer = JsonWriter::new(Vec::new());
+        invalid(writer.key("a"));
+        invalid(writer.end_object());
+        invalid(writer.end_array());
+        writer.begin_object().unwrap();
+        invalid(writer.value(&1));
+        invalid(writer.begin_array());
+        invalid(writer.end_array());
+        writer.key("a").unwrap();
+        invalid(writer.key("b"));
+        invalid(writer.end_object());
+        let mut bad = ::std::collections::HashMap::new();
+        bad.insert(vec![1], 2);
+        invalid(writer.value(&bad));
+        invalid(writer.raw_value("[1,"));
+        invalid(writer.raw_value("1 2"));
+        writer.raw_value("true").unwrap();
+        writer.end_object().unwrap();
+        assert_eq!(writer.finish().unwrap(), b"{\"a\":true}");
+
+        // as is leaving the document incomplete
+        invalid(JsonWriter::new(Vec::new()).finish());
+        let mut writer = JsonWriter::new(Vec::new());
+        writer.begin_array().unwrap();
+        invalid(writer.finish());
+    }
+
+    #[test]
+    fn test_infer_schema() {
+        use super::{infer_schema, FieldSchema, JsonSchema};
+        use std::collections::HashMap;
+
+        fn parse(s: &[&str]) -> Vec<Json> {
+            s.iter().map(|s| Json::from_str(s).unwrap()).collect()
+        }
+        fn object(fields: &[(&str, JsonSchema, bool)]) -> JsonSchema {
+            let mut map = HashMap::new();
+            for &(key, ref schema, required) in fields.iter() {
+                map.insert(key.to_string(), FieldSchema { schema: schema.clone(), required: required });
+            }
+            JsonSchema::Object(map)
+        }
+        fn array(schema: JsonSchema) -> JsonSchema {
+            JsonSchema::Array(Box::new(schema))
+        }
+
+        assert_eq!(infer_schema(&[]), JsonSchema::Any);
+        assert_eq!(infer_schema(&parse(&["1", "-2", "3.5"])), JsonSchema::Number);
+        assert_eq!(infer_schema(&parse(&["[]"])), array(JsonSchema::Any));
+        assert_eq!(infer_schema(&parse(&["[]", "[true]"])), array(JsonSchema::Bool));
+
+        // members missing from some of the objects aren't required
+        let samples = parse(&["{\"id\": 1, \"name\": \"a\", \"extra\": {\"x\": true}}",
+                              "{\"id\": 2, \"name\": null}",
+                              "{\"id\": 3, \"name\": \"c\", \"extra\": {\"y\": 1}}"]);
+        assert_eq!(infer_schema(&samples),
+                   object(&[("id", JsonSchema::Number, true),
+                            ("name", JsonSchema::Union(vec![JsonSchema::String, JsonSchema::Null]),
+                             true),
+                            ("extra", object(&[("x", JsonSchema::Bool, false),
+                                               ("y", JsonSchema::Number, false)]),
+                             false)]));
+
+        // the elements of an array are described together, with unions
+        // flattened and one variant of each type
+        let samples = parse(&["[1, \"a\", [null], {\"k\": 1}]",
+                              "[2.5, [\"b\"], {}, true]",
+                              "\"top\""]);
+        assert_eq!(infer_schema(&samples),
+                   JsonSchema::Union(vec![
+                       array(JsonSchema::Union(vec![
+                           JsonSchema::Number,
+                           JsonSchema::String,
+                           array(JsonSchema::Union(vec![JsonSchema::Null, JsonSchema::String])),
+                           object(&[("k", JsonSchema::Number, false)]),
+                           JsonSchema::Bool,
+                       ])),
+                       JsonSchema::String,
+                   ]));
+    }
+
+    #[test]
+    fn test_from_conversions() {
+        assert_eq!(Json::from(true), Boolean(true));
+        assert_eq!(Json::from(-3i64), I64(-3));
+        assert_eq!(Json::from(3i64), U64(3));
+        assert_eq!(Json::from(3i64), Json::from_str("3").unwrap());
+        assert_eq!(Json::from(0.5), F64(0.5));
+        assert_eq!(Json::from("a".to_string()), String("a".to_string()));
+        assert_eq!(Json::from(vec![Null]), Array(vec![Null]));
+        let mut obj = BTreeMap::new();
+        obj.insert("a".to_string(), Null);
+        assert_eq!(Json::from(obj.clone()), Object(obj));
+    }
+
+    #[cfg(feature = "try-from")]
+    #[test]
+    fn test_try_from() {
+        use super::JsonTypeMismatch;
+        use std::convert::TryFrom;
+
+        fn mismatch(expected: &'static str, got: &'static str) -> JsonTypeMismatch {
+            JsonTypeMismatch { expected: expected, got: got }
+        }
+
+        assert_eq!(bool::try_from(Boolean(false)), Ok(false));
+        assert_eq!(bool::try_from(Null), Err(mismatch("bool", "Null")));
+        assert_eq!(i64::try_from(I64(-1)), Ok(-1));
+        assert_eq!(i64::try_from(U64(7)), Ok(7));
+        assert_eq!(i64::try_from(U64(u64::MAX)), Err(mismatch("i64", "U64")));
+        assert_eq!(i64::try_from(F64(1.0)), Err(mismatch("i64", "F64")));
+        assert_eq!(f64::try_from(F64(1.5)), Ok(1.5));
+        assert_eq!(f64::try_from(I64(-2)), Ok(-2.0));
+        assert_eq!(f64::try_from(String("1".to_string())), Err(mismatch("f64", "String")));
+        assert_eq!(string::String::try_from(String("x".to_string())), Ok("x".to_string()));
+        assert_eq!(string::String::try_from(U64(1)), Err(mismatch("String", "U64")));
+        assert_eq!(Vec::<Json>::try_from(Array(vec![U64(1)])), Ok(vec![U64(1)]));
+        assert_eq!(Vec::<Json>::try_from(Object(BTreeMap::new())),
+                   Err(mismatch("Array", "Object")));
+        assert_eq!(BTreeMap::<string::String, Json>::try_from(Object(BTreeMap::new())),
+                   Ok(BTreeMap::new()));
+        assert_eq!(BTreeMap::<string::String, Json>::try_from(Array(vec![])),
+                   Err(mismatch("Object", "Array")));
+        assert_eq!(mismatch("bool", "Null").to_string(), "expected bool, found Null");
+
+        // round trips through `From`
+        for json in vec![Boolean(true), I64(-5), U64(5), F64(2.5)] {
+            let back = match json {
+                Boolean(_) => Json::from(bool::try_from(json.clone()).unwrap()),
+                F64(_) => Json::from(f64::try_from(json.clone()).unwrap()),
+                _ => Json::from(i64::try_from(json.clone()).unwrap()),
+            };
+            assert_eq!(back, json);
+        }
+    }
+
+    #[test]
+    fn test_to_writer() {
+        use super::{encode_with_len, to_writer};
+        use std::collections::HashMap;
+        use std::io;
+
+        let value = (vec!["caf\u{e9}", "\n"], Some(1.5), ());
+        let encoded = super::encode(&value).unwrap();
+        assert_eq!(encode_with_len(&value).unwrap(), (encoded.clone(), encoded.len()));
+        let mut out = Vec::new();
+        assert_eq!(to_writer(&mut out, &value).unwrap(), encoded.len());
+        assert_eq!(out, encoded.as_bytes());
+        assert_eq!(to_writer(&mut out, &Null).unwrap(), 4);
+        assert_eq!(&out[encoded.len()..], b"null");
+
+        let mut bad = ::std::collections::HashMap::new();
+        bad.insert(vec![1], 2);
+        assert_eq!(encode_with_len(&bad), Err(EncoderError::BadHashmapKey));
+        assert_eq!(to_writer(&mut Vec::new(), &bad).unwrap_err().kind(),
+                   io::ErrorKind::InvalidInput);
+
+        // failures to write are passed on as they are
+        struct Full(usize);
+        impl io::Write for Full {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if self.0 < buf.len() {
+                    return Err(io::Error::new(io::ErrorKind::WriteZero, "full"));
+                }
+                self.0 -= buf.len();
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> { Ok(()) }
+        }
+        assert_eq!(to_writer(&mut Full(3), &vec![1, 2]).unwrap_err().kind(),
+                   io::ErrorKind::WriteZero);
+        assert_eq!(to_writer(&mut Full(5), &vec![1, 2]).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_lazy_json_array() {
+        use super::LazyJsonArray;
+
+        fn collect(s: &str) -> Vec<Result<Json, BuilderError>> {
+            LazyJsonArray::new(s).collect()
+        }
+
+        let s = " [1, \"a\", [2, [3]], {\"b\": null}, -0.5] \n";
+        assert_eq!(collect(s).into_iter().map(|e| e.unwrap()).collect::<Vec<_>>(),
+                   *Json::from_str(s).unwrap().as_array().unwrap());
+        assert_eq!(collect("[]"), []);
+        assert_eq!(collect(" [ ] "), []);
+
+        // elements come one at a time, up to the first error
+        let mut array = LazyJsonArray::new("[1, 2, x, 4]");
+        assert_eq!(array.next(), Some(Ok(U64(1))));
+        assert_eq!(array.next(), Some(Ok(U64(2))));
+        assert_eq!(array.next(), Some(Err(SyntaxError(InvalidSyntax, 1, 8))));
+        assert_eq!(array.next(), None);
+
+        assert_eq!(collect("[1, [2"), [Ok(U64(1)), Err(SyntaxError(EOFWhileParsingArray, 1, 7))]);
+        assert_eq!(collect("[1,]"), [Ok(U64(1)), Err(SyntaxError(InvalidSyntax, 1, 4))]);
+        assert_eq!(collect("[1] 2"), [Ok(U64(1)), Err(SyntaxError(TrailingCharacters, 1, 5))]);
+        assert_eq!(collect("{\"a\": 1}"), [Err(SyntaxError(InvalidSyntax, 1, 1))]);
+        assert_eq!(collect("  1"), [Err(SyntaxError(InvalidSyntax, 1, 3))]);
+        assert_eq!(collect(""), [Err(SyntaxError(EOFWhileParsingValue, 1, 1))]);
+        assert_eq!(collect("["), [Err(SyntaxError(EOFWhileParsingValue, 1, 2))]);
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        let json = Json::from_str("{\"a\": [1, -2.5, null], \"b\": \"caf\\u00e9\"}").unwrap();
+        assert_eq!(json.to_bytes(), json.to_string().into_bytes());
+        assert_eq!(json.to_pretty_bytes(), json.pretty().to_string().into_bytes());
+        assert_eq!(Json::from_str(str::from_utf8(&json.to_bytes()).unwrap()).unwrap(), json);
+        assert_eq!(Null.to_bytes(), b"null");
+        assert_eq!(F64(f64::NAN).to_bytes(), b"null");
+        assert_eq!(Array(vec![]).to_pretty_bytes(), b"[]");
+    }
+
+    #[test]
+    fn test_escaped_keys() {
+        struct Odd;
+
+        impl Encodable for Odd {
+            fn encode<S: ::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+                s.emit_struct("Odd", 1, |s| {
+                    s.emit_struct_field("say \"hi\"\n", 0, |s| s.emit_str("say \"hi\"\n"))
+                })
+            }
+        }
+
+        let key = "say \"hi\"\n";
+        let escaped = format!("\"{}\"", super::escape_str(key));
+        assert_eq!(super::encode(&Odd).unwrap(), format!("{{{0}:{0}}}", escaped));
+        assert_eq!(format!("{}", super::as_pretty_json(&Odd)),
+                   format!("{{\n  {0}: {0}\n}}", escaped));
+
+        let mut map = BTreeMap::new();
+        map.insert(key.to_string(), key.to_string());
+        let s = super::encode(&map).unwrap();
+        assert_eq!(s, format!("{{{0}:{0}}}", escaped));
+        assert_eq!(Json::from_str(&s).unwrap().to_string(), s);
+        assert_eq!(Json::from_str(&s).unwrap().pretty().to_string(),
+                   format!("{{\n  {0}: {0}\n}}", escaped));
+        assert_eq!(super::decode::<BTreeMap<string::String, string::String>>(&s).unwrap(), map);
+    }
+
+    #[test]
+    fn test_minimal_escaping() {
+        fn encode_minimal<T: Encodable>(t: &T) -> string::String {
+            let mut s = string::String::new();
+            {
+                let mut encoder = Encoder::new(&mut s);
+                encoder.set_minimal_escaping(true);
+                t.encode(&mut encoder).unwrap();
+            }
+            s
+        }
+
+        let s = "a/b caf\u{e9} \u{1f600} \u{7f} \"\\\n\u{1}\u{1f}";
+        assert_eq!(encode_minimal(&s),
+                   "\"a/b caf\u{e9} \u{1f600} \u{7f} \\\"\\\\\\n\\u0001\\u001f\"");
+        assert_eq!(super::encode(&s).unwrap(),
+                   "\"a/b caf\u{e9} \u{1f600} \\u007f \\\"\\\\\\n\\u0001\\u001f\"");
+        assert_eq!(encode_minimal(&'\u{7f}'), "\"\u{7f}\"");
+
+        // keys are written the same way
+        let mut map = BTreeMap::new();
+        map.insert("/\u{7f}".to_string(), 1);
+        assert_eq!(encode_minimal(&map), "{\"/\u{7f}\":1}");
+
+        // and it all reads back
+        assert_eq!(super::decode::<string::String>(&encode_minimal(&s)).unwrap(), s);
+    }
 
-        assert_eq!(F64(f64::NAN).to_string(), "null");
-        assert_eq!(F64(f64::NAN).pretty().to_string(), "null");
+    #[test]
+    fn test_escape_str() {
+        use super::{escape_str, unescape_str};
 
-        assert_eq!(F64(f64::INFINITY).to_string(), "null");
-        assert_eq!(F64(f64::INFINITY).pretty().to_string(), "null");
+        for s in &["", "foo", "a\"b\\c/d", "\x00\x1f\x7f\t\r\n", "caf\u{e9} \u{1f600}"] {
+            assert_eq!(format!("\"{}\"", escape_str(s)), super::encode(s).unwrap());
+            assert_eq!(unescape_str(&escape_str(s)).unwrap(), *s);
+        }
+        assert_eq!(unescape_str("\\/\\b\\f\\u0041").unwrap(), "/\x08\x0cA");
+        assert_eq!(unescape_str("\\ud83d\\ude00").unwrap(), "\u{1f600}");
 
-        assert_eq!(F64(f64::NEG_INFINITY).to_string(), "null");
-        assert_eq!(F64(f64::NEG_INFINITY).pretty().to_string(), "null");
+        assert_eq!(unescape_str("ab\\q"), Err(SyntaxError(InvalidEscape, 1, 4)));
+        assert_eq!(unescape_str("\\ude00"), Err(SyntaxError(LoneSurrogate, 1, 6)));
+        assert_eq!(unescape_str("\\ud83d"), Err(SyntaxError(LoneSurrogate, 1, 8)));
+        assert_eq!(unescape_str("a\"b"), Err(SyntaxError(InvalidSyntax, 1, 3)));
+        assert_eq!(unescape_str("a\nb"), Err(SyntaxError(ControlCharacterInString, 2, 1)));
+        assert_eq!(unescape_str("a\\"), Err(SyntaxError(EOFWhileParsingString, 1, 4)));
     }
 
     #[test]
@@ -2887,6 +7857,67 @@ mod tests {
         check_encoder_for_simple!('\u{10ffff}', "\"\u{10ffff}\"");
     }
 
+    #[test]
+    fn test_minify() {
+        use super::minify;
+
+        // Examples from RFC 8259, section 13.
+        let image = "
+            {
+                \"Image\": {
+                    \"Width\":  800,
+                    \"Height\": 600,
+                    \"Title\":  \"View from 15th Floor\",
+                    \"Thumbnail\": {
+                        \"Url\":    \"http://www.example.com/image/481989943\",
+                        \"Height\": 125,
+                        \"Width\":  100
+                    },
+                    \"Animated\" : false,
+                    \"IDs\": [116, 943, 234, 38793]
+                }
+            }";
+        assert_eq!(minify(image).unwrap(),
+                   "{\"Image\":{\"Width\":800,\"Height\":600,\
+                    \"Title\":\"View from 15th Floor\",\"Thumbnail\":{\
+                    \"Url\":\"http://www.example.com/image/481989943\",\
+                    \"Height\":125,\"Width\":100},\"Animated\":false,\
+                    \"IDs\":[116,943,234,38793]}}");
+
+        let places = "
+            [
+                {
+                    \"precision\": \"zip\",
+                    \"Latitude\":  37.7668,
+                    \"Longitude\": -122.3959,
+                    \"Address\":   \"\",
+                    \"City\":      \"SAN FRANCISCO\",
+                    \"State\":     \"CA\",
+                    \"Zip\":       \"94107\",
+                    \"Country\":   \"US\"
+                }
+            ]";
+        assert_eq!(minify(places).unwrap(),
+                   "[{\"precision\":\"zip\",\"Latitude\":37.7668,\
+                    \"Longitude\":-122.3959,\"Address\":\"\",\
+                    \"City\":\"SAN FRANCISCO\",\"State\":\"CA\",\
+                    \"Zip\":\"94107\",\"Country\":\"US\"}]");
+
+        assert_eq!(minify(" \"Hello world!\" ").unwrap(), "\"Hello world!\"");
+        assert_eq!(minify("\t42\r\n").unwrap(), "42");
+        assert_eq!(minify("true").unwrap(), "true");
+        assert_eq!(minify("[ \"a \\\" b\" , 1E+2 ]").unwrap(), "[\"a \\\" b\",1E+2]");
+
+        assert_eq!(minify(""), Err(SyntaxError(EOFWhileParsingValue, 1, 1)));
+        assert_eq!(minify("[1, 2"), Err(SyntaxError(EOFWhileParsingArray, 1, 6)));
+        assert_eq!(minify("{\"a\" 1}"), Err(SyntaxError(ExpectedColon, 1, 6)));
+        assert_eq!(minify("{'a': 1}"), Err(SyntaxError(KeyMustBeAString, 1, 2)));
+        assert_eq!(minify("[1,]"), Err(SyntaxError(InvalidSyntax, 1, 4)));
+        assert_eq!(minify("01"), Err(SyntaxError(InvalidNumber, 1, 2)));
+        assert_eq!(minify("\"tab\there\""), Err(SyntaxError(ControlCharacterInString, 1, 5)));
+        assert_eq!(minify("[] []"), Err(SyntaxError(TrailingCharacters, 1, 4)));
+    }
+
     #[test]
     fn test_trailing_characters() {
         assert_eq!(Json::from_str("nulla"),  Err(SyntaxError(TrailingCharacters, 1, 5)));
@@ -2951,11 +7982,403 @@ mod tests {
         assert_eq!(Json::from_str("123456789.5024"), Ok(F64(123456789.5024)));
         assert_eq!(Json::from_str(" 3 "), Ok(U64(3)));
 
+        // rounded once, to the closest `f64`
+        assert_eq!(Json::from_str("0.75"), Ok(F64(0.75)));
+        assert_eq!(Json::from_str("0.30000000000000004"), Ok(F64(0.1 + 0.2)));
+        assert_eq!(Json::from_str("1.7976931348623157e308"), Ok(F64(f64::MAX)));
+        assert_eq!(Json::from_str("2.2250738585072014e-308"), Ok(F64(f64::MIN_POSITIVE)));
+        assert_eq!(Json::from_str("5e-324"), Ok(F64(5e-324)));
+        assert_eq!(Json::from_str("1e-400"), Ok(F64(0.0)));
+        // out of range, rather than infinite
+        assert_eq!(Json::from_str("1e400"), Err(SyntaxError(InvalidNumber, 1, 6)));
+        assert_eq!(Json::from_str("-1.8e308"), Err(SyntaxError(InvalidNumber, 1, 9)));
+
+        assert_eq!(Json::from_str("-0"), Ok(U64(0)));
+        assert_eq!(Json::from_str("-0.0"), Ok(F64(-0.0)));
+
         assert_eq!(Json::from_str("-9223372036854775808"), Ok(I64(i64::MIN)));
         assert_eq!(Json::from_str("9223372036854775807"), Ok(U64(i64::MAX as u64)));
         assert_eq!(Json::from_str("18446744073709551615"), Ok(U64(u64::MAX)));
     }
 
+    #[test]
+    fn test_read_number_relaxed() {
+        fn relaxed(s: &str) -> Result<Json, BuilderError> {
+            let mut builder = Builder::new(s.chars());
+            builder.set_relaxed(true);
+            builder.build()
+        }
+
+        // leading `+`
+        assert_eq!(relaxed("+1"), Ok(U64(1)));
+        assert_eq!(relaxed("+1.5"), Ok(F64(1.5)));
+        assert_eq!(relaxed("+0.4e1"), Ok(F64(4.0)));
+        // no digits before the decimal point
+        assert_eq!(relaxed(".5"), Ok(F64(0.5)));
+        assert_eq!(relaxed("-.5"), Ok(F64(-0.5)));
+        assert_eq!(relaxed("+.5e1"), Ok(F64(5.0)));
+        // no digits after it
+        assert_eq!(relaxed("5."), Ok(F64(5.0)));
+        assert_eq!(relaxed("-5."), Ok(F64(-5.0)));
+        assert_eq!(relaxed("5.e2"), Ok(F64(500.0)));
+        assert_eq!(relaxed("[+1,.5,5.]"), Ok(Array(vec![U64(1), F64(0.5), F64(5.0)])));
+
+        // still errors
+        assert_eq!(relaxed("."), Err(SyntaxError(InvalidNumber, 1, 2)));
+        assert_eq!(relaxed("+"), Err(SyntaxError(InvalidNumber, 1, 2)));
+        assert_eq!(relaxed("+."), Err(SyntaxError(InvalidNumber, 1, 3)));
+        assert_eq!(relaxed("-."), Err(SyntaxError(InvalidNumber, 1, 3)));
+        assert_eq!(relaxed("+-1"), Err(SyntaxError(InvalidNumber, 1, 2)));
+        assert_eq!(relaxed("++1"), Err(SyntaxError(InvalidNumber, 1, 2)));
+        assert_eq!(relaxed("01"), Err(SyntaxError(InvalidNumber, 1, 2)));
+        assert_eq!(relaxed("5.e"), Err(SyntaxError(InvalidNumber, 1, 4)));
+
+        // and strict mode rejects all of them
+        for &s in ["+1", "+1.5", ".5", "-.5", "5.", "5.e2"].iter() {
+            assert!(Json::from_str(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_case_insensitive_literals() {
+        use super::{from_str_with_config, DecodeConfig};
+
+        fn relaxed(s: &str) -> Result<Json, BuilderError> {
+            let mut builder = Builder::new(s.chars());
+            builder.set_case_insensitive_literals(true);
+            builder.build()
+        }
+
+        for &s in ["true", "True", "TRUE", "tRuE"].iter() {
+            assert_eq!(relaxed(s), Ok(Boolean(true)));
+        }
+        for &s in ["false", "False", "FALSE", "fAlSe"].iter() {
+            assert_eq!(relaxed(s), Ok(Boolean(false)));
+        }
+        for &s in ["null", "Null", "NULL", "nUlL"].iter() {
+            assert_eq!(relaxed(s), Ok(Null));
+        }
+        assert_eq!(relaxed("[TRUE, {\"a\": NULL}]"),
+                   Json::from_str("[true, {\"a\": null}]"));
+
+        // strings are left alone, and misspellings are still errors
+        assert_eq!(relaxed("\"TRUE\""), Ok(String("TRUE".to_string())));
+        assert_eq!(relaxed("NUL"), Err(SyntaxError(InvalidSyntax, 1, 4)));
+        assert_eq!(relaxed("Tru"), Err(SyntaxError(InvalidSyntax, 1, 4)));
+        assert_eq!(relaxed("TRUEE"), Err(SyntaxError(TrailingCharacters, 1, 5)));
+        assert_eq!(relaxed("Nan"), Err(SyntaxError(InvalidSyntax, 1, 2)));
+
+        let config = DecodeConfig::new().case_insensitive_literals(true);
+        assert_eq!(from_str_with_config::<Vec<Option<bool>>>("[True, NULL]", &config).unwrap(),
+                   [Some(true), None]);
+
+        // and strict mode rejects all but lower case
+        for &s in ["True", "TRUE", "tRUE", "False", "NULL", "Null", "nuLL"].iter() {
+            assert!(Json::from_str(s).is_err(), "{}", s);
+            assert!(from_str_with_config::<Option<bool>>(s, &DecodeConfig::new()).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_quote_large_integers() {
+        fn encode<T: Encodable>(t: &T) -> string::String {
+            let mut s = string::String::new();
+            {
+                let mut encoder = Encoder::new(&mut s);
+                encoder.set_quote_large_integers(true);
+                t.encode(&mut encoder).unwrap();
+            }
+            s
+        }
+
+        assert_eq!(encode(&9007199254740991u64), "9007199254740991");
+        assert_eq!(encode(&9007199254740992u64), "\"9007199254740992\"");
+        assert_eq!(encode(&-9007199254740991i64), "-9007199254740991");
+        assert_eq!(encode(&i64::MIN), "\"-9223372036854775808\"");
+        assert_eq!(encode(&u32::MAX), "4294967295");
+        let s = encode(&vec![u64::MAX, 1]);
+        assert_eq!(s, "[\"18446744073709551615\",1]");
+        assert_eq!(super::decode::<Vec<u64>>(&s).unwrap(), [u64::MAX, 1]);
+
+        let mut map = BTreeMap::new();
+        map.insert(u64::MAX, u64::MAX);
+        assert_eq!(encode(&map), "{\"18446744073709551615\":\"18446744073709551615\"}");
+    }
+
+    #[test]
+    fn test_error_chain() {
+        use std::error::Error;
+        use std::io;
+
+        fn parse(s: &str) -> Result<Json, Box<Error>> {
+            Ok(try!(Json::from_str(s)))
+        }
+        assert_eq!(parse("[").unwrap_err().to_string(),
+                   "EOF While parsing value at line 1 column 2");
+        assert_eq!(EOFWhileParsingArray.to_string(), "EOF While parsing array");
+
+        let err = ParseError(IoError(io::Error::new(io::ErrorKind::Other, "gone")));
+        assert_eq!(err.cause().and_then(|e| e.cause()).unwrap().to_string(), "gone");
+    }
+
+    #[test]
+    #[cfg(feature = "error-source")]
+    fn test_error_source_chain() {
+        use std::error::Error;
+        use std::io;
+
+        let err = ParseError(IoError(io::Error::new(io::ErrorKind::Other, "gone")));
+        assert_eq!(err.source().and_then(|e| e.source()).unwrap().to_string(), "gone");
+        let err = EncoderError::FmtError(::std::fmt::Error);
+        assert!(err.source().is_some());
+        assert!(EncoderError::BadHashmapKey.source().is_none());
+    }
+
+    #[test]
+    fn test_ndjson() {
+        use super::ndjson::{encode_ndjson, decode_ndjson, NdjsonError, NdjsonReader};
+        use std::io::{self, Read};
+
+        let text = encode_ndjson(&["a\nb", "c"]).unwrap();
+        assert_eq!(text, "\"a\\nb\"\n\"c\"\n");
+        assert_eq!(decode_ndjson::<string::String>(&text).unwrap(), ["a\nb", "c"]);
+        assert_eq!(encode_ndjson::<u8>(&[]).unwrap(), "");
+        let mut bad = ::std::collections::HashMap::new();
+        bad.insert(vec![1u8], 2u8);
+        assert_eq!(encode_ndjson(&[bad]), Err(super::EncoderError::BadHashmapKey));
+
+        assert_eq!(decode_ndjson::<u32>("1\r\n\n  \n2\n3").unwrap(), [1, 2, 3]);
+        assert_eq!(decode_ndjson::<u32>("1\n\n\"x\"\n3"),
+                   Err(NdjsonError::DecodeError(3, ExpectedError("Number".to_string(),
+                                                                 "x".to_string()))));
+        match decode_ndjson::<u32>("1\n[2") {
+            Err(NdjsonError::DecodeError(2, ParseError(SyntaxError(EOFWhileParsingArray, _, _)))) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let reader = NdjsonReader::<_, u32>::new("1\nfalse\n\n3\n".as_bytes());
+        assert_eq!(reader.collect::<Vec<_>>(),
+                   [Ok(1),
+                    Err(NdjsonError::DecodeError(2, ExpectedError("Number".to_string(),
+                                                                  "false".to_string()))),
+                    Ok(3)]);
+
+        // values are read as they are asked for
+        struct Lines<'a>(&'a [&'a str]);
+        impl<'a> Read for Lines<'a> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let lines = self.0;
+                match lines.first() {
+                    Some(line) => {
+                        self.0 = &lines[1..];
+                        for (b, &c) in buf.iter_mut().zip(line.as_bytes()) {
+                            *b = c;
+                        }
+                        Ok(line.len())
+                    }
+                    None => Err(io::Error::new(io::ErrorKind::Other, "gone")),
+                }
+            }
+        }
+        let mut reader = NdjsonReader::<_, u32>::new(Lines(&["1\n", "2\n"]));
+        assert_eq!(reader.next().unwrap().unwrap(), 1);
+        assert_eq!(reader.next().unwrap().unwrap(), 2);
+        match reader.next() {
+            Some(Err(NdjsonError::IoError(ref e))) if e.kind() == io::ErrorKind::Other => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_read_write_lines() {
+        use super::{read_lines, write_lines};
+        use std::collections::HashMap;
+        use std::io;
+
+        let mut out = Vec::new();
+        write_lines(&mut out, vec![vec!["a\nb"], vec![]]).unwrap();
+        assert_eq!(out, &b"[\"a\\nb\"]\n[]\n"[..]);
+        let lines = read_lines(&out[..]).collect::<Vec<_>>();
+        assert_eq!(lines, [Ok(Array(vec![String("a\nb".to_string())])), Ok(Array(vec![]))]);
+
+        let mut out = Vec::new();
+        write_lines(&mut out, &[] as &[u8]).unwrap();
+        assert!(out.is_empty());
+
+        // a value which fails to encode stops the writing after the lines
+        // before it
+        let mut bad = ::std::collections::HashMap::new();
+        bad.insert(vec![1], 2);
+        let mut out = Vec::new();
+        let err = write_lines(&mut out, vec![HashMap::new(), bad.clone(), HashMap::new()]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(out, b"{}\n");
+
+        // blank lines are skipped, errors are reported at their line in the
+        // input and don't end the iteration
+        let input = b"1\r\n\n  \n[2,\ntrue\n\xff\n{\"a\": x}\nnull";
+        assert_eq!(read_lines(&input[..]).collect::<Vec<_>>(),
+                   [Ok(U64(1)),
+                    Err(SyntaxError(EOFWhileParsingValue, 4, 4)),
+                    Ok(Boolean(true)),
+                    Err(SyntaxError(NotUtf8, 6, 0)),
+                    Err(SyntaxError(InvalidSyntax, 7, 7)),
+                    Ok(Null)]);
+        assert!(read_lines(&b""[..]).next().is_none());
+
+        // a failure to read ends the iteration
+        struct Gone(bool);
+        impl io::Read for Gone {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.0 {
+                    return Err(io::Error::new(io::ErrorKind::Other, "gone"));
+                }
+                self.0 = true;
+                buf[0] = b'1';
+                buf[1] = b'\n';
+                Ok(2)
+            }
+        }
+        let mut lines = read_lines(Gone(false));
+        assert_eq!(lines.next(), Some(Ok(U64(1))));
+        match lines.next() {
+            Some(Err(IoError(ref e))) if e.kind() == io::ErrorKind::Other => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "i128")]
+    fn test_128_bit_integers() {
+        use std::{i128, u128};
+        use super::ToJson;
+
+        // numbers as long as 64 bits hold them, strings beyond that
+        let max = u128::MAX;
+        assert_eq!(super::encode(&max).unwrap(), "\"340282366920938463463374607431768211455\"");
+        assert_eq!(super::decode::<u128>(&super::encode(&max).unwrap()).unwrap(), max);
+        assert_eq!(super::encode(&i128::MIN).unwrap(),
+                   "\"-170141183460469231731687303715884105728\"");
+        assert_eq!(super::decode::<i128>(&super::encode(&i128::MIN).unwrap()).unwrap(),
+                   i128::MIN);
+        assert_eq!(super::encode(&(u64::MAX as u128)).unwrap(), "18446744073709551615");
+        assert_eq!(super::encode(&(u64::MAX as i128)).unwrap(), "18446744073709551615");
+        assert_eq!(super::encode(&(i64::MIN as i128)).unwrap(), "-9223372036854775808");
+        assert_eq!(super::decode::<i128>("3").unwrap(), 3);
+        assert_eq!(super::decode::<i128>("-3").unwrap(), -3);
+        assert_eq!(super::decode::<u128>("18446744073709551615").unwrap(), u64::MAX as u128);
+        assert_eq!(super::decode::<u128>("\"12\"").unwrap(), 12);
+        assert_eq!((5u8 as u128).to_json(), U64(5));
+        assert_eq!((-5i8 as i128).to_json(), I64(-5));
+        assert_eq!(max.to_json(), String("340282366920938463463374607431768211455".to_string()));
+
+        // as map keys and through the in-memory encoder
+        let mut map = BTreeMap::new();
+        map.insert(max, i128::MIN);
+        let s = "{\"340282366920938463463374607431768211455\":\
+                 \"-170141183460469231731687303715884105728\"}";
+        assert_eq!(super::encode(&map).unwrap(), s);
+        assert_eq!(super::to_string_sorted(&map).unwrap(), s);
+        assert_eq!(super::decode::<BTreeMap<u128, i128>>(s).unwrap(), map);
+
+        assert!(super::decode::<u128>("-1").is_err());
+        assert!(super::decode::<u128>("340282366920938463463374607431768211455").is_err());
+        assert!(super::decode::<i128>("\"340282366920938463463374607431768211455\"").is_err());
+
+        // quoted for readers which would round them to an f64
+        let mut s = string::String::new();
+        {
+            let mut encoder = Encoder::new(&mut s);
+            encoder.set_quote_large_integers(true);
+            // no 128-bit literals, which compilers before 128-bit integers can't lex
+            let (quoted, unquoted) = (1u64 << 53, -(1i64 << 53) + 1);
+            (max, i128::MIN, quoted as u128, unquoted as i128).encode(&mut encoder).unwrap();
+        }
+        assert_eq!(s, "[\"340282366920938463463374607431768211455\",\
+                        \"-170141183460469231731687303715884105728\",\
+                        \"9007199254740992\",-9007199254740991]");
+        assert_eq!(super::decode::<(u128, i128, u128, i128)>(&s).unwrap(),
+                   (max, i128::MIN, (1u64 << 53) as u128, (-(1i64 << 53) + 1) as i128));
+    }
+
+    #[test]
+    fn test_lone_surrogates() {
+        fn build(s: &str, replace: bool) -> Result<Json, BuilderError> {
+            let mut builder = Builder::new(s.chars());
+            builder.set_replace_lone_surrogates(replace);
+            builder.build()
+        }
+
+        let cases = [
+            (r#""\ud800""#, "\u{fffd}", 9),
+            (r#""\udc00""#, "\u{fffd}", 7),
+            (r#""a\ud800b""#, "a\u{fffd}b", 9),
+            (r#""\ud800\n""#, "\u{fffd}\n", 9),
+            (r#""\ud800A""#, "\u{fffd}A", 8),
+            (r#""\ud800\u0041""#, "\u{fffd}A", 13),
+            (r#""\ud800\ud800""#, "\u{fffd}\u{fffd}", 13),
+            (r#""\udc00𐀀""#, "\u{fffd}\u{10000}", 7),
+            (r#""\ud800𐀀""#, "\u{fffd}\u{10000}", 8),
+        ];
+        for &(s, replaced, col) in cases.iter() {
+            assert_eq!(build(s, false), Err(SyntaxError(LoneSurrogate, 1, col)));
+            assert_eq!(build(s, true), Ok(String(replaced.to_string())));
+        }
+        assert_eq!(build(r#""😀""#, false), Ok(String("\u{1f600}".to_string())));
+
+        let mut parser = Parser::new(r#"["\udfff", 1]"#.chars());
+        parser.set_replace_lone_surrogates(true);
+        assert_eq!(parser.nth(1), Some(StringValue("\u{fffd}".to_string())));
+    }
+
+    #[test]
+    fn test_max_number_len() {
+        use std::iter::repeat;
+
+        fn digits(d: &str, n: usize) -> string::String {
+            repeat(d).take(n).collect()
+        }
+
+        let long = format!("0.{}", digits("1", 64));
+        assert!(Json::from_str(&long).unwrap().is_f64());
+        assert_eq!(Json::from_str(&format!("[{}1]", long)),
+                   Err(SyntaxError(NumberTooLong, 1, 69)));
+        let huge = format!("1{}.0", digits("1", 1000000));
+        assert_eq!(Json::from_str(&huge), Err(SyntaxError(NumberTooLong, 1, 66)));
+
+        // signs, decimal points and zeros around the other digits don't count
+        let zeros = digits("0", 1000);
+        let padded = format!("-0.{}{}{}", zeros, digits("1", 64), zeros);
+        assert!(Json::from_str(&padded).unwrap().is_f64());
+        let padded = format!("[1{}.0, 1.{}e-{}5{}]", &zeros[..300], digits("1", 62), zeros, zeros);
+        assert!(Json::from_str(&padded).is_ok());
+
+        // the digits of the exponent do
+        let mut builder = Builder::new("[1.5e123]".chars());
+        builder.set_max_number_len(5);
+        assert_eq!(builder.build(), Ok(Array(vec![F64(1.5e123)])));
+        let mut builder = Builder::new("[1.25e123]".chars());
+        builder.set_max_number_len(5);
+        assert_eq!(builder.build(), Err(SyntaxError(NumberTooLong, 1, 10)));
+
+        let mut parser = Parser::new("-123".chars());
+        parser.set_max_number_len(2);
+        assert_eq!(parser.next(), Some(Error(SyntaxError(NumberTooLong, 1, 5))));
+    }
+
+    #[test]
+    fn test_encoded_floats_parse_back() {
+        let values = [f64::MIN_POSITIVE, -f64::MIN_POSITIVE, 5e-324, 1e300, 1e-300,
+                      1.2345678901234567e-300, 1e20, f64::MAX, f64::MIN, 0.1];
+        for &value in values.iter() {
+            let encoded = super::encode(&value).unwrap();
+            assert!(Json::from_str(&encoded) == Ok(F64(value)), "{}", encoded);
+            assert_eq!(super::decode::<f64>(&encoded), Ok(value));
+        }
+    }
+
     #[test]
     fn test_decode_numbers() {
         let v: f64 = super::decode("3").unwrap();
@@ -3048,6 +8471,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_encode_decode_char() {
+        use std::collections::BTreeMap;
+
+        for &c in ['a', '"', '\u{7f}', '\u{e9}', '\u{ffff}', '\u{1d54f}'].iter() {
+            let s = super::encode(&c).unwrap();
+            assert_eq!(super::decode::<char>(&s).unwrap(), c);
+        }
+        assert_eq!(super::encode(&'\u{1d54f}').unwrap(), "\"\u{1d54f}\"");
+
+        // astral-plane chars may also arrive as a surrogate pair
+        assert_eq!(super::decode::<char>("\"\\ud835\\udd4f\"").unwrap(), '\u{1d54f}');
+
+        let mut map = BTreeMap::new();
+        map.insert('\u{1d54f}', 'x');
+        let s = super::encode(&map).unwrap();
+        assert_eq!(s, "{\"\u{1d54f}\":\"x\"}");
+        assert_eq!(super::decode::<BTreeMap<char, char>>(&s).unwrap(), map);
+
+        for &s in ["\"\"", "\"ab\"", "\"\\ud835\\udd4f\\ud835\\udd4f\""].iter() {
+            match super::decode::<char>(s) {
+                Err(ExpectedError(ref exp, _)) => assert_eq!(exp, "single character string"),
+                other => panic!("unexpected result {:?} for {}", other, s),
+            }
+        }
+        assert_eq!(super::decode::<char>("120"),
+                   Err(ExpectedError("String".to_string(), "120".to_string())));
+        assert_eq!(super::decode::<char>("\"\\ud835\""),
+                   Err(ParseError(SyntaxError(LoneSurrogate, 1, 9))));
+    }
+
     #[test]
     fn test_read_array() {
         assert_eq!(Json::from_str("["),     Err(SyntaxError(EOFWhileParsingValue, 1, 2)));
@@ -3087,6 +8541,137 @@ mod tests {
         assert_eq!(v, vec![vec![3], vec![1, 2]]);
     }
 
+    #[test]
+    fn test_encode_decode_linked_list() {
+        use std::collections::LinkedList;
+
+        let empty: LinkedList<u32> = LinkedList::new();
+        assert_eq!(super::encode(&empty).unwrap(), "[]");
+        let l: LinkedList<u32> = super::decode("[]").unwrap();
+        assert_eq!(l, empty);
+
+        let mut single = LinkedList::new();
+        single.push_back("a".to_string());
+        assert_eq!(super::encode(&single).unwrap(), "[\"a\"]");
+        let l: LinkedList<string::String> = super::decode("[\"a\"]").unwrap();
+        assert_eq!(l, single);
+
+        let long: LinkedList<u32> = (0..1000).collect();
+        let s = super::encode(&long).unwrap();
+        let l: LinkedList<u32> = super::decode(&s).unwrap();
+        assert_eq!(l, long);
+        assert!(l.iter().cloned().eq(0..1000));
+    }
+
+    #[test]
+    fn test_encode_decode_sets() {
+        use std::collections::{BTreeSet, HashSet};
+
+        let set: BTreeSet<u32> = [3, 1, 2].iter().cloned().collect();
+        assert_eq!(super::encode(&set).unwrap(), "[1,2,3]");
+
+        // Duplicates collapse into a single element.
+        let set: BTreeSet<u32> = super::decode("[2, 1, 2]").unwrap();
+        assert_eq!(set.into_iter().collect::<Vec<_>>(), vec![1, 2]);
+        let set: HashSet<u32> = super::decode("[2, 1, 2]").unwrap();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn test_encode_decode_btreemap() {
+        let mut map = BTreeMap::new();
+        map.insert("b".to_string(), vec![2u32]);
+        map.insert("a".to_string(), vec![]);
+        let s = super::encode(&map).unwrap();
+        assert_eq!(s, "{\"a\":[],\"b\":[2]}");
+        assert_eq!(super::decode::<BTreeMap<string::String, Vec<u32>>>(&s).unwrap(), map);
+
+        // keys which aren't strings are written as strings
+        let mut map = BTreeMap::new();
+        map.insert(10u32, true);
+        map.insert(2, false);
+        let s = super::encode(&map).unwrap();
+        assert_eq!(s, "{\"2\":false,\"10\":true}");
+        assert_eq!(super::decode::<BTreeMap<u32, bool>>(&s).unwrap(), map);
+    }
+
+    #[test]
+    fn test_encode_decode_random_sets() {
+        use std::collections::{BTreeSet, HashSet};
+        use rand::{thread_rng, Rng};
+
+        for _ in 0..100 {
+            let times = thread_rng().gen_range(0, 100);
+            let v = thread_rng().gen_iter::<u32>().take(times).collect::<Vec<_>>();
+
+            let set: BTreeSet<u32> = v.iter().cloned().collect();
+            let s = super::encode(&set).unwrap();
+            assert_eq!(super::decode::<BTreeSet<u32>>(&s).unwrap(), set);
+
+            let set: HashSet<u32> = v.iter().cloned().collect();
+            let s = super::encode(&set).unwrap();
+            assert_eq!(super::decode::<HashSet<u32>>(&s).unwrap(), set);
+        }
+    }
+
+    // Generates values which survive a trip through the encoder and parser:
+    // non-negative integers only come back as `U64`, and non-finite floats
+    // encode as `null`.
+    impl ::quickcheck::Arbitrary for Json {
+        fn arbitrary<G: ::quickcheck::Gen>(g: &mut G) -> Json {
+            fn arbitrary_json<G: ::quickcheck::Gen>(g: &mut G, depth: usize) -> Json {
+                use quickcheck::Arbitrary;
+                use rand::Rng;
+
+                let kinds = if depth == 0 { 6 } else { 8 };
+                match g.gen_range(0, kinds) {
+                    0 => Null,
+                    1 => Boolean(Arbitrary::arbitrary(g)),
+                    2 => U64(Arbitrary::arbitrary(g)),
+                    3 => {
+                        let n: i64 = Arbitrary::arbitrary(g);
+                        if n < 0 { I64(n) } else { U64(n as u64) }
+                    }
+                    4 => {
+                        // Half the time any bit pattern, for the extremes and
+                        // subnormals which small values never reach.
+                        let f: f64 = if g.gen() {
+                            Arbitrary::arbitrary(g)
+                        } else {
+                            unsafe { ::std::mem::transmute::<u64, f64>(g.gen()) }
+                        };
+                        if f.is_finite() { F64(f) } else { Null }
+                    }
+                    5 => String(Arbitrary::arbitrary(g)),
+                    6 => {
+                        let len = g.gen_range(0, 5);
+                        Array((0..len).map(|_| arbitrary_json(g, depth - 1)).collect())
+                    }
+                    _ => {
+                        let len = g.gen_range(0, 5);
+                        Object((0..len).map(|_| {
+                            (Arbitrary::arbitrary(g), arbitrary_json(g, depth - 1))
+                        }).collect())
+                    }
+                }
+            }
+            arbitrary_json(g, 3)
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_arbitrary_json() {
+        use quickcheck::quickcheck;
+
+        fn prop(json: Json) -> bool {
+            let compact = super::encode(&json).unwrap();
+            let pretty = json.pretty().to_string();
+            Json::from_str(&compact).ok() == Some(json.clone()) &&
+                Json::from_str(&pretty).ok() == Some(json)
+        }
+        quickcheck(prop as fn(Json) -> bool);
+    }
+
     #[test]
     fn test_decode_tuple() {
         let t: (usize, usize, usize) = super::decode("[1, 2, 3]").unwrap();
@@ -3106,6 +8691,32 @@ mod tests {
         assert!(super::decode::<(usize, usize)>("[1, 2, 3]").is_err());
     }
 
+    #[test]
+    fn test_encode_decode_tuple16() {
+        type T16 = (u8, i16, u32, i64, f64, bool, char, string::String,
+                    Option<u8>, Vec<u8>, (), (u8,), usize, isize, f32,
+                    Option<string::String>);
+        let t: T16 = (1, -2, 3, -4, 5.5, true, 'x', "eight".to_string(),
+                      None, vec![10], (), (12,), 13, -14, 15.5,
+                      Some("sixteen".to_string()));
+        let s = super::encode(&t).unwrap();
+        assert_eq!(s, "[1,-2,3,-4,5.5,true,\"x\",\"eight\",null,[10],null,[12],13,-14,15.5,\"sixteen\"]");
+
+        // the std traits stop at 12-tuples, so compare by re-encoding
+        let d: T16 = super::decode(&s).unwrap();
+        assert_eq!(d.7, "eight");
+        assert_eq!(d.15, Some("sixteen".to_string()));
+        assert_eq!(super::encode(&d).unwrap(), s);
+
+        let short = "[1,-2,3,-4,5.5,true,\"x\",\"eight\",null,[10],null,[12],13,-14,15.5]";
+        match super::decode::<T16>(short) {
+            Err(ExpectedError(ref exp, ref found)) => {
+                assert_eq!((&exp[..], &found[..]), ("Tuple16", "Tuple15"));
+            }
+            _ => panic!("expected a length error"),
+        }
+    }
+
     #[test]
     fn test_read_object() {
         assert_eq!(Json::from_str("{"),       Err(SyntaxError(EOFWhileParsingObject, 1, 2)));
@@ -3180,11 +8791,122 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_from_str_with_config() {
+        use super::{from_str_with_config, DecodeConfig};
+
+        fn decode<T: ::Decodable>(s: &str, config: DecodeConfig) -> Result<T, DecoderError> {
+            from_str_with_config(s, &config)
+        }
+        fn error(e: Result<Json, DecoderError>) -> DecoderError {
+            e.unwrap_err()
+        }
+
+        // the defaults are those of `decode`
+        let s = "{\"a\": [1, {\"b\": [2]}], \"a\": []}";
+        assert_eq!(decode::<Json>(s, DecodeConfig::new()), super::decode::<Json>(s));
+        assert_eq!(error(decode("[1,]", DecodeConfig::new())),
+                   ParseError(SyntaxError(InvalidSyntax, 1, 4)));
+
+        // max_depth
+        let config = DecodeConfig::new().max_depth(3);
+        assert!(decode::<Json>("[1, {\"b\": [2]}]", config.clone()).is_ok());
+        assert_eq!(error(decode("[1, {\"b\": [[2]]}]", config.clone())),
+                   ParseError(SyntaxError(NestingTooDeep, 1, 12)));
+        assert_eq!(error(decode("{}", DecodeConfig::new().max_depth(0))),
+                   ParseError(SyntaxError(NestingTooDeep, 1, 1)));
+
+        // max_bytes, checked before parsing
+        let config = DecodeConfig::new().max_bytes(6);
+        assert_eq!(decode::<Json>("[1, 2]", config.clone()), Ok(Array(vec![U64(1), U64(2)])));
+        assert_eq!(error(decode("[1,\n 2 ]", config.clone())),
+                   ParseError(SyntaxError(InputTooLong, 2, 3)));
+        assert_eq!(error(decode("\"\u{e9}\u{e9}\u{e9}\"", config.clone())),
+                   ParseError(SyntaxError(InputTooLong, 1, 4)));
+
+        // strict_keys
+        let config = DecodeConfig::new().strict_keys(true);
+        assert_eq!(error(decode(s, config.clone())),
+                   ParseError(SyntaxError(DuplicateKey, 1, 30)));
+        assert!(decode::<Json>("{\"a\": {\"a\": 1}}", config.clone()).is_ok());
+        let mut builder = Builder::new("{\"a\": 1, \"a\": 2}".chars());
+        builder.set_strict_keys(true);
+        assert_eq!(builder.build_pairs(), Err(ParseError(SyntaxError(DuplicateKey, 1, 16))));
+
+        // allow_trailing_comma
+        let config = DecodeConfig::new().allow_trailing_comma(true);
+        assert_eq!(decode::<Vec<u32>>("[1, 2,]", config.clone()), Ok(vec![1, 2]));
+        assert_eq!(decode::<Json>("{\"a\": {\"b\": [1,],},}", config.clone()),
+                   Json::from_str("{\"a\": {\"b\": [1]}}").map_err(ParseError));
+        assert!(decode::<Json>("[,]", config.clone()).is_err());
+        assert!(decode::<Json>("[1,,]", config.clone()).is_err());
+        assert!(decode::<Json>("{,}", config.clone()).is_err());
+
+        // the parser and decoder options
+        assert_eq!(decode::<f64>("+.5", DecodeConfig::new().relaxed(true)), Ok(0.5));
+        assert!(decode::<u32>("123", DecodeConfig::new().max_number_len(2)).is_err());
+        assert_eq!(decode::<string::String>("\"\\ud800\"",
+                                            DecodeConfig::new().replace_lone_surrogates(true)),
+                   Ok("\u{fffd}".to_string()));
+    }
+
+    #[test]
+    fn test_decode_pairs() {
+        use super::decode_pairs;
+
+        let pairs = decode_pairs("{\"z\": 1, \"a\": [true], \"m\": {\"y\": null, \"b\": 2}}").unwrap();
+        let keys: Vec<&str> = pairs.iter().map(|&(ref k, _)| &k[..]).collect();
+        assert_eq!(keys, ["z", "a", "m"]);
+        assert_eq!(pairs[0].1, U64(1));
+        assert_eq!(pairs[1].1, Array(vec![Boolean(true)]));
+        assert_eq!(pairs[2].1, Json::from_str("{\"b\": 2, \"y\": null}").unwrap());
+
+        assert_eq!(decode_pairs("{\"a\": 1, \"a\": 2}").unwrap(),
+                   vec![("a".to_string(), U64(1)), ("a".to_string(), U64(2))]);
+        assert_eq!(decode_pairs(" {} ").unwrap(), vec![]);
+
+        assert_eq!(decode_pairs("[1]"),
+                   Err(ExpectedError("Object".to_string(), "[1]".to_string())));
+        assert_eq!(decode_pairs("{\"a\": 1"),
+                   Err(ParseError(SyntaxError(EOFWhileParsingObject, 1, 8))));
+        assert_eq!(decode_pairs("{} x"),
+                   Err(ParseError(SyntaxError(TrailingCharacters, 1, 4))));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_encode_mutably_borrowed_cell() {
+        let names = RefCell::new(vec![1u8]);
+        let _borrow = names.borrow_mut();
+        let _ = super::encode(&names);
+    }
+
+    #[test]
+    fn test_encode_through_pointers() {
+        use std::rc::Rc;
+        use std::sync::Arc;
+
+        let shared = Rc::new(vec![1u8, 2]);
+        let value = (Box::new(1u8), &"a", shared.clone(), shared);
+        assert_eq!(super::encode(&value).unwrap(), "[1,\"a\",[1,2],[1,2]]");
+        let boxed: Box<[u8]> = vec![3, 4].into_boxed_slice();
+        assert_eq!(super::encode(&boxed).unwrap(), "[3,4]");
+        assert_eq!(super::encode(&Arc::new(Some(5u8))).unwrap(), "5");
+
+        // decoding gives every pointer its own value
+        let (a, b): (Rc<Vec<u8>>, Rc<Vec<u8>>) = super::decode("[[1,2],[1,2]]").unwrap();
+        assert_eq!(a, b);
+        assert!(&*a as *const Vec<u8> != &*b as *const Vec<u8>);
+        let a: Arc<string::String> = super::decode("\"c\"").unwrap();
+        assert_eq!(*a, "c");
+    }
+
     #[derive(RustcDecodable)]
     struct FloatStruct {
         f: f64,
         a: Vec<f64>
     }
+
     #[test]
     fn test_decode_struct_with_nan() {
         let s = "{\"f\":null,\"a\":[null,123]}";
@@ -3246,11 +8968,13 @@ mod tests {
         z: string::String,
         w: Vec<DecodeStruct>
     }
+
     #[derive(RustcDecodable)]
     enum DecodeEnum {
         A(f64),
         B(string::String)
     }
+
     fn check_err<T: Decodable>(to_parse: &'static str, expected: DecoderError) {
         let res: DecodeResult<T> = match Json::from_str(to_parse) {
             Err(e) => Err(ParseError(e)),
@@ -3266,6 +8990,7 @@ mod tests {
             }
         }
     }
+
     #[test]
     fn test_decode_errors_struct() {
         check_err::<DecodeStruct>("[]", ExpectedError("Object".to_string(), "[]".to_string()));
@@ -3280,6 +9005,7 @@ mod tests {
         check_err::<DecodeStruct>("{\"x\": 1, \"y\": true, \"z\": \"\"}",
                                   MissingFieldError("w".to_string()));
     }
+
     #[test]
     fn test_decode_errors_enum() {
         check_err::<DecodeEnum>("{}",
@@ -3301,6 +9027,23 @@ mod tests {
         assert!(found_str.unwrap().as_string().unwrap() == "cat");
     }
 
+    #[test]
+    fn test_get() {
+        let json_value = Json::from_str("{\"dog\": {\"cats\": [\"tom\"]}}").unwrap();
+        assert_eq!(json_value.get("dog").and_then(|dog| dog.get("cats"))
+                             .and_then(|cats| cats.get_index(0)),
+                   Ok(&String("tom".to_string())));
+
+        assert_eq!(json_value.get("cat"), Err(LookupError::MissingKey("cat".to_string())));
+        assert_eq!(json_value.get_index(0), Err(LookupError::ExpectedArray));
+        let cats = json_value.find_path(&["dog", "cats"]).unwrap();
+        assert_eq!(cats.get("tom"), Err(LookupError::ExpectedObject));
+        assert_eq!(cats.get_index(1), Err(LookupError::IndexOutOfBounds(1, 1)));
+        assert_eq!(cats.get_index(1).unwrap_err().to_string(),
+                   "index 1 out of bounds for an array of length 1");
+        assert_eq!(json_value.get("cat").unwrap_err().to_string(), "missing key `cat`");
+    }
+
     #[test]
     fn test_find_path(){
         let json_value = Json::from_str("{\"dog\":{\"cat\": {\"mouse\" : \"cheese\"}}}").unwrap();
@@ -3315,6 +9058,50 @@ mod tests {
         assert!(found_str.unwrap() == "cheese");
     }
 
+    #[test]
+    fn test_entries(){
+        let json_value = Json::from_str(
+            "{\"a\":[1,{\"b\":true}],\"c/d\":{\"e~f\":null},\"g\":[],\"h\":{}}").unwrap();
+        let entries: Vec<_> = json_value.entries().collect();
+        assert_eq!(entries, vec![
+            ("/a/0".to_string(), &U64(1)),
+            ("/a/1/b".to_string(), &Boolean(true)),
+            ("/c~1d/e~0f".to_string(), &Null),
+            ("/g".to_string(), &Array(vec![])),
+            ("/h".to_string(), &Object(BTreeMap::new())),
+        ]);
+
+        let scalar = U64(3);
+        assert_eq!(scalar.entries().collect::<Vec<_>>(), vec![("".to_string(), &U64(3))]);
+    }
+
+    #[test]
+    fn test_deep_eq_unordered() {
+        fn eq(a: &str, b: &str) -> bool {
+            let (a, b) = (Json::from_str(a).unwrap(), Json::from_str(b).unwrap());
+            assert_eq!(a.deep_eq_unordered(&b), b.deep_eq_unordered(&a));
+            a.deep_eq_unordered(&b)
+        }
+
+        assert!(eq("[1, 2, 3]", "[3, 1, 2]"));
+        assert!(eq("[]", "[]"));
+        assert!(eq("{\"a\": [{\"b\": [1, 2]}, null]}", "{\"a\": [null, {\"b\": [2, 1]}]}"));
+        assert!(eq("[[1, 2], [3]]", "[[3], [2, 1]]"));
+        assert!(eq("\"x\"", "\"x\""));
+
+        // arrays are compared as multisets
+        assert!(!eq("[1, 1, 2]", "[1, 2, 2]"));
+        assert!(!eq("[1, 2]", "[1, 2, 2]"));
+        assert!(!eq("[[1, 2], [1]]", "[[1], [1]]"));
+
+        // and everything else as with `==`
+        assert!(!eq("{\"a\": 1}", "{\"a\": 1, \"b\": 2}"));
+        assert!(!eq("{\"a\": 1}", "{\"b\": 1}"));
+        assert!(!eq("{\"a\": [1]}", "{\"a\": 1}"));
+        assert!(!eq("1", "1.0"));
+        assert!(!eq("null", "[]"));
+    }
+
     #[test]
     fn test_index(){
         let json_value = Json::from_str("{\"animals\":[\"dog\",\"cat\",\"mouse\"]}").unwrap();
@@ -3322,6 +9109,14 @@ mod tests {
         assert_eq!(array[0].as_string().unwrap(), "dog");
         assert_eq!(array[1].as_string().unwrap(), "cat");
         assert_eq!(array[2].as_string().unwrap(), "mouse");
+
+        // misses are null all the way down
+        assert_eq!(array[3], Null);
+        assert_eq!(json_value["plants"], Null);
+        assert_eq!(json_value["plants"][0]["name"], Null);
+        assert_eq!(json_value[0], Null);
+        assert_eq!(array["dog"], Null);
+        assert_eq!(array[0][0], Null);
     }
 
     #[test]
@@ -3351,12 +9146,187 @@ mod tests {
         assert!(json_array.is_some() && json_array.unwrap().len() == expected_length);
     }
 
+    #[test]
+    fn test_typed_arrays(){
+        let strs = Json::from_str("[\"a\", \"b\"]").unwrap();
+        assert_eq!(strs.as_str_array(), Some(vec!["a", "b"]));
+        assert_eq!(strs.as_i64_array(), None);
+
+        let numbers = Json::from_str("[1, -2, 3]").unwrap();
+        assert_eq!(numbers.as_i64_array(), Some(vec![1, -2, 3]));
+        assert_eq!(numbers.as_u64_array(), None);
+        assert_eq!(numbers.as_f64_array(), Some(vec![1.0, -2.0, 3.0]));
+        assert_eq!(numbers.as_str_array(), None);
+
+        let mixed = Json::from_str("[1, \"a\"]").unwrap();
+        assert_eq!(mixed.as_str_array(), None);
+        assert_eq!(mixed.as_i64_array(), None);
+
+        let empty = Json::from_str("[]").unwrap();
+        assert_eq!(empty.as_str_array(), Some(vec![]));
+        assert_eq!(empty.as_u64_array(), Some(vec![]));
+        assert_eq!(Json::from_str("\"a\"").unwrap().as_str_array(), None);
+    }
+
+    #[test]
+    fn test_len(){
+        assert_eq!(Json::from_str("[1, [2, 3]]").unwrap().len(), Some(2));
+        assert_eq!(Json::from_str("{\"a\": 1}").unwrap().len(), Some(1));
+        assert_eq!(Json::from_str("\"h\\u00e9\"").unwrap().len(), Some(3));
+        assert_eq!(Json::from_str("3").unwrap().len(), None);
+        assert_eq!(Null.len(), None);
+
+        assert!(Json::from_str("[]").unwrap().is_empty());
+        assert!(Json::from_str("{}").unwrap().is_empty());
+        assert!(Json::from_str("\"\"").unwrap().is_empty());
+        assert!(!Json::from_str("[null]").unwrap().is_empty());
+        assert!(!Null.is_empty());
+    }
+
     #[test]
     fn test_is_string(){
         let json_value = Json::from_str("\"dog\"").unwrap();
         assert!(json_value.is_string());
     }
 
+    #[test]
+    fn test_sorted(){
+        let json_value = Json::from_str("{\"b\": {\"d\": 1, \"c\": 2}, \"a\": [{\"f\": 3, \"e\": 4}]}").unwrap();
+        let sorted = json_value.sorted();
+        assert_eq!(sorted, json_value);
+        assert_eq!(sorted.to_string(), "{\"a\":[{\"e\":4,\"f\":3}],\"b\":{\"c\":2,\"d\":1}}");
+    }
+
+    #[test]
+    fn test_normalize(){
+        let mut json_value = Array(vec![
+            F64(-0.0), F64(0.0), F64(2.0), F64(-2.0), F64(2.5), F64(1e20),
+            F64(f64::NAN), F64(f64::NEG_INFINITY), I64(3), I64(-3), U64(4),
+            String("1.0".to_string()),
+        ]);
+        let mut object = BTreeMap::new();
+        object.insert("a".to_string(), F64(1.0));
+        json_value.as_array_mut().unwrap().push(Object(object));
+        json_value.normalize();
+
+        let mut object = BTreeMap::new();
+        object.insert("a".to_string(), U64(1));
+        assert_eq!(json_value, Array(vec![
+            U64(0), U64(0), U64(2), I64(-2), F64(2.5), F64(1e20),
+            Null, Null, U64(3), I64(-3), U64(4),
+            String("1.0".to_string()), Object(object),
+        ]));
+        assert_eq!(F64(-0.0).to_string(), "-0.0");
+        let mut zero = F64(-0.0);
+        zero.normalize();
+        assert_eq!(zero.to_string(), "0");
+    }
+
+    #[test]
+    fn test_ord() {
+        use std::cmp::Ordering::{Less, Equal, Greater};
+        use std::collections::BTreeSet;
+
+        let ordered = [
+            Null,
+            Boolean(false),
+            Boolean(true),
+            F64(f64::NEG_INFINITY),
+            I64(i64::MIN),
+            F64(-1.5),
+            I64(-1),
+            F64(-0.5),
+            I64(0),
+            U64(0),
+            F64(-0.0),
+            F64(0.5),
+            U64(1),
+            F64(1.0),
+            I64(2),
+            U64(u64::MAX),
+            F64(18446744073709551616.0),
+            F64(f64::INFINITY),
+            F64(f64::NAN),
+            String("".to_string()),
+            String("a".to_string()),
+            String("b".to_string()),
+            Array(vec![]),
+            Array(vec![Null, U64(1)]),
+            Array(vec![U64(1)]),
+            Object(BTreeMap::new()),
+            mk_object(&[("a".to_string(), U64(2))]),
+            mk_object(&[("a".to_string(), U64(2)), ("b".to_string(), Null)]),
+            mk_object(&[("a".to_string(), String("x".to_string()))]),
+            mk_object(&[("b".to_string(), Null)]),
+        ];
+        for (i, a) in ordered.iter().enumerate() {
+            for (j, b) in ordered.iter().enumerate() {
+                assert!(a.cmp(b) == i.cmp(&j), "{} vs {}", a, b);
+                assert_eq!(a.partial_cmp(b), Some(i.cmp(&j)));
+            }
+        }
+        assert_eq!(F64(f64::NAN).cmp(&F64(f64::NAN)), Equal);
+        // `==` agrees with the order
+        assert_eq!(F64(f64::NAN), F64(-f64::NAN));
+        assert_eq!(Array(vec![F64(f64::NAN)]), Array(vec![F64(f64::NAN)]));
+        assert_eq!(F64(-0.0), F64(0.0));
+        assert!(U64(1) != F64(1.0));
+        assert!(I64(1) != U64(1));
+        assert_eq!(U64(9007199254740993).cmp(&F64(9007199254740992.0)), Greater);
+        assert_eq!(I64(-9007199254740993).cmp(&F64(-9007199254740992.0)), Less);
+
+        let set: BTreeSet<Json> = [U64(3), Null, U64(3), String("x".to_string()), Null]
+            .iter().cloned().collect();
+        assert_eq!(set.into_iter().collect::<Vec<_>>(),
+                   vec![Null, U64(3), String("x".to_string())]);
+        assert!(Boolean(true) < U64(0));
+        assert_eq!(U64(1).cmp(&I64(1)), Greater);
+        assert_eq!(I64(1).cmp(&U64(1)), Less);
+    }
+
+    #[test]
+    fn test_hash() {
+        use std::collections::HashMap;
+        use std::hash::{Hash, Hasher};
+
+        // FNV-1a, as `DefaultHasher` needs Rust 1.13 and `SipHasher` is
+        // deprecated from then on.
+        struct Fnv(u64);
+
+        impl Hasher for Fnv {
+            fn finish(&self) -> u64 { self.0 }
+            fn write(&mut self, bytes: &[u8]) {
+                for &b in bytes {
+                    self.0 = (self.0 ^ b as u64).wrapping_mul(0x100000001b3);
+                }
+            }
+        }
+
+        fn hash(json: &Json) -> u64 {
+            let mut hasher = Fnv(0xcbf29ce484222325);
+            json.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        assert_eq!(hash(&F64(1.0)), hash(&U64(1)));
+        assert_eq!(hash(&F64(-3.0)), hash(&I64(-3)));
+        assert_eq!(hash(&F64(-0.0)), hash(&F64(0.0)));
+        assert_eq!(hash(&F64(0.0)), hash(&U64(0)));
+        assert_eq!(hash(&F64(f64::NAN)), hash(&F64(-f64::NAN)));
+        assert!(hash(&F64(0.5)) != hash(&F64(1.5)));
+        assert!(hash(&U64(1)) != hash(&String("1".to_string())));
+        assert!(hash(&Array(vec![])) != hash(&Object(BTreeMap::new())));
+
+        let mut memo = HashMap::new();
+        memo.insert(Json::from_str("{\"a\": [1, 2.5, null]}").unwrap(), 1);
+        memo.insert(Json::from_str("[true]").unwrap(), 2);
+        assert_eq!(memo.get(&Json::from_str("{ \"a\": [1, 2.5, null] }").unwrap()), Some(&1));
+        assert_eq!(memo.get(&Json::from_str("[true]").unwrap()), Some(&2));
+        assert_eq!(memo.get(&Json::from_str("[false]").unwrap()), None);
+        memo.insert(Array(vec![F64(f64::NAN)]), 3);
+        assert_eq!(memo.get(&Array(vec![F64(f64::NAN)])), Some(&3));
+    }
+
     #[test]
     fn test_as_string(){
         let json_value = Json::from_str("\"dog\"").unwrap();
@@ -3365,6 +9335,22 @@ mod tests {
         assert_eq!(json_str, Some(expected_str));
     }
 
+    #[test]
+    fn test_base64_bytes(){
+        use base64::Base64Bytes;
+        use super::ToJson;
+
+        let json_value = Json::from_bytes_base64(b"\x00\xffbytes");
+        assert_eq!(json_value, String("AP9ieXRlcw==".to_string()));
+        assert_eq!(json_value.as_base64_bytes(), Some(b"\x00\xffbytes".to_vec()));
+        assert_eq!(Base64Bytes(b"bytes".to_vec()).to_json(), String("Ynl0ZXM=".to_string()));
+        assert_eq!(::hex::HexBytes(b"\x00\xff".to_vec()).to_json(), String("00ff".to_string()));
+
+        assert_eq!(String("".to_string()).as_base64_bytes(), Some(vec![]));
+        assert_eq!(String("not base64!".to_string()).as_base64_bytes(), None);
+        assert_eq!(Array(vec![U64(1)]).as_base64_bytes(), None);
+    }
+
     #[test]
     fn test_is_number(){
         let json_value = Json::from_str("12").unwrap();
@@ -3584,7 +9570,15 @@ mod tests {
 
     fn assert_stream_equal(src: &str,
                            expected: Vec<(JsonEvent, Vec<StackElement>)>) {
+        assert_stream_equal_with(src, |_| {}, expected)
+    }
+    fn assert_stream_equal_with<F>(src: &str,
+                                   configure: F,
+                                   expected: Vec<(JsonEvent, Vec<StackElement>)>)
+        where F: FnOnce(&mut Parser<::std::str::Chars>)
+    {
         let mut parser = Parser::new(src.chars());
+        configure(&mut parser);
         let mut i = 0;
         loop {
             let evt = match parser.next() {
@@ -3635,6 +9629,89 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_comments() {
+        use super::{from_str_with_config, DecodeConfig};
+
+        fn parse(src: &str) -> Result<Json, DecoderError> {
+            from_str_with_config(src, &DecodeConfig::new().allow_comments(true))
+        }
+
+        let src = "// settings
+                   {
+                       \"name\": \"x\", // the name
+                       /* a block
+                          comment, with \"quotes\" and // inside */
+                       \"ports\": [80 /* http */, 443/**/]
+                   } /* done **/ // really
+                   ";
+        assert_eq!(parse(src), Json::from_str("{\"name\": \"x\", \"ports\": [80, 443]}")
+                                   .map_err(ParseError));
+        // comment markers in strings are kept
+        assert_eq!(parse("\"// not /* a */ comment\""),
+                   Ok(String("// not /* a */ comment".to_string())));
+        assert_eq!(parse("1 // no newline"), Ok(U64(1)));
+        assert_eq!(parse("//\n2"), Ok(U64(2)));
+
+        // a lone `/` is still an error, where it is
+        assert_eq!(parse("[1, /2]"), Err(ParseError(SyntaxError(InvalidSyntax, 1, 5))));
+        assert_eq!(parse("1 /"), Err(ParseError(SyntaxError(TrailingCharacters, 1, 3))));
+        assert_eq!(parse("[1 /* unterminated ]"),
+                   Err(ParseError(SyntaxError(EOFWhileParsingArray, 1, 21))));
+        // a comment may follow a number directly
+        assert!(parse("[1/**/]").is_ok());
+        assert!(parse("// only a comment").is_err());
+
+        // and off by default
+        assert_eq!(Json::from_str("// comment\n1"), Err(SyntaxError(InvalidSyntax, 1, 1)));
+        assert_eq!(Json::from_str("[1 /* c */]"), Err(SyntaxError(InvalidSyntax, 1, 4)));
+    }
+
+    #[test]
+    fn test_trailing_comma_streaming() {
+        fn events(src: &str) -> Vec<JsonEvent> {
+            let mut parser = Parser::new(src.chars());
+            parser.set_allow_trailing_comma(true);
+            parser.collect()
+        }
+
+        let src = "{\"a\": [1, 2, 3,], \"b\": {\"c\": 1,},}";
+        assert_stream_equal_with(src, |p| p.set_allow_trailing_comma(true), vec![
+            (ObjectStart, vec![]),
+              (ArrayStart, vec![Key("a")]),
+                (U64Value(1), vec![Key("a"), Index(0)]),
+                (U64Value(2), vec![Key("a"), Index(1)]),
+                (U64Value(3), vec![Key("a"), Index(2)]),
+              (ArrayEnd, vec![Key("a")]),
+              (ObjectStart, vec![Key("b")]),
+                (U64Value(1), vec![Key("b"), Key("c")]),
+              (ObjectEnd, vec![Key("b")]),
+            (ObjectEnd, vec![]),
+        ]);
+        assert_eq!(events(src), vec![
+            ObjectStart,
+              ArrayStart, U64Value(1), U64Value(2), U64Value(3), ArrayEnd,
+              ObjectStart, U64Value(1), ObjectEnd,
+            ObjectEnd,
+        ]);
+        let mut builder = Builder::new(src.chars());
+        builder.set_allow_trailing_comma(true);
+        assert_eq!(builder.build(),
+                   Json::from_str("{\"a\": [1, 2, 3], \"b\": {\"c\": 1}}"));
+
+        // off by default
+        assert_eq!(last_event("[1, 2, 3,]"), Error(SyntaxError(InvalidSyntax, 1, 10)));
+        assert_eq!(last_event("{\"a\": 1,}"), Error(SyntaxError(TrailingComma, 1, 9)));
+
+        // a comma still has to follow an element
+        for &src in ["[,]", "[1,,]", "[1,,2]", "{,}", "{\"a\": 1,,}", "[1,", "{\"a\": 1,"].iter() {
+            match events(src).pop() {
+                Some(Error(_)) => {}
+                evt => panic!("{} gave {:?}", src, evt),
+            }
+        }
+    }
+
     #[test]
     #[cfg_attr(target_word_size = "32", ignore)] // FIXME(#14064)
     fn test_read_object_streaming() {
@@ -3801,6 +9878,68 @@ mod tests {
         assert_eq!(last_event("{}a"),    Error(SyntaxError(TrailingCharacters, 1, 3)));
     }
     #[test]
+    fn test_recovering_streaming() {
+        let events: Vec<JsonEvent> =
+            Parser::new_recovering("[1, tru, {\"a\": 1 \"b\": 2}, [4,,5], 6]".chars())
+                .collect();
+        assert_eq!(events, vec![
+            ArrayStart,
+            U64Value(1),
+            Error(SyntaxError(InvalidSyntax, 1, 8)),
+            ObjectStart,
+            U64Value(1),
+            Error(SyntaxError(InvalidSyntax, 1, 18)),
+            ObjectEnd,
+            ArrayStart,
+            U64Value(4),
+            Error(SyntaxError(InvalidSyntax, 1, 30)),
+            U64Value(5),
+            ArrayEnd,
+            U64Value(6),
+            ArrayEnd,
+        ]);
+
+        // a mismatched bracket still closes the innermost container
+        let events: Vec<JsonEvent> = Parser::new_recovering("[[2}, 3]".chars()).collect();
+        assert_eq!(events, vec![
+            ArrayStart,
+            ArrayStart,
+            U64Value(2),
+            Error(SyntaxError(InvalidSyntax, 1, 4)),
+            ArrayEnd,
+            U64Value(3),
+            ArrayEnd,
+        ]);
+    }
+    #[test]
+    fn test_build_recovering() {
+        fn build(s: &str) -> Result<Json, Vec<BuilderError>> {
+            Builder::new(s.chars()).build_recovering()
+        }
+
+        assert_eq!(build("[1, {\"a\": true}]"), Ok(Json::from_str("[1, {\"a\": true}]").unwrap()));
+
+        let mut builder = Builder::new("[1, tru, {\"a\": 1 \"b\": 2}, \"x\\qy\", [4,,5], 6]".chars());
+        assert_eq!(builder.build_recovering(), Err(vec![
+            SyntaxError(InvalidSyntax, 1, 8),
+            SyntaxError(InvalidSyntax, 1, 18),
+            SyntaxError(InvalidEscape, 1, 30),
+            SyntaxError(InvalidSyntax, 1, 38),
+        ]));
+
+        assert_eq!(build("{1: 2, \"b\": 3, \"c\" 4, \"d\": [}"), Err(vec![
+            SyntaxError(KeyMustBeAString, 1, 2),
+            SyntaxError(ExpectedColon, 1, 20),
+            SyntaxError(InvalidSyntax, 1, 29),
+            SyntaxError(EOFWhileParsingObject, 1, 30),
+        ]));
+        assert_eq!(build("[\"\\ud800\", 1]"),
+                   Err(vec![SyntaxError(LoneSurrogate, 1, 10)]));
+        assert_eq!(build("{\"a\": [1, 2"), Err(vec![SyntaxError(EOFWhileParsingArray, 1, 12)]));
+        assert_eq!(build("[1] x"), Err(vec![SyntaxError(TrailingCharacters, 1, 5)]));
+        assert_eq!(build(""), Err(vec![SyntaxError(EOFWhileParsingValue, 1, 1)]));
+    }
+    #[test]
     fn test_read_identifiers_streaming() {
         assert_eq!(Parser::new("null".chars()).next(), Some(NullValue));
         assert_eq!(Parser::new("true".chars()).next(), Some(BooleanValue(true)));
@@ -3909,6 +10048,8 @@ mod tests {
         assert_eq!("abc".to_string().to_json(), String("abc".to_string()));
         assert_eq!((1, 2).to_json(), array2);
         assert_eq!((1, 2, 3).to_json(), array3);
+        assert_eq!((1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16).to_json(),
+                   Array((1..17).map(I64).collect()));
         assert_eq!([1, 2].to_json(), array2);
         assert_eq!((&[1, 2, 3]).to_json(), array3);
         assert_eq!((vec![1, 2]).to_json(), array2);
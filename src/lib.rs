@@ -25,7 +25,7 @@
 //!
 //! ```toml
 //! [dependencies]
-//! rustc-serialize = "0.3"
+//! rustc-serialize = "0.4"
 //! ```
 //!
 //! and this to your crate root:
@@ -33,7 +33,16 @@
 //! ```rust
 //! extern crate rustc_serialize;
 //! ```
+//!
+//! # Features
+//!
+//! Everything but the `base64` and `hex` modules needs the `std` feature,
+//! which is on by default. Without it the crate is `no_std`, leaving the slice
+//! based part of `base64` and `hex`, and the `alloc` feature adds the `Vec`
+//! and `String` based part back, which needs Rust 1.36.
 
+// Tests always link `std`, which the test harness needs anyway.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![cfg_attr(rustbuild, feature(staged_api, rustc_private))]
 #![cfg_attr(rustbuild, unstable(feature = "rustc_private", issue = "27812"))]
 
@@ -48,14 +57,18 @@
             unstable(feature = "rustc_private",
                      reason = "use the crates.io `rustc-serialize` library instead"))]
 
+#[cfg(all(feature = "alloc", not(feature = "std")))] extern crate alloc;
+#[cfg(test)] extern crate quickcheck;
 #[cfg(test)] extern crate rand;
 
+#[cfg(feature = "std")]
 pub use self::serialize::{Decoder, Encoder, Decodable, Encodable,
-                          DecoderHelpers, EncoderHelpers};
+                          DecoderHelpers, EncoderHelpers, ValueKind};
 
 
 // Limit collections from allocating more than
 // 1 MB for calls to `with_capacity`.
+#[cfg(feature = "std")]
 fn cap_capacity<T>(given_len: usize) -> usize {
     use std::cmp::min;
     use std::mem::size_of;
@@ -67,13 +80,14 @@ fn cap_capacity<T>(given_len: usize) -> usize {
     }
 }
 
-mod serialize;
-mod collection_impls;
+#[cfg(feature = "std")] mod serialize;
+#[cfg(feature = "std")] mod collection_impls;
 
 pub mod base64;
 pub mod hex;
-pub mod json;
+#[cfg(feature = "std")] pub mod json;
 
+#[cfg(feature = "std")]
 mod rustc_serialize {
     pub use serialize::*;
 }
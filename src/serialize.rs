@@ -46,6 +46,14 @@ use cap_capacity;
 /// collection-emitting method has returned, encoding should be complete.
 pub trait Encoder {
     /// The error type for method results.
+    ///
+    /// Every encoder has its own, covering the ways it can fail, such as
+    /// `json::EncoderError` for failing writes and map keys JSON can't
+    /// represent. An encoder which can't fail at all can use `()`.
+    ///
+    /// There is deliberately no error type shared by all encoders: fixing
+    /// `Error` to one would break every `Encoder` implemented outside this
+    /// crate.
     type Error;
 
     // Primitive types:
@@ -57,6 +65,15 @@ pub trait Encoder {
     /// Emit a usize value.
     fn emit_usize(&mut self, v: usize) -> Result<(), Self::Error>;
 
+    /// Emit a u128 value.
+    ///
+    /// By default this emits the decimal digits of the value as a string,
+    /// which the default `Decoder::read_u128` reads back.
+    #[cfg(feature = "i128")]
+    fn emit_u128(&mut self, v: u128) -> Result<(), Self::Error> {
+        self.emit_str(&v.to_string())
+    }
+
     /// Emit a u64 value.
     fn emit_u64(&mut self, v: u64) -> Result<(), Self::Error>;
 
@@ -72,6 +89,15 @@ pub trait Encoder {
     /// Emit a isize value.
     fn emit_isize(&mut self, v: isize) -> Result<(), Self::Error>;
 
+    /// Emit a i128 value.
+    ///
+    /// By default this emits the decimal digits of the value as a string,
+    /// which the default `Decoder::read_i128` reads back.
+    #[cfg(feature = "i128")]
+    fn emit_i128(&mut self, v: i128) -> Result<(), Self::Error> {
+        self.emit_str(&v.to_string())
+    }
+
     /// Emit a i64 value.
     fn emit_i64(&mut self, v: i64) -> Result<(), Self::Error>;
 
@@ -105,6 +131,17 @@ pub trait Encoder {
     /// Emit a string value.
     fn emit_str(&mut self, v: &str) -> Result<(), Self::Error>;
 
+    /// Emit a number given as its decimal representation, such as that held
+    /// by a `json::JsonNumber`, which may be more precise than any of the
+    /// number types.
+    ///
+    /// The default implementation emits it as a string with `emit_str`,
+    /// which is what formats that can't write numbers of any precision
+    /// need.
+    fn emit_number_str(&mut self, v: &str) -> Result<(), Self::Error> {
+        self.emit_str(v)
+    }
+
     // Compound types:
     /// Emit an enumeration value.
     ///
@@ -301,6 +338,26 @@ pub trait Encoder {
                             -> Result<(), Self::Error>
         where F: FnOnce(&mut Self) -> Result<(), Self::Error>;
 
+    /// Emit a struct field whose own fields are to be inlined into the
+    /// enclosing struct.
+    ///
+    /// This should only be called from a function passed to `emit_struct`,
+    /// in place of `emit_struct_field`.
+    ///
+    /// * `f_idx` is the (zero-based) index of the field.
+    /// * `f` is a function that will call `emit_struct` to encode the field.
+    ///
+    /// The default implementation simply calls `f`, which is what formats
+    /// that encode the fields of structs one after the other need. Formats
+    /// which name fields should merge the fields into the enclosing struct.
+    fn emit_struct_flattened<F>(&mut self, f_idx: usize, f: F)
+                                -> Result<(), Self::Error>
+        where F: FnOnce(&mut Self) -> Result<(), Self::Error>
+    {
+        let _ = f_idx;
+        f(self)
+    }
+
     /// Emit a tuple value.
     ///
     /// * `len` is the number of items in the tuple.
@@ -488,6 +545,28 @@ pub trait Encoder {
         where F: FnOnce(&mut Self) -> Result<(), Self::Error>;
 }
 
+/// The kinds of value `Decoder::next_kind` tells apart, each naming the
+/// method to read it with.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ValueKind {
+    /// Read with `read_nil`.
+    Nil,
+    /// Read with `read_bool`.
+    Bool,
+    /// An integer, read with `read_i64`.
+    I64,
+    /// An integer, possibly beyond the range of `i64`, read with `read_u64`.
+    U64,
+    /// Read with `read_f64`.
+    F64,
+    /// Read with `read_str`.
+    String,
+    /// Read with `read_seq`.
+    Seq,
+    /// Read with `read_map`, with keys read with `read_str`.
+    Map,
+}
+
 /// Trait for reading in an encoding for deserialization.
 ///
 /// This trait provides methods to decode basic types and generic forms of
@@ -513,6 +592,19 @@ pub trait Decoder {
     /// Read a usize value.
     fn read_usize(&mut self) -> Result<usize, Self::Error>;
 
+    /// Read a u128 value.
+    ///
+    /// By default this reads a string of decimal digits, as written by the
+    /// default `Encoder::emit_u128`.
+    #[cfg(feature = "i128")]
+    fn read_u128(&mut self) -> Result<u128, Self::Error> {
+        let s = try!(self.read_str());
+        match s.parse() {
+            Ok(v) => Ok(v),
+            Err(_) => Err(self.error(&format!("invalid u128 `{}`", s))),
+        }
+    }
+
     /// Read a u64 value.
     fn read_u64(&mut self) -> Result<u64, Self::Error>;
 
@@ -528,6 +620,19 @@ pub trait Decoder {
     /// Read a isize value.
     fn read_isize(&mut self) -> Result<isize, Self::Error>;
 
+    /// Read a i128 value.
+    ///
+    /// By default this reads a string of decimal digits, as written by the
+    /// default `Encoder::emit_i128`.
+    #[cfg(feature = "i128")]
+    fn read_i128(&mut self) -> Result<i128, Self::Error> {
+        let s = try!(self.read_str());
+        match s.parse() {
+            Ok(v) => Ok(v),
+            Err(_) => Err(self.error(&format!("invalid i128 `{}`", s))),
+        }
+    }
+
     /// Read a i64 value.
     fn read_i64(&mut self) -> Result<i64, Self::Error>;
 
@@ -555,6 +660,15 @@ pub trait Decoder {
     /// Read a string value.
     fn read_str(&mut self) -> Result<String, Self::Error>;
 
+    /// Read a number as its decimal representation, as written by
+    /// `Encoder::emit_number_str`.
+    ///
+    /// The default implementation reads a string with `read_str`, matching
+    /// the default implementation of `Encoder::emit_number_str`.
+    fn read_number_str(&mut self) -> Result<String, Self::Error> {
+        self.read_str()
+    }
+
     // Compound types:
     /// Read an enumeration value.
     ///
@@ -659,6 +773,25 @@ pub trait Decoder {
                                -> Result<T, Self::Error>
         where F: FnOnce(&mut Self) -> Result<T, Self::Error>;
 
+    /// Read a struct field whose own fields are inlined into the enclosing
+    /// struct.
+    ///
+    /// This should only be called from a function passed to `read_struct`,
+    /// in place of `read_struct_field`.
+    ///
+    /// * `f_idx` is the (zero-based) index of the field.
+    /// * `f` is a function that will call `read_struct` to decode the field.
+    ///
+    /// The default implementation simply calls `f`, matching the default
+    /// implementation of `Encoder::emit_struct_flattened`.
+    fn read_struct_flattened<T, F>(&mut self, f_idx: usize, f: F)
+                                   -> Result<T, Self::Error>
+        where F: FnOnce(&mut Self) -> Result<T, Self::Error>
+    {
+        let _ = f_idx;
+        f(self)
+    }
+
     /// Read a tuple value.
     ///
     /// * `len` is the number of items in the tuple.
@@ -788,6 +921,29 @@ pub trait Decoder {
                               -> Result<T, Self::Error>
         where F: FnOnce(&mut Self) -> Result<T, Self::Error>;
 
+    /// Tell what kind of value comes next, without reading it.
+    ///
+    /// This is for types decoding whatever they are given, such as
+    /// `json::Json`. Decoders which can't tell can leave the default
+    /// implementation, which fails.
+    fn next_kind(&mut self) -> Result<ValueKind, Self::Error> {
+        Err(self.error("this decoder can't tell what kind of value comes next"))
+    }
+
+    /// Decode the next value in the first of `len` ways that works, as for
+    /// the variants of an untagged enum.
+    ///
+    /// `f` is called with the indices `0` to `len - 1` in turn, reading the
+    /// same value each time, until it succeeds. If none does, the error of
+    /// the last attempt is returned. Decoders which can't go back over a
+    /// value can leave the default implementation, which fails.
+    fn read_alternatives<T, F>(&mut self, len: usize, f: F) -> Result<T, Self::Error>
+        where F: FnMut(&mut Self, usize) -> Result<T, Self::Error>
+    {
+        let _ = (len, f);
+        Err(self.error("this decoder can't decode a value more than one way"))
+    }
+
     // Failure
     /// Record a decoding error.
     ///
@@ -952,6 +1108,20 @@ impl Decodable for u32 {
     }
 }
 
+#[cfg(feature = "i128")]
+impl Encodable for u128 {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_u128(*self)
+    }
+}
+
+#[cfg(feature = "i128")]
+impl Decodable for u128 {
+    fn decode<D: Decoder>(d: &mut D) -> Result<u128, D::Error> {
+        d.read_u128()
+    }
+}
+
 impl Encodable for u64 {
     fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
         s.emit_u64(*self)
@@ -1012,6 +1182,20 @@ impl Decodable for i32 {
     }
 }
 
+#[cfg(feature = "i128")]
+impl Encodable for i128 {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_i128(*self)
+    }
+}
+
+#[cfg(feature = "i128")]
+impl Decodable for i128 {
+    fn decode<D: Decoder>(d: &mut D) -> Result<i128, D::Error> {
+        d.read_i128()
+    }
+}
+
 impl Encodable for i64 {
     fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
         s.emit_i64(*self)
@@ -1134,6 +1318,8 @@ impl<T:Encodable> Encodable for Rc<T> {
     }
 }
 
+/// Sharing isn't preserved: every `Rc` decoded gets its own allocation, even
+/// when the values were encoded from clones of the same one.
 impl<T:Decodable> Decodable for Rc<T> {
     #[inline]
     fn decode<D: Decoder>(d: &mut D) -> Result<Rc<T>, D::Error> {
@@ -1317,7 +1503,7 @@ macro_rules! tuple {
     )
 }
 
-tuple! { T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, }
+tuple! { T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15, }
 
 macro_rules! array {
     () => ();
@@ -1425,6 +1611,11 @@ impl<T: Decodable + Copy> Decodable for Cell<T> {
 // `encoder.error("attempting to Encode borrowed RefCell")`
 // from `encode` when `try_borrow` returns `None`.
 
+/// Encodes the value the cell holds.
+///
+/// # Panics
+///
+/// Panics if the value is currently mutably borrowed.
 impl<T: Encodable> Encodable for RefCell<T> {
     fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
         self.borrow().encode(s)
@@ -1443,6 +1634,7 @@ impl<T:Encodable> Encodable for Arc<T> {
     }
 }
 
+/// As with `Rc`, every `Arc` decoded gets its own allocation.
 impl<T:Decodable+Send+Sync> Decodable for Arc<T> {
     fn decode<D: Decoder>(d: &mut D) -> Result<Arc<T>, D::Error> {
         Ok(Arc::new(try!(Decodable::decode(d))))